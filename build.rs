@@ -0,0 +1,8 @@
+// Field names and lifetimes are spelled out throughout the crate
+#![allow(clippy::redundant_field_names, clippy::redundant_static_lifetimes)]
+
+include!("lua/link.rs");
+
+fn main() {
+    link("lua");
+}
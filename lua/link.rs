@@ -0,0 +1,108 @@
+// Links the lua version selected with the features, included by the build scripts of yuna
+// and yuna-derive.
+
+#[cfg(feature = "vendored")]
+extern crate cc;
+#[cfg(feature = "system")]
+extern crate pkg_config;
+
+use std::env;
+use std::path::Path;
+
+/// How a lua version is found with pkg-config and where its bundled sources are.
+// The pkg-config fields are unused without the system feature
+#[allow(dead_code)]
+struct Library {
+    feature: &'static str,
+    names: &'static [&'static str],
+    min: &'static str,
+    max: &'static str,
+    sources: Option<&'static str>,
+}
+
+/// The pkg-config names of each version are tried in order.
+const LIBRARIES: &'static [Library] = &[
+    Library { feature: "LUA51", names: &["lua5.1","lua-5.1","lua51"], min: "5.1", max: "5.2", sources: Some("lua-5.1.5") },
+    Library { feature: "LUA52", names: &["lua5.2","lua-5.2","lua52"], min: "5.2", max: "5.3", sources: Some("lua-5.2.4") },
+    Library { feature: "LUA53", names: &["lua5.3","lua-5.3","lua53"], min: "5.3", max: "5.4", sources: Some("lua-5.3.6") },
+    Library { feature: "LUA54", names: &["lua5.4","lua-5.4","lua54"], min: "5.4", max: "5.5", sources: Some("lua-5.4.9") },
+    Library { feature: "LUAJIT", names: &["luajit"], min: "2.0", max: "3.0", sources: None },
+];
+
+/// Links the selected version, `lua` is the directory with the bundled sources relative to
+/// the manifest of the crate.
+fn link(lua: &str) {
+    for lib in LIBRARIES {
+        if env::var_os(format!("CARGO_FEATURE_{}",lib.feature)).is_none() {
+            continue;
+        }
+        if cfg!(feature = "vendored") {
+            let sources = lib.sources.expect("LuaJIT can't be vendored, use the system feature");
+            vendored(&Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join(lua).join(sources));
+        } else if cfg!(feature = "system") {
+            system(lib);
+        } else {
+            panic!("yuna needs either the vendored or the system feature to link lua");
+        }
+    }
+}
+
+/// Links the lua library found with pkg-config.
+#[cfg(feature = "system")]
+fn system(lib: &Library) {
+    // Distributions disagree on the name, the plain `lua` package is used as last resort
+    let found = lib.names.iter().any(|name| pkg_config::probe_library(name).is_ok())
+        || (lib.sources.is_some() && pkg_config::Config::new().range_version(lib.min..lib.max).probe("lua").is_ok());
+    if !found {
+        panic!("lua {} was not found with pkg-config, tried {}",lib.min,lib.names.join(", "));
+    }
+}
+
+#[cfg(not(feature = "system"))]
+fn system(_lib: &Library) {}
+
+/// Compiles the bundled sources of lua into a static library.
+///
+/// `YUNA_LUA_APICHECK` turns the checks of the arguments of api calls on or off, by default
+/// they are on in debug builds. `YUNA_LUAI_MAXCCALLS` sets the limit of nested C calls.
+#[cfg(feature = "vendored")]
+fn vendored(dir: &Path) {
+    let target = env::var("TARGET").unwrap();
+    let mut build = cc::Build::new();
+    build.include(dir).warnings(false);
+
+    if target.contains("windows") {
+        build.define("LUA_USE_WINDOWS",None);
+    } else if target.contains("apple") {
+        build.define("LUA_USE_MACOSX",None);
+    } else if target.contains("linux") {
+        build.define("LUA_USE_LINUX",None);
+    } else {
+        build.define("LUA_USE_POSIX",None);
+    }
+
+    println!("cargo:rerun-if-env-changed=YUNA_LUA_APICHECK");
+    let apicheck = match env::var("YUNA_LUA_APICHECK") {
+        Ok(value) => value != "0",
+        Err(_) => env::var("PROFILE").map(|profile| profile == "debug").unwrap_or(false),
+    };
+    if apicheck {
+        build.define("LUA_USE_APICHECK",None);
+    }
+    println!("cargo:rerun-if-env-changed=YUNA_LUAI_MAXCCALLS");
+    if let Ok(calls) = env::var("YUNA_LUAI_MAXCCALLS") {
+        build.define("LUAI_MAXCCALLS",Some(calls.as_str()));
+    }
+
+    for entry in dir.read_dir().unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension() == Some("c".as_ref()) {
+            build.file(&path);
+        }
+    }
+    println!("cargo:rerun-if-changed={}",dir.display());
+    build.compile("lua");
+}
+
+#[cfg(not(feature = "vendored"))]
+fn vendored(_dir: &Path) {}
@@ -0,0 +1,145 @@
+//! Precompiling scripts and checking their syntax, like `luac`.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use yuna::State;
+
+const USAGE: &'static str = "usage: yuna compile [--strip] script.lua [-o out.luac]
+       yuna compile --check path...
+
+options:
+    -o FILE     write the bytecode to FILE instead of luac.out
+    --strip     leave out debug information like line numbers and local names
+    --check     only check the syntax of the files, directories are searched for .lua files";
+
+const DEFAULT_OUTPUT: &'static str = "luac.out";
+
+/// The parsed command line of `yuna compile`.
+struct Options {
+    check: bool,
+    strip: bool,
+    output: Option<String>,
+    inputs: Vec<String>,
+}
+
+fn parse(args: &[String]) -> Result<Options,String> {
+    let mut options = Options { check: false, strip: false, output: None, inputs: Vec::new() };
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--check" => options.check = true,
+            "--strip" | "-s" => options.strip = true,
+            "-o" => options.output = Some(iter.next().ok_or("-o needs a file name")?.clone()),
+            option if option.starts_with('-') => return Err(format!("unknown option '{}'",option)),
+            input => options.inputs.push(String::from(input)),
+        }
+    }
+    if options.inputs.is_empty() {
+        return Err(String::from("no input given"));
+    }
+    if options.check && (options.strip || options.output.is_some()) {
+        return Err(String::from("--check writes no output"));
+    }
+    if !options.check && options.inputs.len() > 1 {
+        return Err(String::from("only one script can be compiled at a time"));
+    }
+    Ok(options)
+}
+
+/// Collects the `.lua` files below the path in a stable order.
+fn lua_files(path: &Path,files: &mut Vec<PathBuf>) -> io::Result<()> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+    let mut entries = fs::read_dir(path)?.map(|entry| entry.map(|e| e.path())).collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() {
+            lua_files(&entry,files)?;
+        } else if entry.extension().is_some_and(|ext| ext == "lua") {
+            files.push(entry);
+        }
+    }
+    Ok(())
+}
+
+/// Prints a diagnostic for every file with a syntax error and returns the exit code.
+fn check(inputs: &[String]) -> i32 {
+    let state = State::new();
+    let mut files = Vec::new();
+    for input in inputs {
+        if let Err(err) = lua_files(Path::new(input),&mut files) {
+            eprintln!("yuna: cannot read {}: {}",input,err);
+            return 1;
+        }
+    }
+
+    let mut failed = false;
+    for file in files {
+        let name = file.display().to_string();
+        let source = match fs::read(&file) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("{}: cannot read: {}",name,err);
+                failed = true;
+                continue;
+            },
+        };
+        // Like luaL_loadfile a UTF-8 BOM and a first line starting with '#' are skipped,
+        // keeping the line numbers
+        let mut code = source.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(&source);
+        if code.starts_with(b"#") {
+            let end = code.iter().position(|&b| b == b'\n').unwrap_or(code.len());
+            code = &code[end..];
+        }
+        if let Err(diagnostics) = state.check_syntax(code,&name) {
+            for diagnostic in diagnostics {
+                eprintln!("{}",diagnostic);
+            }
+            failed = true;
+        }
+    }
+    if failed { 1 } else { 0 }
+}
+
+fn compile(input: &str,output: &str,strip: bool) -> i32 {
+    let state = State::new();
+    let chunk = match state.load_file(input) {
+        Ok(chunk) => chunk,
+        Err(err) => {
+            eprintln!("yuna: {}",err);
+            return 1;
+        },
+    };
+    let bytecode = chunk.dump(strip).expect("loaded chunks are lua functions");
+    match fs::write(output,bytecode) {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("yuna: cannot write {}: {}",output,err);
+            1
+        },
+    }
+}
+
+pub fn run(args: &[String]) -> i32 {
+    if args.first().is_some_and(|a| a == "--help" || a == "-h") {
+        println!("{}",USAGE);
+        return 0;
+    }
+    let options = match parse(args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("yuna compile: {}\n{}",message,USAGE);
+            return 2;
+        },
+    };
+    if options.check {
+        check(&options.inputs)
+    } else {
+        let output = options.output.as_ref().map_or(DEFAULT_OUTPUT,|o| o.as_str());
+        compile(&options.inputs[0],output,options.strip)
+    }
+}
@@ -0,0 +1,42 @@
+//! Command line interface to yuna.
+//!
+//! Without arguments an interactive lua prompt is started.
+
+// Field names and lifetimes are spelled out throughout the crate
+#![allow(clippy::redundant_field_names, clippy::redundant_static_lifetimes)]
+
+extern crate rustyline;
+extern crate yuna;
+
+mod compile;
+mod repl;
+mod run;
+
+use std::env;
+use std::process;
+
+const USAGE: &'static str = "usage: yuna [command]
+
+commands:
+    repl                        start an interactive prompt (default)
+    run script.lua [args...]    run a script, see `yuna run --help`
+    compile script.lua          precompile a script, see `yuna compile --help`
+    help                        print this message";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let code = match args.first().map(|s| s.as_str()) {
+        None | Some("repl") => repl::run(),
+        Some("run") => run::run(&args[1..]),
+        Some("compile") => compile::run(&args[1..]),
+        Some("help") | Some("--help") | Some("-h") => {
+            println!("{}",USAGE);
+            0
+        },
+        Some(command) => {
+            eprintln!("yuna: unknown command '{}'\n{}",command,USAGE);
+            2
+        },
+    };
+    process::exit(code);
+}
@@ -0,0 +1,168 @@
+//! The interactive prompt.
+
+use std::env;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::PathBuf;
+
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+
+use yuna::{Function, LuaError, LuaValue, State};
+
+const PROMPT: &'static str = "> ";
+const CONTINUATION_PROMPT: &'static str = ">> ";
+
+/// A source of input lines.
+trait Input {
+    /// Returns the next line or `None` at the end of input.
+    fn read_line(&mut self,prompt: &str) -> Option<String>;
+
+    /// Remembers a complete chunk.
+    fn add_history(&mut self,_chunk: &str) {}
+}
+
+/// Line editing with history, used if stdin is a terminal.
+struct Editor {
+    editor: DefaultEditor,
+    history: Option<PathBuf>,
+}
+
+impl Editor {
+    fn new() -> rustyline::Result<Self> {
+        let mut editor = DefaultEditor::new()?;
+        let history = env::var_os("HOME").map(|home| PathBuf::from(home).join(".yuna_history"));
+        if let Some(ref history) = history {
+            let _ = editor.load_history(history);
+        }
+        Ok(Editor { editor: editor, history: history })
+    }
+}
+
+impl Input for Editor {
+    fn read_line(&mut self,prompt: &str) -> Option<String> {
+        match self.editor.readline(prompt) {
+            Ok(line) => Some(line),
+            // Ctrl-C discards the current line
+            Err(ReadlineError::Interrupted) => Some(String::new()),
+            Err(_) => None,
+        }
+    }
+
+    fn add_history(&mut self,chunk: &str) {
+        let _ = self.editor.add_history_entry(chunk);
+        if let Some(ref history) = self.history {
+            let _ = self.editor.save_history(history);
+        }
+    }
+}
+
+/// Plain lines without prompts, used if stdin is piped.
+struct Lines<R> {
+    reader: R,
+}
+
+impl<R: BufRead> Input for Lines<R> {
+    fn read_line(&mut self,_prompt: &str) -> Option<String> {
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line.trim_end_matches(['\n', '\r']).to_owned()),
+        }
+    }
+}
+
+/// The result of compiling the input collected so far.
+enum Chunk {
+    Complete(Function),
+    Incomplete,
+    Invalid(LuaError),
+}
+
+/// Compiles the input, expressions are turned into `return` statements first.
+fn compile(state: &State,code: &str) -> Chunk {
+    if let Ok(f) = state.load(format!("return {}",code).as_bytes(),"=stdin") {
+        return Chunk::Complete(f);
+    }
+    match state.load(code.as_bytes(),"=stdin") {
+        Ok(f) => Chunk::Complete(f),
+        Err(LuaError::Syntax(ref message)) if message.ends_with("<eof>") || message.ends_with("<eof>'") => Chunk::Incomplete,
+        Err(err) => Chunk::Invalid(err),
+    }
+}
+
+/// Formats a result, numbers and strings as in lua and everything else with `Debug`.
+pub fn show(value: &LuaValue) -> String {
+    match *value {
+        LuaValue::Nil => String::from("nil"),
+        LuaValue::LuaBoolean(b) => b.to_string(),
+        LuaValue::LuaNumber(n) if n.fract() == 0.0 && n.abs() < 1e15 => format!("{}",n as i64),
+        LuaValue::LuaNumber(n) => n.to_string(),
+        LuaValue::LuaString(ref s) => format!("{:?}",s),
+        LuaValue::LuaTable(ref t) => format!("{:?}",t),
+        LuaValue::LuaFunction(ref f) => format!("{:?}",f),
+        LuaValue::LuaUserData(ref u) => format!("{:?}",u),
+        LuaValue::LuaThread(ref t) => format!("{:?}",t),
+    }
+}
+
+/// Reads, evaluates and prints chunks until the input ends.
+fn repl<I: Input>(state: &mut State,input: &mut I) {
+    let mut code = String::new();
+    loop {
+        let prompt = if code.is_empty() { PROMPT } else { CONTINUATION_PROMPT };
+        let line = match input.read_line(prompt) {
+            Some(line) => line,
+            None => break,
+        };
+        if !code.is_empty() {
+            code.push('\n');
+        }
+        code.push_str(&line);
+        if code.trim().is_empty() {
+            code.clear();
+            continue;
+        }
+
+        let result = match compile(state,&code) {
+            Chunk::Incomplete => continue,
+            Chunk::Complete(f) => f.call(vec![]),
+            Chunk::Invalid(err) => Err(err),
+        };
+        input.add_history(&code);
+        code.clear();
+
+        match result {
+            Ok(ref values) if values.is_empty() => {},
+            Ok(values) => {
+                let shown: Vec<String> = values.iter().map(show).collect();
+                println!("{}",shown.join("\t"));
+            },
+            Err(err) => eprintln!("{:#}",err),
+        }
+        let _ = io::stdout().flush();
+    }
+}
+
+/// Runs the prompt on stdin and returns the exit code.
+pub fn run() -> i32 {
+    let mut state = State::new();
+    state.openlibs();
+    // Keep script output in order with the printed results
+    state.set_output(io::stdout());
+    state.set_error_output(io::stderr());
+
+    let stdin = io::stdin();
+    if stdin.is_terminal() {
+        println!("yuna {} prompt, exit with Ctrl-D",yuna::raw::ffi::LUA_VERSION);
+        match Editor::new() {
+            Ok(mut editor) => repl(&mut state,&mut editor),
+            Err(err) => {
+                eprintln!("yuna: {}",err);
+                return 1;
+            },
+        }
+    } else {
+        repl(&mut state,&mut Lines { reader: stdin.lock() });
+    }
+    0
+}
@@ -0,0 +1,113 @@
+//! Running a script file like the standalone interpreter.
+
+use yuna::{Library, LuaIndex, LuaValue, State};
+
+const USAGE: &'static str = "usage: yuna run [options] script.lua [--] [args...]
+
+options:
+    --sandbox                   only open safe libraries, without file access
+    --lib NAME                  open only the named library, may be repeated
+    --memory-limit BYTES        fail allocations above the limit
+    --instruction-limit COUNT   abort after executing the given number of instructions";
+
+/// The parsed command line of `yuna run`.
+struct Options {
+    sandbox: bool,
+    libs: Vec<Library>,
+    memory_limit: Option<usize>,
+    instruction_limit: Option<u64>,
+    script: String,
+    args: Vec<String>,
+}
+
+fn parse(args: &[String]) -> Result<Options,String> {
+    let mut options = Options {
+        sandbox: false,
+        libs: Vec::new(),
+        memory_limit: None,
+        instruction_limit: None,
+        script: String::new(),
+        args: Vec::new(),
+    };
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--sandbox" => options.sandbox = true,
+            "--lib" => {
+                let name = iter.next().ok_or("--lib needs a library name")?;
+                let lib = Library::from_name(name).ok_or_else(|| format!("unknown library '{}'",name))?;
+                options.libs.push(lib);
+            },
+            "--memory-limit" => {
+                let value = iter.next().ok_or("--memory-limit needs a number of bytes")?;
+                options.memory_limit = Some(value.parse().map_err(|_| format!("invalid memory limit '{}'",value))?);
+            },
+            "--instruction-limit" => {
+                let value = iter.next().ok_or("--instruction-limit needs a number")?;
+                options.instruction_limit = Some(value.parse().map_err(|_| format!("invalid instruction limit '{}'",value))?);
+            },
+            option if option.starts_with("--") => return Err(format!("unknown option '{}'",option)),
+            script => {
+                options.script = String::from(script);
+                let rest: Vec<String> = iter.cloned().collect();
+                options.args = match rest.first().map(|s| s.as_str()) {
+                    Some("--") => rest[1..].to_vec(),
+                    _ => rest,
+                };
+                return Ok(options);
+            },
+        }
+    }
+    Err(String::from("no script given"))
+}
+
+/// Creates the global `arg` table, the script is at index 0 and the interpreter at -1.
+fn set_arg(state: &mut State,options: &Options) {
+    let mut arg = state.create_table();
+    arg.set(-1,"yuna");
+    arg.set(0,options.script.as_str());
+    for (i,value) in options.args.iter().enumerate() {
+        arg.set(i as i32 + 1,value.as_str());
+    }
+    state.set("arg",&arg);
+}
+
+pub fn run(args: &[String]) -> i32 {
+    if args.first().is_some_and(|a| a == "--help" || a == "-h") {
+        println!("{}",USAGE);
+        return 0;
+    }
+    let options = match parse(args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("yuna run: {}\n{}",message,USAGE);
+            return 2;
+        },
+    };
+
+    let mut state = State::new();
+    if options.sandbox {
+        // Sandboxed last, opening the base library again would restore `dofile`
+        state.open_libs(&options.libs);
+        state.sandbox();
+    } else if options.libs.is_empty() {
+        state.openlibs();
+    } else {
+        state.open_libs(&options.libs);
+    }
+    set_arg(&mut state,&options);
+    state.set_memory_limit(options.memory_limit);
+    state.set_instruction_limit(options.instruction_limit);
+
+    let result = state.load_file(&options.script).and_then(|chunk| {
+        let args = options.args.iter().map(|a| LuaValue::from_string(a.as_str())).collect();
+        chunk.call(args)
+    });
+    match result {
+        Ok(_) => 0,
+        Err(err) => {
+            eprintln!("yuna: {:#}",err);
+            1
+        },
+    }
+}
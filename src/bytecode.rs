@@ -0,0 +1,202 @@
+//! Rewriting of precompiled chunks in the format of lua 5.1 and 5.2.
+//!
+//! `lua_dump` of these versions always includes debug information, stripping it is done on
+//! the dumped bytes the same way `luac -s` writes them. The line information is also read
+//! to find the executable lines of all functions in a chunk.
+
+#[cfg(feature = "lua51")]
+const SIGNATURE: &'static [u8] = b"\x1bLua\x51\x00";
+#[cfg(feature = "lua52")]
+const SIGNATURE: &'static [u8] = b"\x1bLua\x52\x00";
+
+/// Size of the chunk header, in lua 5.2 including the `LUAC_TAIL`.
+#[cfg(feature = "lua51")]
+const HEADER_SIZE: usize = 12;
+#[cfg(feature = "lua52")]
+const HEADER_SIZE: usize = 18;
+
+const LUA_TNIL: u8 = 0;
+const LUA_TBOOLEAN: u8 = 1;
+const LUA_TNUMBER: u8 = 3;
+const LUA_TSTRING: u8 = 4;
+
+/// Reads a chunk and writes it again without debug information.
+///
+/// The lines of all instructions are collected on the way.
+struct Stripper<'a> {
+    input: &'a [u8],
+    pos: usize,
+    output: Vec<u8>,
+    little_endian: bool,
+    int_size: usize,
+    size_t_size: usize,
+    instruction_size: usize,
+    number_size: usize,
+    lines: Vec<u32>,
+}
+
+impl<'a> Stripper<'a> {
+    fn take(&mut self,n: usize) -> Option<&'a [u8]> {
+        let input = self.input;
+        let bytes = input.get(self.pos..self.pos.checked_add(n)?)?;
+        self.pos += n;
+        Some(bytes)
+    }
+
+    fn copy(&mut self,n: usize) -> Option<()> {
+        let bytes = self.take(n)?;
+        self.output.extend_from_slice(bytes);
+        Some(())
+    }
+
+    fn read_raw(&mut self,size: usize) -> Option<u64> {
+        let bytes = self.take(size)?;
+        let mut value: u64 = 0;
+        for i in 0..size {
+            let byte = if self.little_endian { bytes[size - 1 - i] } else { bytes[i] };
+            value = value.checked_mul(256)? | byte as u64;
+        }
+        Some(value)
+    }
+
+    /// Reads a count or size, which can't be larger than the chunk.
+    fn read_uint(&mut self,size: usize) -> Option<usize> {
+        let value = self.read_raw(size)?;
+        if value > self.input.len() as u64 {
+            return None;
+        }
+        Some(value as usize)
+    }
+
+    /// Reads a count, the value is also written.
+    fn copy_int(&mut self) -> Option<usize> {
+        let start = self.pos;
+        let value = self.read_uint(self.int_size)?;
+        self.output.extend_from_slice(&self.input[start..self.pos]);
+        Some(value)
+    }
+
+    fn write_zero(&mut self,size: usize) {
+        let len = self.output.len() + size;
+        self.output.resize(len,0);
+    }
+
+    fn skip_string(&mut self) -> Option<()> {
+        let len = self.read_uint(self.size_t_size)?;
+        self.take(len).map(|_| ())
+    }
+
+    fn copy_string(&mut self) -> Option<()> {
+        let start = self.pos;
+        self.skip_string()?;
+        self.output.extend_from_slice(&self.input[start..self.pos]);
+        Some(())
+    }
+
+    fn function(&mut self) -> Option<()> {
+        let (size_t_size,int_size) = (self.size_t_size,self.int_size);
+
+        // Lua 5.1 starts with the source and has the number of upvalues in the header,
+        // linedefined, lastlinedefined, (nups,) numparams, is_vararg, maxstacksize
+        #[cfg(feature = "lua51")]
+        {
+            self.skip_string()?;
+            self.write_zero(size_t_size);
+            self.copy(2 * int_size + 4)?;
+        }
+        #[cfg(feature = "lua52")]
+        self.copy(2 * int_size + 3)?;
+
+        let code = self.copy_int()?;
+        self.copy(code.checked_mul(self.instruction_size)?)?;
+
+        let constants = self.copy_int()?;
+        for _ in 0..constants {
+            let kind = *self.take(1)?.first()?;
+            self.output.push(kind);
+            match kind {
+                LUA_TNIL => {},
+                LUA_TBOOLEAN => self.copy(1)?,
+                LUA_TNUMBER => self.copy(self.number_size)?,
+                LUA_TSTRING => self.copy_string()?,
+                _ => return None,
+            }
+        }
+        let protos = self.copy_int()?;
+        for _ in 0..protos {
+            self.function()?;
+        }
+
+        #[cfg(feature = "lua52")]
+        {
+            let upvalues = self.copy_int()?;
+            self.copy(upvalues.checked_mul(2)?)?;
+        }
+
+        // Debug information: (source,) line info, local variables and upvalue names
+        #[cfg(feature = "lua52")]
+        self.skip_string()?;
+        let lines = self.read_uint(self.int_size)?;
+        for _ in 0..lines {
+            let line = self.read_raw(self.int_size)?;
+            self.lines.push(line as u32);
+        }
+        let locals = self.read_uint(self.int_size)?;
+        for _ in 0..locals {
+            self.skip_string()?;
+            self.take(2 * self.int_size)?;
+        }
+        let names = self.read_uint(self.int_size)?;
+        for _ in 0..names {
+            self.skip_string()?;
+        }
+        #[cfg(feature = "lua52")]
+        self.write_zero(size_t_size);
+        self.write_zero(3 * int_size);
+        Some(())
+    }
+}
+
+/// Removes the debug information from a chunk dumped by lua.
+///
+/// Returns `None` if the bytes are no valid chunk.
+pub fn strip(chunk: &[u8]) -> Option<Vec<u8>> {
+    read(chunk).map(|stripper| stripper.output)
+}
+
+/// Returns the lines with code of all functions in a chunk dumped by lua, sorted and
+/// without duplicates.
+///
+/// Returns `None` if the bytes are no valid chunk.
+pub fn lines(chunk: &[u8]) -> Option<Vec<u32>> {
+    let mut lines = read(chunk)?.lines;
+    lines.sort();
+    lines.dedup();
+    Some(lines)
+}
+
+fn read<'a>(chunk: &'a [u8]) -> Option<Stripper<'a>> {
+    if chunk.len() < HEADER_SIZE || !chunk.starts_with(SIGNATURE) {
+        return None;
+    }
+    let mut stripper = Stripper {
+        input: chunk,
+        pos: 0,
+        output: Vec::with_capacity(chunk.len()),
+        little_endian: chunk[6] == 1,
+        int_size: chunk[7] as usize,
+        size_t_size: chunk[8] as usize,
+        instruction_size: chunk[9] as usize,
+        number_size: chunk[10] as usize,
+        lines: Vec::new(),
+    };
+    if [stripper.int_size,stripper.size_t_size].iter().any(|&size| size == 0 || size > 8) {
+        return None;
+    }
+    stripper.copy(HEADER_SIZE)?;
+    stripper.function()?;
+    if stripper.pos != chunk.len() {
+        return None;
+    }
+    Some(stripper)
+}
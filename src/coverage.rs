@@ -0,0 +1,183 @@
+//! Line coverage collected with the line hook.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::CStr;
+use std::fmt::Write;
+use std::ptr;
+
+#[cfg(any(feature = "lua51", feature = "lua52"))]
+use bytecode::lines as chunk_lines;
+use ffi;
+use function;
+use libc;
+#[cfg(not(any(feature = "lua51", feature = "lua52")))]
+use lineinfo::lines as chunk_lines;
+use protect;
+
+/// Hit counts of the lines of a chunk, lines without hits are executable but never ran.
+type Lines = BTreeMap<u32,u64>;
+
+/// Counts the lines run while coverage is collected.
+#[derive(Default)]
+pub struct Collector {
+    chunks: HashMap<String,Lines>,
+    /// Functions whose executable lines are known, by chunk name and line defined.
+    functions: HashSet<(String,libc::c_int)>,
+}
+
+/// Counts the line of a line event.
+///
+/// The collector is only borrowed while no lua function runs, errors raised by lua would
+/// jump over the borrow and leave it borrowed forever.
+pub unsafe fn line(collector: &RefCell<Option<Collector>>,l: *mut ffi::lua_State,ar: *mut ffi::lua_Debug) {
+    ffi::lua_getinfo(l,b"Sl\0".as_ptr() as *const libc::c_char,ar);
+    let line = (*ar).currentline;
+    if line <= 0 || (*ar).source.is_null() {
+        return;
+    }
+    let function = (CStr::from_ptr((*ar).source).to_string_lossy().into_owned(),(*ar).linedefined);
+    let known = match *collector.borrow() {
+        Some(ref collector) => collector.functions.contains(&function),
+        None => return,
+    };
+    let executable = if known { None } else { executable(l,ar) };
+
+    if let Some(ref mut collector) = *collector.borrow_mut() {
+        let lines = collector.chunks.entry(function.0.clone()).or_default();
+        // Without memory for the lines they are read again on the next line event
+        if let Some(executable) = executable {
+            for line in executable {
+                lines.entry(line).or_insert(0);
+            }
+            collector.functions.insert(function.clone());
+        }
+        *lines.entry(line as u32).or_insert(0) += 1;
+    }
+}
+
+/// Returns the executable lines of the running function, for main chunks also the ones of
+/// the functions nested in it, `None` if lua ran out of memory.
+unsafe fn executable(l: *mut ffi::lua_State,ar: *mut ffi::lua_Debug) -> Option<Vec<u32>> {
+    let mut lines = Vec::new();
+    // Pushes the function and a table with its lines as keys, which allocates
+    let status = protect::run_raw(l,0,1,|l| {
+        ffi::lua_getinfo(l,b"fL\0".as_ptr() as *const libc::c_char,ar);
+        ffi::lua_pushnil(l);
+        while ffi::lua_next(l,-2) != 0 {
+            lines.push(ffi::lua_tointegerx(l,-2,ptr::null_mut()) as u32);
+            ffi::lua_pop(l,1);
+        }
+        ffi::lua_pop(l,1);
+        1
+    });
+    if status != ffi::LUA_OK {
+        ffi::lua_pop(l,1);
+        return None;
+    }
+
+    // Nested functions may never be called, their lines are read from the bytecode
+    if (*ar).linedefined == 0 {
+        if let Some(chunk) = function::dump_top(l,false) {
+            lines.extend(chunk_lines(&chunk).unwrap_or_default());
+        }
+    }
+    ffi::lua_pop(l,1);
+    Some(lines)
+}
+
+impl Collector {
+    pub fn finish(self) -> Coverage {
+        Coverage { chunks: self.chunks.into_iter().collect() }
+    }
+}
+
+/// The result of `State::stop_coverage`, the hit counts of executable lines by chunk name.
+///
+/// Lines are executable if lua generated code for them. The lines of functions nested in a
+/// chunk are included once any code of the chunk ran, even if they are never called.
+#[derive(Debug,Clone,Default,PartialEq)]
+pub struct Coverage {
+    chunks: BTreeMap<String,Lines>,
+}
+
+impl Coverage {
+    /// Returns the chunk names, e.g. `@script.lua` for files.
+    pub fn chunks(&self) -> Vec<&str> {
+        self.chunks.keys().map(|chunk| chunk.as_str()).collect()
+    }
+
+    /// Returns the executable lines of a chunk with their hit counts, sorted by line.
+    pub fn lines(&self,chunk: &str) -> Vec<(u32,u64)> {
+        match self.chunks.get(chunk) {
+            Some(lines) => lines.iter().map(|(&line,&hits)| (line,hits)).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Adds the hit counts of another coverage, e.g. of a different state.
+    pub fn merge(&mut self,other: &Coverage) {
+        for (chunk,lines) in &other.chunks {
+            let merged = self.chunks.entry(chunk.clone()).or_default();
+            for (&line,&hits) in lines {
+                *merged.entry(line).or_insert(0) += hits;
+            }
+        }
+    }
+
+    /// Returns the coverage in the lcov tracefile format, as read by `genhtml` and most
+    /// coverage services.
+    ///
+    /// Only chunks named after files are included, i.e. names starting with `@` or `=`,
+    /// which is left out of the path.
+    pub fn lcov(&self) -> String {
+        let mut lcov = String::new();
+        for (chunk,lines) in &self.chunks {
+            if !chunk.starts_with('@') && !chunk.starts_with('=') {
+                continue;
+            }
+            let _ = writeln!(lcov,"TN:\nSF:{}",&chunk[1..]);
+            for (line,hits) in lines {
+                let _ = writeln!(lcov,"DA:{},{}",line,hits);
+            }
+            let hit = lines.values().filter(|&&hits| hits > 0).count();
+            let _ = writeln!(lcov,"LF:{}\nLH:{}\nend_of_record",lines.len(),hit);
+        }
+        lcov
+    }
+
+    /// Returns the coverage as a JSON object mapping chunk names to objects mapping lines to
+    /// hit counts, e.g. `{"@main.lua":{"1":1,"2":0}}`.
+    pub fn json(&self) -> String {
+        let mut json = String::from("{");
+        for (i,(chunk,lines)) in self.chunks.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            json_string(&mut json,chunk);
+            json.push_str(":{");
+            for (j,(line,hits)) in lines.iter().enumerate() {
+                let _ = write!(json,"{}\"{}\":{}",if j > 0 { "," } else { "" },line,hits);
+            }
+            json.push('}');
+        }
+        json.push('}');
+        json
+    }
+}
+
+fn json_string(json: &mut String,s: &str) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => { let _ = write!(json,"\\u{:04x}",c as u32); },
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
@@ -0,0 +1,506 @@
+//! A Debug Adapter Protocol server for stepping through scripts, built on debug hooks.
+//!
+//! The server runs on the thread of the `State`: while the script is stopped at a breakpoint
+//! the hook waits for requests of the client. Supported are line breakpoints, pausing,
+//! stepping in, over and out, stack traces, variables with lazily expanded tables and
+//! evaluating expressions in a frame.
+
+mod protocol;
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use serde_json::Value;
+
+use ffi;
+use stack;
+use {DebugInfo, HookAction, HookMask, LuaIndex, LuaRef, LuaValue, StackFrame, State, Table};
+
+use self::protocol::{Connection, Request};
+pub use self::protocol::{read_message, write_message};
+
+/// The only thread reported to the client, coroutines run on it too.
+const THREAD_ID: i64 = 1;
+
+/// What the hook does on the next line.
+#[derive(Debug,Clone,Copy,PartialEq)]
+enum Mode {
+    Run,
+    /// Stops with the given reason.
+    Pause(&'static str),
+    StepIn,
+    /// Stops once the stack is at most as deep as given.
+    StepOver(usize),
+    /// Stops once the stack is less deep than given.
+    StepOut(usize),
+    /// The client is gone, the script runs without the debugger.
+    Detached,
+}
+
+/// What `variablesReference` numbers refer to while stopped.
+enum Variables {
+    Locals(usize),
+    Globals,
+    Table(Table),
+}
+
+/// The state of the script while it is stopped.
+struct Stopped {
+    frames: Vec<StackFrame>,
+    variables: Vec<Variables>,
+}
+
+/// Whether the script continues after a request.
+enum Flow {
+    Stay,
+    Resume,
+}
+
+struct Session {
+    connection: Connection,
+    mode: Cell<Mode>,
+    configured: Cell<bool>,
+    /// Lines with breakpoints by canonical file path.
+    breakpoints: RefCell<HashMap<PathBuf,Vec<u32>>>,
+    /// The file paths of chunk names, `None` if the chunk is no file.
+    sources: RefCell<HashMap<String,Option<PathBuf>>>,
+    stopped: RefCell<Option<Stopped>>,
+}
+
+/// Resolves a path given by the client or a chunk name to compare breakpoints.
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn type_name(value: &LuaValue) -> &'static str {
+    match *value {
+        LuaValue::Nil => "nil",
+        LuaValue::LuaBoolean(_) => "boolean",
+        LuaValue::LuaNumber(_) => "number",
+        LuaValue::LuaString(_) => "string",
+        LuaValue::LuaTable(_) => "table",
+        LuaValue::LuaFunction(_) => "function",
+        LuaValue::LuaUserData(_) => "userdata",
+        LuaValue::LuaThread(_) => "thread",
+    }
+}
+
+/// Formats a value for the client, tables are shown by their children.
+fn display(value: &LuaValue) -> String {
+    match *value {
+        LuaValue::Nil => String::from("nil"),
+        LuaValue::LuaBoolean(b) => b.to_string(),
+        LuaValue::LuaNumber(n) if n.fract() == 0.0 && n.abs() < 1e15 => format!("{}",n as i64),
+        LuaValue::LuaNumber(n) => n.to_string(),
+        LuaValue::LuaString(ref s) => format!("{:?}",s),
+        LuaValue::LuaTable(ref t) => match t.pairs() {
+            Ok(pairs) => format!("table ({} entries)",pairs.len()),
+            Err(()) => String::from("table"),
+        },
+        LuaValue::LuaFunction(ref f) => format!("{:?}",f),
+        LuaValue::LuaUserData(ref u) => format!("{:?}",u),
+        LuaValue::LuaThread(ref t) => format!("{:?}",t),
+    }
+}
+
+/// The name of a table field as shown to the client.
+fn key_name(key: &LuaValue) -> String {
+    match *key {
+        LuaValue::LuaString(ref s) => s.clone(),
+        ref key => format!("[{}]",display(key)),
+    }
+}
+
+/// Orders array indices first, then names.
+fn sort_fields(fields: &mut [(LuaValue,LuaValue)]) {
+    fields.sort_by(|(a,_),(b,_)| match (a,b) {
+        (&LuaValue::LuaNumber(a),&LuaValue::LuaNumber(b)) => a.partial_cmp(&b).unwrap_or(::std::cmp::Ordering::Equal),
+        (&LuaValue::LuaNumber(_),_) => ::std::cmp::Ordering::Less,
+        (_,&LuaValue::LuaNumber(_)) => ::std::cmp::Ordering::Greater,
+        (a,b) => key_name(a).cmp(&key_name(b)),
+    });
+}
+
+impl Session {
+    fn depth(&self) -> usize {
+        self.stopped.borrow().as_ref().map_or(0,|stopped| stopped.frames.len())
+    }
+
+    /// Handles a request, while stopped or while the script runs.
+    fn handle(&self,state: &State,request: Request) -> Flow {
+        let connection = &self.connection;
+        let arguments = &request.arguments;
+        match request.command.as_str() {
+            "initialize" => {
+                connection.respond(&request,json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsEvaluateForHovers": true,
+                }));
+                connection.event("initialized",json!({}));
+            },
+            "launch" | "attach" => {
+                if arguments["stopOnEntry"] == true {
+                    self.mode.set(Mode::Pause("entry"));
+                }
+                connection.respond(&request,json!({}));
+            },
+            "setBreakpoints" => {
+                let path = arguments["source"]["path"].as_str().map(|path| canonical(Path::new(path)));
+                let lines: Vec<u32> = arguments["breakpoints"].as_array()
+                                                              .map(|breakpoints| breakpoints.iter()
+                                                                                            .filter_map(|b| b["line"].as_u64())
+                                                                                            .map(|line| line as u32)
+                                                                                            .collect())
+                                                              .unwrap_or_default();
+                let breakpoints: Vec<Value> = lines.iter().map(|&line| json!({ "verified": path.is_some(), "line": line })).collect();
+                if let Some(path) = path {
+                    self.breakpoints.borrow_mut().insert(path,lines);
+                }
+                connection.respond(&request,json!({ "breakpoints": breakpoints }));
+            },
+            "setExceptionBreakpoints" => connection.respond(&request,json!({})),
+            "configurationDone" => {
+                self.configured.set(true);
+                connection.respond(&request,json!({}));
+            },
+            "threads" => connection.respond(&request,json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] })),
+            "pause" => {
+                self.mode.set(Mode::Pause("pause"));
+                connection.respond(&request,json!({}));
+            },
+            "continue" => {
+                self.mode.set(Mode::Run);
+                connection.respond(&request,json!({ "allThreadsContinued": true }));
+                return Flow::Resume;
+            },
+            "next" | "stepIn" | "stepOut" => {
+                self.mode.set(match request.command.as_str() {
+                    "next" => Mode::StepOver(self.depth()),
+                    "stepIn" => Mode::StepIn,
+                    _ => Mode::StepOut(self.depth()),
+                });
+                connection.respond(&request,json!({}));
+                return Flow::Resume;
+            },
+            "stackTrace" => self.stack_trace(&request),
+            "scopes" => self.scopes(&request),
+            "variables" => self.variables(state,&request),
+            "evaluate" => self.evaluate(state,&request),
+            "disconnect" => {
+                self.mode.set(Mode::Detached);
+                connection.respond(&request,json!({}));
+                return Flow::Resume;
+            },
+            _ => connection.respond_error(&request,&format!("unsupported request '{}'",request.command)),
+        }
+        Flow::Stay
+    }
+
+    fn stack_trace(&self,request: &Request) {
+        let stopped = self.stopped.borrow();
+        let frames = stopped.as_ref().map_or(&[][..],|stopped| &stopped.frames[..]);
+        let start = request.arguments["startFrame"].as_u64().unwrap_or(0) as usize;
+        let levels = match request.arguments["levels"].as_u64() {
+            Some(levels) if levels > 0 => levels as usize,
+            _ => frames.len(),
+        };
+        let stack_frames: Vec<Value> = frames.iter().enumerate().skip(start).take(levels).map(|(i,frame)| {
+            let name = match (frame.name.as_ref(),frame.what.as_str()) {
+                (Some(name),_) => name.clone(),
+                (None,"main") => String::from("main chunk"),
+                (None,_) => String::from("?"),
+            };
+            let mut json = json!({
+                "id": i + 1,
+                "name": name,
+                "line": frame.line.unwrap_or(0),
+                "column": if frame.line.is_some() { 1 } else { 0 },
+            });
+            let chunk = frame.chunk_name();
+            if let Some(file) = chunk.strip_prefix('@') {
+                let path = canonical(Path::new(file));
+                let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+                json["source"] = json!({ "name": name, "path": path.to_string_lossy() });
+            } else if frame.what != "C" {
+                json["source"] = json!({ "name": frame.source });
+            }
+            json
+        }).collect();
+        self.connection.respond(request,json!({ "stackFrames": stack_frames, "totalFrames": frames.len() }));
+    }
+
+    /// Remembers what the variables are read from and returns the `variablesReference`.
+    fn reference(stopped: &mut Stopped,variables: Variables) -> usize {
+        stopped.variables.push(variables);
+        stopped.variables.len()
+    }
+
+    fn scopes(&self,request: &Request) {
+        let mut stopped = self.stopped.borrow_mut();
+        let frame = request.arguments["frameId"].as_u64().unwrap_or(0) as usize;
+        match stopped.as_mut() {
+            Some(stopped) if frame > 0 && frame <= stopped.frames.len() => {
+                let locals = Session::reference(stopped,Variables::Locals(frame - 1));
+                let globals = Session::reference(stopped,Variables::Globals);
+                self.connection.respond(request,json!({ "scopes": [
+                    { "name": "Locals", "variablesReference": locals, "expensive": false },
+                    { "name": "Globals", "variablesReference": globals, "expensive": true },
+                ]}));
+            },
+            _ => self.connection.respond_error(request,"no such frame"),
+        }
+    }
+
+    /// Describes a value, tables get a reference to expand them later.
+    fn variable(stopped: &mut Stopped,name: String,value: LuaValue) -> Value {
+        let reference = match value {
+            LuaValue::LuaTable(ref t) if t.pairs().is_ok_and(|pairs| !pairs.is_empty()) => Session::reference(stopped,Variables::Table(t.clone())),
+            _ => 0,
+        };
+        json!({
+            "name": name,
+            "value": display(&value),
+            "type": type_name(&value),
+            "variablesReference": reference,
+        })
+    }
+
+    fn variables(&self,state: &State,request: &Request) {
+        let mut stopped = self.stopped.borrow_mut();
+        let stopped = match stopped.as_mut() {
+            Some(stopped) => stopped,
+            None => return self.connection.respond_error(request,"not stopped"),
+        };
+        let reference = request.arguments["variablesReference"].as_u64().unwrap_or(0) as usize;
+        let fields: Vec<(String,LuaValue)> = match stopped.variables.get(reference.wrapping_sub(1)) {
+            Some(&Variables::Locals(frame)) => stopped.frames[frame].locals(),
+            Some(&Variables::Globals) => {
+                // Without memory for the fields there is nothing to show
+                let mut fields = state.global().pairs().unwrap_or_default();
+                sort_fields(&mut fields);
+                fields.into_iter().map(|(key,value)| (key_name(&key),value)).collect()
+            },
+            Some(Variables::Table(t)) => {
+                let mut fields = t.pairs().unwrap_or_default();
+                sort_fields(&mut fields);
+                fields.into_iter().map(|(key,value)| (key_name(&key),value)).collect()
+            },
+            None => return self.connection.respond_error(request,"no such variables"),
+        };
+        let variables: Vec<Value> = fields.into_iter().map(|(name,value)| Session::variable(stopped,name,value)).collect();
+        self.connection.respond(request,json!({ "variables": variables }));
+    }
+
+    /// Evaluates an expression or statement, locals of the frame are visible to it.
+    fn evaluate(&self,state: &State,request: &Request) {
+        let expression = request.arguments["expression"].as_str().unwrap_or("");
+        let frame = request.arguments["frameId"].as_u64().unwrap_or(0) as usize;
+        let chunk = match state.load(format!("return {}",expression).as_bytes(),"=eval") {
+            Ok(chunk) => chunk,
+            Err(_) => match state.load(expression.as_bytes(),"=eval") {
+                Ok(chunk) => chunk,
+                Err(err) => return self.connection.respond_error(request,err.message()),
+            },
+        };
+
+        let locals = match self.stopped.borrow().as_ref() {
+            Some(stopped) if frame > 0 && frame <= stopped.frames.len() => Some(stopped.frames[frame - 1].locals()),
+            _ => None,
+        };
+        if let Some(locals) = locals {
+            let mut env = state.create_table();
+            for (name,value) in locals {
+                env.set(name.as_str(),value);
+            }
+            // The environment falls back to the globals and replaces the one of the chunk
+            let l = state.context.l;
+            unsafe {
+                env.write_self();
+                ffi::lua_createtable(l,0,1);
+                ffi::lua_pushglobaltable(l);
+                ffi::lua_setfield(l,-2,b"__index\0".as_ptr() as *const ::libc::c_char);
+                ffi::lua_setmetatable(l,-2);
+                ffi::lua_pop(l,1);
+            }
+            chunk.set_env(&env);
+        }
+
+        match chunk.call(vec![]) {
+            Ok(results) => {
+                let result = if results.is_empty() {
+                    String::from("nil")
+                } else {
+                    results.iter().map(display).collect::<Vec<_>>().join(", ")
+                };
+                let reference = match (results.len(),results.into_iter().next(),self.stopped.borrow_mut().as_mut()) {
+                    (1,Some(LuaValue::LuaTable(t)),Some(stopped)) => Session::reference(stopped,Variables::Table(t)),
+                    _ => 0,
+                };
+                self.connection.respond(request,json!({ "result": result, "variablesReference": reference }));
+            },
+            Err(err) => self.connection.respond_error(request,err.message()),
+        }
+    }
+
+    /// Returns the path of the chunk if it is a file.
+    fn source_path(&self,chunk: String) -> Option<PathBuf> {
+        let mut sources = self.sources.borrow_mut();
+        sources.entry(chunk)
+               .or_insert_with_key(|chunk| chunk.strip_prefix('@').map(|file| canonical(Path::new(file))))
+               .clone()
+    }
+
+    fn at_breakpoint(&self,info: &DebugInfo) -> bool {
+        let line = match info.line() {
+            Some(line) => line,
+            None => return false,
+        };
+        // Chunk names are only looked at on lines with a breakpoint in any file
+        if !self.breakpoints.borrow().values().any(|lines| lines.contains(&line)) {
+            return false;
+        }
+        let path = match self.source_path(info.source()) {
+            Some(path) => path,
+            None => return false,
+        };
+        self.breakpoints.borrow().iter().any(|(file,lines)| {
+            lines.contains(&line) && (*file == path || (path.is_relative() && file.ends_with(&path)))
+        })
+    }
+
+    /// Called by the hook for every line.
+    fn on_line(&self,state: &State,info: &DebugInfo) -> HookAction {
+        while let Some(request) = self.connection.try_recv() {
+            self.handle(state,request);
+        }
+        if self.connection.is_closed() {
+            self.mode.set(Mode::Detached);
+        }
+        let reason = match self.mode.get() {
+            Mode::Detached => return HookAction::Continue,
+            Mode::Run => None,
+            Mode::Pause(reason) => Some(reason),
+            Mode::StepIn => Some("step"),
+            Mode::StepOver(depth) if stack::depth(&state.context) <= depth => Some("step"),
+            Mode::StepOut(depth) if stack::depth(&state.context) < depth => Some("step"),
+            Mode::StepOver(_) | Mode::StepOut(_) => None,
+        };
+        let reason = reason.or_else(|| if self.at_breakpoint(info) { Some("breakpoint") } else { None });
+        if let Some(reason) = reason {
+            self.stop(state,reason);
+        }
+        HookAction::Continue
+    }
+
+    /// Waits for requests until the client lets the script continue.
+    fn stop(&self,state: &State,reason: &str) {
+        *self.stopped.borrow_mut() = Some(Stopped { frames: state.stack_frames(), variables: Vec::new() });
+        self.mode.set(Mode::Run);
+        self.connection.event("stopped",json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }));
+        loop {
+            match self.connection.recv() {
+                Some(request) => if let Flow::Resume = self.handle(state,request) { break },
+                None => {
+                    self.mode.set(Mode::Detached);
+                    break;
+                },
+            }
+        }
+        // The frames refer to the state, they must not outlive the stop
+        *self.stopped.borrow_mut() = None;
+    }
+}
+
+/// Sends script output to the client as `output` events.
+struct Output {
+    session: Rc<Session>,
+    category: &'static str,
+}
+
+impl Write for Output {
+    fn write(&mut self,buf: &[u8]) -> io::Result<usize> {
+        self.session.connection.event("output",json!({ "category": self.category, "output": String::from_utf8_lossy(buf) }));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A debug adapter connected to a client like an editor.
+///
+/// # Examples
+///
+/// ```no_run
+/// let mut state = yuna::State::new();
+/// state.openlibs();
+///
+/// let debugger = yuna::dap::Debugger::listen("127.0.0.1:4711").unwrap();
+/// debugger.attach(&mut state).unwrap();
+/// let result = state.do_file("main.lua");
+/// debugger.finish(if result.is_ok() { 0 } else { 1 });
+/// ```
+pub struct Debugger {
+    session: Rc<Session>,
+}
+
+impl Debugger {
+    /// Talks to the client over the given streams.
+    pub fn new<R: Read + Send + 'static,W: Write + 'static>(reader: R,writer: W) -> Self {
+        Debugger {
+            session: Rc::new(Session {
+                connection: Connection::new(reader,writer),
+                mode: Cell::new(Mode::Run),
+                configured: Cell::new(false),
+                breakpoints: RefCell::new(HashMap::new()),
+                sources: RefCell::new(HashMap::new()),
+                stopped: RefCell::new(None),
+            }),
+        }
+    }
+
+    /// Talks to the client over stdin and stdout, the client starts the program.
+    pub fn stdio() -> Self {
+        Debugger::new(io::stdin(),io::stdout())
+    }
+
+    /// Waits for a client to connect to the address.
+    pub fn listen<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let (stream,_) = listener.accept()?;
+        Ok(Debugger::new(stream.try_clone()?,stream))
+    }
+
+    /// Handles the requests configuring the session and installs the debug hook.
+    ///
+    /// Returns once the client sent `configurationDone`. Script output is sent to the
+    /// client from then on, so it doesn't interfere with the protocol on stdout.
+    pub fn attach(&self,state: &mut State) -> io::Result<()> {
+        let session = &self.session;
+        while !session.configured.get() && session.mode.get() != Mode::Detached {
+            match session.connection.recv() {
+                Some(request) => { session.handle(state,request); },
+                None => return Err(io::Error::new(io::ErrorKind::ConnectionAborted,"debug client disconnected")),
+            }
+        }
+        state.set_output(Output { session: session.clone(), category: "stdout" });
+        state.set_error_output(Output { session: session.clone(), category: "stderr" });
+
+        let session = session.clone();
+        state.set_hook(HookMask { line: true, ..HookMask::default() },move |state,info| session.on_line(state,info));
+        Ok(())
+    }
+
+    /// Tells the client the program ended.
+    pub fn finish(&self,exit_code: i32) {
+        let connection = &self.session.connection;
+        connection.event("exited",json!({ "exitCode": exit_code }));
+        connection.event("terminated",json!({}));
+    }
+}
@@ -0,0 +1,143 @@
+//! Framing and sending of Debug Adapter Protocol messages.
+
+use std::cell::{Cell, RefCell};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use serde_json::{self, Value};
+
+/// A request of the client.
+pub struct Request {
+    pub seq: i64,
+    pub command: String,
+    pub arguments: Value,
+}
+
+/// Reads one message with its `Content-Length` header, `None` at the end of the stream.
+pub fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            if length.is_some() {
+                break;
+            }
+            continue;
+        }
+        let mut parts = line.splitn(2,':');
+        if let (Some(name),Some(value)) = (parts.next(),parts.next()) {
+            if name.trim().eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let mut body = vec![0; length.unwrap()];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body).map(Some).map_err(|err| io::Error::new(io::ErrorKind::InvalidData,err))
+}
+
+/// Writes one message with its `Content-Length` header.
+pub fn write_message<W: Write + ?Sized>(writer: &mut W,message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer,"Content-Length: {}\r\n\r\n{}",body.len(),body)?;
+    writer.flush()
+}
+
+/// The connection to a client.
+///
+/// Requests are read on a separate thread, so they can be checked for while scripts run.
+pub struct Connection {
+    writer: RefCell<Box<dyn Write>>,
+    requests: Receiver<Request>,
+    seq: Cell<i64>,
+    closed: Cell<bool>,
+}
+
+impl Connection {
+    pub fn new<R: Read + Send + 'static,W: Write + 'static>(reader: R,writer: W) -> Connection {
+        let (sender,requests) = mpsc::channel();
+        thread::spawn(move || {
+            let mut reader = BufReader::new(reader);
+            while let Ok(Some(message)) = read_message(&mut reader) {
+                if message["type"] != "request" {
+                    continue;
+                }
+                let request = Request {
+                    seq: message["seq"].as_i64().unwrap_or(0),
+                    command: message["command"].as_str().unwrap_or("").to_string(),
+                    arguments: message["arguments"].clone(),
+                };
+                if sender.send(request).is_err() {
+                    break;
+                }
+            }
+        });
+        Connection {
+            writer: RefCell::new(Box::new(writer)),
+            requests: requests,
+            seq: Cell::new(0),
+            closed: Cell::new(false),
+        }
+    }
+
+    /// Waits for the next request, `None` once the client is gone.
+    pub fn recv(&self) -> Option<Request> {
+        let request = self.requests.recv().ok();
+        self.closed.set(request.is_none());
+        request
+    }
+
+    /// Returns the next request if one arrived.
+    pub fn try_recv(&self) -> Option<Request> {
+        match self.requests.try_recv() {
+            Ok(request) => Some(request),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                self.closed.set(true);
+                None
+            },
+        }
+    }
+
+    /// Returns true once the client closed the connection.
+    pub fn is_closed(&self) -> bool {
+        self.closed.get()
+    }
+
+    fn send(&self,mut message: Value) {
+        let seq = self.seq.get() + 1;
+        self.seq.set(seq);
+        message["seq"] = Value::from(seq);
+        // A client which went away is noticed by the reader
+        let _ = write_message(&mut **self.writer.borrow_mut(),&message);
+    }
+
+    pub fn respond(&self,request: &Request,body: Value) {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request.seq,
+            "success": true,
+            "command": request.command,
+            "body": body,
+        }));
+    }
+
+    pub fn respond_error(&self,request: &Request,message: &str) {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request.seq,
+            "success": false,
+            "command": request.command,
+            "message": message,
+        }));
+    }
+
+    pub fn event(&self,event: &str,body: Value) {
+        self.send(json!({ "type": "event", "event": event, "body": body }));
+    }
+}
@@ -0,0 +1,378 @@
+use std::error;
+use std::ffi::CStr;
+use std::fmt;
+use std::panic;
+use std::ptr;
+use std::rc::Rc;
+
+use ffi;
+use function;
+use lauxlib;
+use libc;
+use protect;
+use {LuaContext, LuaRead, LuaValue};
+
+/// A single function activation of a lua call stack.
+#[derive(Debug,Clone,PartialEq)]
+pub struct TraceFrame {
+    /// The chunk name as lua prints it, e.g. `[string "..."]` or a file name.
+    pub source: String,
+    /// The line currently executed or `None` if not available, e.g. for C functions.
+    pub line: Option<u32>,
+    /// The name of the function if lua could determine it.
+    pub name: Option<String>,
+    /// `"Lua"`, `"C"` or `"main"`.
+    pub what: String,
+}
+
+/// The call stack at the point an error was raised.
+#[derive(Debug,Clone,PartialEq,Default)]
+pub struct Traceback {
+    text: String,
+    frames: Vec<TraceFrame>,
+}
+
+impl Traceback {
+    /// The traceback formatted like lua's `debug.traceback`.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The frames of the traceback, innermost first.
+    pub fn frames(&self) -> &[TraceFrame] {
+        &self.frames
+    }
+}
+
+/// A problem found while checking a chunk, see `State::check_syntax`.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Diagnostic {
+    /// The chunk name as lua prints it.
+    pub source: String,
+    /// The line of the problem if lua reported one.
+    pub line: Option<u32>,
+    /// The message without the location prefix.
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// Creates a diagnostic from a message of the form `source:line: message`.
+    pub fn from_message(message: &str) -> Diagnostic {
+        match split_location(message) {
+            Some((source,line,text)) => Diagnostic {
+                source: source.to_owned(),
+                line: Some(line),
+                message: text.to_owned(),
+            },
+            None => Diagnostic {
+                source: String::new(),
+                line: None,
+                message: message.to_owned(),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f,"{}:{}: {}",self.source,line,self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+/// Errors which can occur while loading or running lua code.
+#[derive(Debug)]
+pub enum LuaError {
+    /// The chunk could not be compiled.
+    Syntax(String),
+    /// An error was raised while running the code.
+    Runtime {
+        message: String,
+        traceback: Traceback,
+        /// The error object if it was not a string, e.g. the table of `error({code = 42})`.
+        value: Option<LuaValue>,
+        /// The error returned by a rust callback.
+        cause: Option<Rc<dyn error::Error>>,
+    },
+    /// Memory allocation failed.
+    Memory,
+    /// An error was raised while handling another error.
+    ErrorHandler(String),
+    /// A file could not be opened or read.
+    File(String),
+}
+
+impl LuaError {
+    /// The error message as reported by lua.
+    pub fn message(&self) -> &str {
+        match *self {
+            LuaError::Syntax(ref message)             => message,
+            LuaError::Runtime { ref message, .. }     => message,
+            LuaError::Memory                          => "not enough memory",
+            LuaError::ErrorHandler(ref message)       => message,
+            LuaError::File(ref message)               => message,
+        }
+    }
+
+    /// The traceback text of a runtime error.
+    pub fn traceback(&self) -> Option<&str> {
+        match *self {
+            LuaError::Runtime { ref traceback, .. } => Some(traceback.text()),
+            _ => None,
+        }
+    }
+
+    /// The stack frames of a runtime error, innermost first.
+    pub fn frames(&self) -> &[TraceFrame] {
+        match *self {
+            LuaError::Runtime { ref traceback, .. } => traceback.frames(),
+            _ => &[],
+        }
+    }
+
+    /// The chunk name of the location the error was raised at, as lua prints it.
+    ///
+    /// It is parsed from the `source:line:` prefix of the message, which `error(message, 2)`
+    /// points at the caller. Without a prefix it is the innermost lua function of the
+    /// traceback.
+    pub fn source(&self) -> Option<&str> {
+        match split_location(self.message()) {
+            Some((source,_,_)) => Some(source),
+            None => self.lua_frame().map(|frame| frame.source.as_str()),
+        }
+    }
+
+    /// The line of the location the error was raised at, see `source`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut state = yuna::State::new();
+    ///
+    /// let err = state.do_string("x = 1\nx = = 2").unwrap_err();
+    /// assert_eq!(err.source(),Some("[string \"x = 1...\"]"));
+    /// assert_eq!(err.line(),Some(2));
+    /// assert_eq!(err.message_without_location(),"unexpected symbol near '='");
+    /// ```
+    pub fn line(&self) -> Option<u32> {
+        match split_location(self.message()) {
+            Some((_,line,_)) => Some(line),
+            None => self.lua_frame().and_then(|frame| frame.line),
+        }
+    }
+
+    /// The error message without the `source:line:` prefix lua adds.
+    pub fn message_without_location(&self) -> &str {
+        match split_location(self.message()) {
+            Some((_,_,message)) => message,
+            None => self.message(),
+        }
+    }
+
+    /// The innermost frame of a lua function, skipping e.g. the C function `error`.
+    fn lua_frame(&self) -> Option<&TraceFrame> {
+        self.frames().iter().find(|frame| frame.what != "C" && frame.line.is_some())
+    }
+
+    /// The error object raised by lua if it was not a string.
+    pub fn value(&self) -> Option<&LuaValue> {
+        match *self {
+            LuaError::Runtime { ref value, .. } => value.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Returns the error returned by a rust callback if it is of type `T`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::fmt;
+    /// use yuna::LuaIndex;
+    ///
+    /// #[derive(Debug)]
+    /// struct NotFound;
+    ///
+    /// impl fmt::Display for NotFound {
+    ///     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    ///         f.write_str("not found")
+    ///     }
+    /// }
+    ///
+    /// impl std::error::Error for NotFound {}
+    ///
+    /// let mut state = yuna::State::new();
+    /// let find = state.create_function(|_,_| Err(Box::new(NotFound)));
+    /// state.set("find",&find);
+    ///
+    /// let err = state.do_string("find()").unwrap_err();
+    /// assert!(err.downcast_ref::<NotFound>().is_some());
+    /// ```
+    pub fn downcast_ref<T: error::Error + 'static>(&self) -> Option<&T> {
+        match *self {
+            LuaError::Runtime { cause: Some(ref cause), .. } => cause.downcast_ref::<T>(),
+            _ => None,
+        }
+    }
+}
+
+/// Prints the error message, the alternate form `{:#}` appends the traceback.
+impl fmt::Display for LuaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.message())?;
+        match self.traceback() {
+            Some(traceback) if f.alternate() => write!(f,"\n{}",traceback),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl error::Error for LuaError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            LuaError::Runtime { cause: Some(ref cause), .. } => Some(&**cause),
+            _ => None,
+        }
+    }
+}
+
+/// Splits a message of the form `source:line: message` as created by `luaL_where`.
+fn split_location(message: &str) -> Option<(&str,u32,&str)> {
+    // Code in string chunk names may contain anything, so skip them as a whole
+    let start = if message.starts_with("[string \"") {
+        message.find("\"]:").map(|i| i + 2)?
+    } else {
+        0
+    };
+    for (i,_) in message[start..].match_indices(':') {
+        let i = start + i;
+        let rest = &message[i + 1..];
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        if i > 0 && digits > 0 && rest[digits..].starts_with(':') {
+            let line = rest[..digits].parse().ok()?;
+            let text = &rest[digits + 1..];
+            return Some((&message[..i],line,text.strip_prefix(' ').unwrap_or(text)));
+        }
+        if start > 0 {
+            break;
+        }
+    }
+    None
+}
+
+/// Reads a possibly null C string.
+pub unsafe fn read_cstr(s: *const libc::c_char) -> Option<String> {
+    if s.is_null() {
+        None
+    } else {
+        Some(CStr::from_ptr(s).to_string_lossy().into_owned())
+    }
+}
+
+/// Returns the frames of the call stack starting at `level`.
+pub unsafe fn stack_frames(l: *mut ffi::lua_State,level: libc::c_int) -> Vec<TraceFrame> {
+    let mut frames = Vec::new();
+    let mut ar: ffi::lua_Debug = std::mem::zeroed();
+    let mut level = level;
+    while ffi::lua_getstack(l,level,&mut ar) != 0 {
+        ffi::lua_getinfo(l,b"Sln\0".as_ptr() as *const libc::c_char,&mut ar);
+        frames.push(TraceFrame {
+            source: read_cstr(ar.short_src.as_ptr()).unwrap_or_default(),
+            line: if ar.currentline > 0 { Some(ar.currentline as u32) } else { None },
+            name: read_cstr(ar.name),
+            what: read_cstr(ar.what).unwrap_or_default(),
+        });
+        level += 1;
+    }
+    frames
+}
+
+/// Message handler which records the traceback in the `Traceback` of the running `pcall`.
+///
+/// The error value itself is passed through unchanged.
+pub extern "C-unwind" fn message_handler(l: *mut ffi::lua_State) -> libc::c_int {
+    unsafe {
+        let handle = ::handle_ptr(l);
+        if handle.is_null() || (*handle).traceback.get().is_null() {
+            return 1;
+        }
+        let traceback = (*handle).traceback.get();
+
+        lauxlib::luaL_traceback(l,l,ptr::null(),1);
+        (*traceback).text = read_cstr(ffi::lua_tostring(l,-1)).unwrap_or_default();
+        ffi::lua_pop(l,1);
+        (*traceback).frames = stack_frames(l,1);
+    }
+    1
+}
+
+/// Converts the error value on top of the stack into a message.
+unsafe fn error_message(l: *mut ffi::lua_State) -> String {
+    match ffi::lua_type(l,-1) {
+        ffi::LUA_TSTRING | ffi::LUA_TNUMBER => read_cstr(ffi::lua_tostring(l,-1)).unwrap_or_default(),
+        tp => {
+            let name = read_cstr(ffi::lua_typename(l,tp)).unwrap_or_default();
+            format!("(error object is a {} value)",name)
+        },
+    }
+}
+
+/// Reads the error value on top of the stack if it is not a string.
+unsafe fn error_value(context: &LuaContext) -> Option<LuaValue> {
+    match ffi::lua_type(context.l,-1) {
+        ffi::LUA_TSTRING => None,
+        _ => LuaRead::lua_read_index(context,-1).ok(),
+    }
+}
+
+/// Pops the error value of a failed load or call and converts it into a `LuaError`.
+///
+/// If the error is a panic caught in a rust callback, the panic is resumed instead.
+pub unsafe fn pop_error(context: &LuaContext,status: libc::c_int,traceback: Traceback) -> LuaError {
+    if let Some(payload) = function::take_panic(context.l) {
+        ffi::lua_pop(context.l,1);
+        panic::resume_unwind(payload);
+    }
+    let cause = function::error_cause(context.l);
+    let value = match cause {
+        Some(_) => None,
+        None => error_value(context),
+    };
+    let message = match cause {
+        Some(ref cause) => cause.to_string(),
+        None => error_message(context.l),
+    };
+    ffi::lua_pop(context.l,1);
+    match status {
+        ffi::LUA_ERRSYNTAX      => LuaError::Syntax(message),
+        ffi::LUA_ERRMEM         => LuaError::Memory,
+        ffi::LUA_ERRERR         => LuaError::ErrorHandler(message),
+        lauxlib::LUA_ERRFILE    => LuaError::File(message),
+        _                       => LuaError::Runtime { message: message, traceback: traceback, value: value, cause: cause },
+    }
+}
+
+/// Calls the function below the `nargs` arguments on top of the stack in protected mode.
+///
+/// On success `nresults` values are left on the stack, on error nothing.
+pub unsafe fn pcall(context: &LuaContext,nargs: libc::c_int,nresults: libc::c_int) -> Result<(),LuaError> {
+    let l = context.l;
+    let base = ffi::lua_gettop(l) - nargs;
+    let mut traceback = Traceback::default();
+
+    // The handler is pushed without allocating, calls may be nested
+    protect::push_function(l,message_handler);
+    ffi::lua_insert(l,base);
+    let previous = context.handle.traceback.replace(&mut traceback);
+
+    let status = ffi::lua_pcall(l,nargs,nresults,base);
+    context.handle.traceback.set(previous);
+    ffi::lua_remove(l,base);
+
+    match status {
+        ffi::LUA_OK => Ok(()),
+        _ => Err(pop_error(context,status,traceback)),
+    }
+}
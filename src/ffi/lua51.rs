@@ -0,0 +1,170 @@
+//! The parts of the API specific to lua 5.1 and LuaJIT, with the functions of lua 5.2 they
+//! lack.
+
+use libc::{c_char, c_int, c_uint, c_void, ptrdiff_t, size_t};
+
+use super::*;
+
+pub const LUA_REGISTRYINDEX: c_int = -10000;
+pub const LUA_ENVIRONINDEX: c_int = -10001;
+pub const LUA_GLOBALSINDEX: c_int = -10002;
+pub fn lua_upvalueindex(i: c_int) -> c_int { LUA_GLOBALSINDEX - i }
+
+pub const LUA_ERRERR: c_int = 5;
+
+pub type lua_Integer = ptrdiff_t;
+pub type lua_Unsigned = c_uint;
+
+pub const LUA_HOOKTAILRET: c_int = 4;
+
+#[cfg(feature = "luajit")]
+pub const LUAJIT_MODE_ENGINE: c_int = 0;
+#[cfg(feature = "luajit")]
+pub const LUAJIT_MODE_OFF: c_int = 0x0000;
+#[cfg(feature = "luajit")]
+pub const LUAJIT_MODE_ON: c_int = 0x0100;
+#[cfg(feature = "luajit")]
+pub const LUAJIT_MODE_FLUSH: c_int = 0x0200;
+
+#[repr(C)]
+pub struct lua_Debug {
+    pub event: c_int,
+    pub name: *const c_char,
+    pub namewhat: *const c_char,
+    pub what: *const c_char,
+    pub source: *const c_char,
+    pub currentline: c_int,
+    pub nups: c_int,
+    pub linedefined: c_int,
+    pub lastlinedefined: c_int,
+    pub short_src: [c_char; LUA_IDSIZE],
+    i_ci: c_int,
+}
+
+extern "C-unwind" {
+    pub fn lua_remove(L: *mut lua_State, idx: c_int);
+    pub fn lua_insert(L: *mut lua_State, idx: c_int);
+    pub fn lua_replace(L: *mut lua_State, idx: c_int);
+
+    pub fn lua_equal(L: *mut lua_State, idx1: c_int, idx2: c_int) -> c_int;
+    pub fn lua_lessthan(L: *mut lua_State, idx1: c_int, idx2: c_int) -> c_int;
+    pub fn lua_objlen(L: *mut lua_State, idx: c_int) -> size_t;
+
+    pub fn lua_pushlstring(L: *mut lua_State, s: *const c_char, l: size_t);
+    pub fn lua_pushstring(L: *mut lua_State, s: *const c_char);
+
+    pub fn lua_gettable(L: *mut lua_State, idx: c_int);
+    pub fn lua_getfield(L: *mut lua_State, idx: c_int, k: *const c_char);
+    pub fn lua_rawget(L: *mut lua_State, idx: c_int);
+    pub fn lua_rawgeti(L: *mut lua_State, idx: c_int, n: c_int);
+    pub fn lua_newuserdata(L: *mut lua_State, sz: size_t) -> *mut c_void;
+    pub fn lua_getfenv(L: *mut lua_State, idx: c_int);
+
+    pub fn lua_rawseti(L: *mut lua_State, idx: c_int, n: c_int);
+    pub fn lua_setfenv(L: *mut lua_State, idx: c_int) -> c_int;
+
+    pub fn lua_call(L: *mut lua_State, nargs: c_int, nresults: c_int);
+    pub fn lua_pcall(L: *mut lua_State, nargs: c_int, nresults: c_int, errfunc: c_int) -> c_int;
+    pub fn lua_cpcall(L: *mut lua_State, func: lua_CFunction, ud: *mut c_void) -> c_int;
+    pub fn lua_load(L: *mut lua_State, reader: lua_Reader, dt: *mut c_void, chunkname: *const c_char) -> c_int;
+    pub fn lua_dump(L: *mut lua_State, writer: lua_Writer, data: *mut c_void) -> c_int;
+
+    pub fn lua_yield(L: *mut lua_State, nresults: c_int) -> c_int;
+    pub fn lua_resume(L: *mut lua_State, narg: c_int) -> c_int;
+
+    pub fn lua_gc(L: *mut lua_State, what: c_int, data: c_int) -> c_int;
+
+    pub fn lua_sethook(L: *mut lua_State, func: lua_Hook, mask: c_int, count: c_int) -> c_int;
+
+    #[cfg(feature = "lua51")]
+    fn lua_tonumber(L: *mut lua_State, idx: c_int) -> lua_Number;
+    #[cfg(feature = "lua51")]
+    fn lua_tointeger(L: *mut lua_State, idx: c_int) -> lua_Integer;
+}
+
+#[cfg(feature = "luajit")]
+extern "C-unwind" {
+    pub fn lua_copy(L: *mut lua_State, fromidx: c_int, toidx: c_int);
+    pub fn lua_tonumberx(L: *mut lua_State, idx: c_int, isnum: *mut c_int) -> lua_Number;
+    pub fn lua_tointegerx(L: *mut lua_State, idx: c_int, isnum: *mut c_int) -> lua_Integer;
+    pub fn lua_loadx(L: *mut lua_State, reader: lua_Reader, dt: *mut c_void, chunkname: *const c_char, mode: *const c_char) -> c_int;
+    pub fn lua_isyieldable(L: *mut lua_State) -> c_int;
+    pub fn lua_upvalueid(L: *mut lua_State, fidx: c_int, n: c_int) -> *mut c_void;
+    pub fn lua_upvaluejoin(L: *mut lua_State, fidx1: c_int, n1: c_int, fidx2: c_int, n2: c_int);
+
+    pub fn luaJIT_setmode(L: *mut lua_State, idx: c_int, mode: c_int) -> c_int;
+
+    pub fn luaopen_bit(L: *mut lua_State) -> c_int;
+    pub fn luaopen_jit(L: *mut lua_State) -> c_int;
+    pub fn luaopen_ffi(L: *mut lua_State) -> c_int;
+}
+
+#[cfg(feature = "lua51")]
+#[inline(always)]
+pub unsafe fn lua_copy(L: *mut lua_State, fromidx: c_int, toidx: c_int) {
+    lua_pushvalue(L, fromidx);
+    lua_replace(L, toidx);
+}
+
+#[cfg(feature = "lua51")]
+pub unsafe fn lua_tonumberx(L: *mut lua_State, idx: c_int, isnum: *mut c_int) -> lua_Number {
+    if !isnum.is_null() {
+        *isnum = lua_isnumber(L, idx);
+    }
+    lua_tonumber(L, idx)
+}
+
+#[cfg(feature = "lua51")]
+pub unsafe fn lua_tointegerx(L: *mut lua_State, idx: c_int, isnum: *mut c_int) -> lua_Integer {
+    if !isnum.is_null() {
+        *isnum = lua_isnumber(L, idx);
+    }
+    lua_tointeger(L, idx)
+}
+
+// Compat functions of lua 5.2
+
+#[inline(always)]
+pub unsafe fn lua_absindex(L: *mut lua_State, idx: c_int) -> c_int {
+    if idx > 0 || idx <= LUA_REGISTRYINDEX { idx } else { lua_gettop(L) + idx + 1 }
+}
+
+#[inline(always)]
+pub unsafe fn lua_rawlen(L: *mut lua_State, idx: c_int) -> size_t { lua_objlen(L, idx) }
+
+pub unsafe fn lua_compare(L: *mut lua_State, idx1: c_int, idx2: c_int, op: c_int) -> c_int {
+    match op {
+        LUA_OPEQ => lua_equal(L, idx1, idx2),
+        LUA_OPLT => lua_lessthan(L, idx1, idx2),
+        LUA_OPLE => (lua_lessthan(L, idx1, idx2) != 0 || lua_equal(L, idx1, idx2) != 0) as c_int,
+        _ => 0,
+    }
+}
+
+/// Like in lua 5.2 numbers are converted modulo 2^32.
+pub unsafe fn lua_tounsignedx(L: *mut lua_State, idx: c_int, isnum: *mut c_int) -> lua_Unsigned {
+    lua_tonumberx(L, idx, isnum) as i64 as lua_Unsigned
+}
+
+#[inline(always)]
+pub unsafe fn lua_pushunsigned(L: *mut lua_State, n: lua_Unsigned) { lua_pushnumber(L, n as lua_Number) }
+
+pub unsafe fn lua_rawgetp(L: *mut lua_State, idx: c_int, p: *const c_void) {
+    let idx = lua_absindex(L, idx);
+    lua_pushlightuserdata(L, p as *mut c_void);
+    lua_rawget(L, idx);
+}
+
+pub unsafe fn lua_rawsetp(L: *mut lua_State, idx: c_int, p: *const c_void) {
+    let idx = lua_absindex(L, idx);
+    lua_pushlightuserdata(L, p as *mut c_void);
+    lua_insert(L, -2);
+    lua_rawset(L, idx);
+}
+
+#[inline(always)]
+pub unsafe fn lua_pushglobaltable(L: *mut lua_State) { lua_pushvalue(L, LUA_GLOBALSINDEX) }
+#[inline(always)]
+pub unsafe fn lua_getglobal(L: *mut lua_State, name: *const c_char) { lua_getfield(L, LUA_GLOBALSINDEX, name) }
+#[inline(always)]
+pub unsafe fn lua_setglobal(L: *mut lua_State, name: *const c_char) { lua_setfield(L, LUA_GLOBALSINDEX, name) }
@@ -0,0 +1,158 @@
+//! The parts of the API specific to lua 5.3 and 5.4.
+
+use libc::{c_char, c_int, c_uchar, c_void, intptr_t, size_t};
+
+use super::*;
+
+pub const LUAI_MAXSTACK: c_int = 1000000;
+pub const LUAI_FIRSTPSEUDOIDX: c_int = -LUAI_MAXSTACK - 1000;
+pub const LUA_REGISTRYINDEX: c_int = LUAI_FIRSTPSEUDOIDX;
+pub fn lua_upvalueindex(i: c_int) -> c_int { LUA_REGISTRYINDEX - i }
+
+#[cfg(feature = "lua53")]
+pub const LUA_ERRGCMM: c_int = 5;
+#[cfg(feature = "lua53")]
+pub const LUA_ERRERR: c_int = 6;
+#[cfg(feature = "lua54")]
+pub const LUA_ERRERR: c_int = 5;
+
+pub const LUA_RIDX_MAINTHREAD: c_int = 1;
+pub const LUA_RIDX_GLOBALS: c_int = 2;
+
+pub type lua_Integer = i64;
+pub type lua_Unsigned = u64;
+pub type lua_KContext = intptr_t;
+pub type lua_KFunction = extern "C-unwind" fn(L: *mut lua_State, status: c_int, ctx: lua_KContext) -> c_int;
+
+pub const LUA_OPADD: c_int = 0;
+
+pub const LUA_HOOKTAILCALL: c_int = 4;
+
+#[repr(C)]
+pub struct lua_Debug {
+    pub event: c_int,
+    pub name: *const c_char,
+    pub namewhat: *const c_char,
+    pub what: *const c_char,
+    pub source: *const c_char,
+    #[cfg(feature = "lua54")]
+    pub srclen: size_t,
+    pub currentline: c_int,
+    pub linedefined: c_int,
+    pub lastlinedefined: c_int,
+    pub nups: c_uchar,
+    pub nparams: c_uchar,
+    pub isvararg: c_char,
+    pub istailcall: c_char,
+    #[cfg(feature = "lua54")]
+    pub ftransfer: ::libc::c_ushort,
+    #[cfg(feature = "lua54")]
+    pub ntransfer: ::libc::c_ushort,
+    pub short_src: [c_char; LUA_IDSIZE],
+    i_ci: *mut c_void,
+}
+
+extern "C-unwind" {
+    pub fn lua_absindex(L: *mut lua_State, idx: c_int) -> c_int;
+    pub fn lua_rotate(L: *mut lua_State, idx: c_int, n: c_int);
+    pub fn lua_copy(L: *mut lua_State, fromidx: c_int, toidx: c_int);
+
+    pub fn lua_isinteger(L: *mut lua_State, idx: c_int) -> c_int;
+    pub fn lua_tonumberx(L: *mut lua_State, idx: c_int, isnum: *mut c_int) -> lua_Number;
+    pub fn lua_tointegerx(L: *mut lua_State, idx: c_int, isnum: *mut c_int) -> lua_Integer;
+    pub fn lua_rawlen(L: *mut lua_State, idx: c_int) -> size_t;
+
+    pub fn lua_arith(L: *mut lua_State, op: c_int);
+    pub fn lua_compare(L: *mut lua_State, idx1: c_int, idx2: c_int, op: c_int) -> c_int;
+
+    pub fn lua_pushlstring(L: *mut lua_State, s: *const c_char, l: size_t) -> *const c_char;
+    pub fn lua_pushstring(L: *mut lua_State, s: *const c_char) -> *const c_char;
+
+    pub fn lua_getglobal(L: *mut lua_State, name: *const c_char) -> c_int;
+    pub fn lua_gettable(L: *mut lua_State, idx: c_int) -> c_int;
+    pub fn lua_getfield(L: *mut lua_State, idx: c_int, k: *const c_char) -> c_int;
+    pub fn lua_geti(L: *mut lua_State, idx: c_int, n: lua_Integer) -> c_int;
+    pub fn lua_rawget(L: *mut lua_State, idx: c_int) -> c_int;
+    pub fn lua_rawgeti(L: *mut lua_State, idx: c_int, n: lua_Integer) -> c_int;
+    pub fn lua_rawgetp(L: *mut lua_State, idx: c_int, p: *const c_void) -> c_int;
+    #[cfg(feature = "lua53")]
+    pub fn lua_newuserdata(L: *mut lua_State, sz: size_t) -> *mut c_void;
+    #[cfg(feature = "lua54")]
+    pub fn lua_newuserdatauv(L: *mut lua_State, sz: size_t, nuvalue: c_int) -> *mut c_void;
+    #[cfg(feature = "lua53")]
+    pub fn lua_getuservalue(L: *mut lua_State, idx: c_int) -> c_int;
+    #[cfg(feature = "lua54")]
+    pub fn lua_getiuservalue(L: *mut lua_State, idx: c_int, n: c_int) -> c_int;
+
+    pub fn lua_setglobal(L: *mut lua_State, name: *const c_char);
+    pub fn lua_seti(L: *mut lua_State, idx: c_int, n: lua_Integer);
+    pub fn lua_rawseti(L: *mut lua_State, idx: c_int, n: lua_Integer);
+    pub fn lua_rawsetp(L: *mut lua_State, idx: c_int, p: *const c_void);
+    #[cfg(feature = "lua53")]
+    pub fn lua_setuservalue(L: *mut lua_State, idx: c_int);
+    #[cfg(feature = "lua54")]
+    pub fn lua_setiuservalue(L: *mut lua_State, idx: c_int, n: c_int) -> c_int;
+
+    pub fn lua_callk(L: *mut lua_State, nargs: c_int, nresults: c_int, ctx: lua_KContext, k: Option<lua_KFunction>);
+    pub fn lua_pcallk(L: *mut lua_State, nargs: c_int, nresults: c_int, errfunc: c_int, ctx: lua_KContext, k: Option<lua_KFunction>) -> c_int;
+    pub fn lua_load(L: *mut lua_State, reader: lua_Reader, dt: *mut c_void, chunkname: *const c_char, mode: *const c_char) -> c_int;
+    pub fn lua_dump(L: *mut lua_State, writer: lua_Writer, data: *mut c_void, strip: c_int) -> c_int;
+
+    pub fn lua_yieldk(L: *mut lua_State, nresults: c_int, ctx: lua_KContext, k: Option<lua_KFunction>) -> c_int;
+    #[cfg(feature = "lua53")]
+    pub fn lua_resume(L: *mut lua_State, from: *mut lua_State, narg: c_int) -> c_int;
+    #[cfg(feature = "lua54")]
+    pub fn lua_resume(L: *mut lua_State, from: *mut lua_State, narg: c_int, nres: *mut c_int) -> c_int;
+    pub fn lua_isyieldable(L: *mut lua_State) -> c_int;
+
+    #[cfg(feature = "lua53")]
+    pub fn lua_gc(L: *mut lua_State, what: c_int, data: c_int) -> c_int;
+    #[cfg(feature = "lua54")]
+    pub fn lua_gc(L: *mut lua_State, what: c_int, ...) -> c_int;
+    pub fn lua_len(L: *mut lua_State, idx: c_int);
+    pub fn lua_stringtonumber(L: *mut lua_State, s: *const c_char) -> size_t;
+
+    #[cfg(feature = "lua54")]
+    pub fn lua_toclose(L: *mut lua_State, idx: c_int);
+    #[cfg(feature = "lua54")]
+    pub fn lua_closeslot(L: *mut lua_State, idx: c_int);
+    #[cfg(feature = "lua54")]
+    pub fn lua_warning(L: *mut lua_State, msg: *const c_char, tocont: c_int);
+
+    pub fn lua_upvalueid(L: *mut lua_State, fidx: c_int, n: c_int) -> *mut c_void;
+    pub fn lua_upvaluejoin(L: *mut lua_State, fidx1: c_int, n1: c_int, fidx2: c_int, n2: c_int);
+    pub fn lua_sethook(L: *mut lua_State, func: lua_Hook, mask: c_int, count: c_int);
+
+    pub fn luaopen_coroutine(L: *mut lua_State) -> c_int;
+    pub fn luaopen_utf8(L: *mut lua_State) -> c_int;
+}
+
+#[inline(always)]
+pub unsafe fn lua_call(L: *mut lua_State, nargs: c_int, nresults: c_int) { lua_callk(L, nargs, nresults, 0, None) }
+#[inline(always)]
+pub unsafe fn lua_pcall(L: *mut lua_State, nargs: c_int, nresults: c_int, errfunc: c_int) -> c_int { lua_pcallk(L, nargs, nresults, errfunc, 0, None) }
+#[inline(always)]
+pub unsafe fn lua_yield(L: *mut lua_State, nresults: c_int) -> c_int { lua_yieldk(L, nresults, 0, None) }
+#[inline(always)]
+pub unsafe fn lua_pushglobaltable(L: *mut lua_State) { lua_rawgeti(L, LUA_REGISTRYINDEX, LUA_RIDX_GLOBALS as lua_Integer); }
+#[inline(always)]
+pub unsafe fn lua_insert(L: *mut lua_State, idx: c_int) { lua_rotate(L, idx, 1) }
+#[inline(always)]
+pub unsafe fn lua_remove(L: *mut lua_State, idx: c_int) { lua_rotate(L, idx, -1); lua_pop(L, 1) }
+#[inline(always)]
+pub unsafe fn lua_replace(L: *mut lua_State, idx: c_int) { lua_copy(L, -1, idx); lua_pop(L, 1) }
+#[cfg(feature = "lua54")]
+#[inline(always)]
+pub unsafe fn lua_newuserdata(L: *mut lua_State, sz: size_t) -> *mut c_void { lua_newuserdatauv(L, sz, 1) }
+#[cfg(feature = "lua54")]
+#[inline(always)]
+pub unsafe fn lua_getuservalue(L: *mut lua_State, idx: c_int) -> c_int { lua_getiuservalue(L, idx, 1) }
+#[cfg(feature = "lua54")]
+#[inline(always)]
+pub unsafe fn lua_setuservalue(L: *mut lua_State, idx: c_int) { lua_setiuservalue(L, idx, 1); }
+
+// Compat functions of lua 5.2 that are macros in lua 5.3 and gone in lua 5.4
+#[inline(always)]
+pub unsafe fn lua_tounsignedx(L: *mut lua_State, idx: c_int, isnum: *mut c_int) -> lua_Unsigned { lua_tointegerx(L, idx, isnum) as lua_Unsigned }
+#[inline(always)]
+pub unsafe fn lua_pushunsigned(L: *mut lua_State, n: lua_Unsigned) { lua_pushinteger(L, n as lua_Integer) }
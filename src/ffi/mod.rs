@@ -0,0 +1,199 @@
+//! The lua C API of the version selected with one of the `lua51`, `lua52`, `lua53`, `lua54`
+//! and `luajit` features.
+//!
+//! Every version exposes its own functions with their real signatures. Functions of lua 5.2
+//! missing in a version are emulated, e.g. `lua_rawgetp` or `lua_tounsignedx` on lua 5.1,
+//! so most code can be written against the API of lua 5.2.
+
+// The bindings keep the names of the C API, whose manual documents their safety
+#![allow(non_camel_case_types, non_snake_case, clippy::missing_safety_doc)]
+
+#[cfg(not(any(feature = "lua51", feature = "lua52", feature = "lua53", feature = "lua54", feature = "luajit")))]
+compile_error!("select a lua version with one of the features lua51, lua52, lua53, lua54 or luajit");
+
+#[cfg(any(all(feature = "lua51", any(feature = "lua52", feature = "lua53", feature = "lua54", feature = "luajit")),
+          all(feature = "lua52", any(feature = "lua53", feature = "lua54", feature = "luajit")),
+          all(feature = "lua53", any(feature = "lua54", feature = "luajit")),
+          all(feature = "lua54", feature = "luajit")))]
+compile_error!("the features lua51, lua52, lua53, lua54 and luajit are mutually exclusive");
+
+use libc::{c_char, c_double, c_int, c_void, size_t};
+use std::ptr;
+
+#[cfg(any(feature = "lua51", feature = "luajit"))]
+mod lua51;
+#[cfg(any(feature = "lua51", feature = "luajit"))]
+pub use self::lua51::*;
+#[cfg(feature = "lua52")]
+mod lua52;
+#[cfg(feature = "lua52")]
+pub use self::lua52::*;
+#[cfg(any(feature = "lua53", feature = "lua54"))]
+mod lua53;
+#[cfg(any(feature = "lua53", feature = "lua54"))]
+pub use self::lua53::*;
+
+/// The `_VERSION` of the selected lua version.
+#[cfg(any(feature = "lua51", feature = "luajit"))]
+pub const LUA_VERSION: &'static str = "Lua 5.1";
+#[cfg(feature = "lua52")]
+pub const LUA_VERSION: &'static str = "Lua 5.2";
+#[cfg(feature = "lua53")]
+pub const LUA_VERSION: &'static str = "Lua 5.3";
+#[cfg(feature = "lua54")]
+pub const LUA_VERSION: &'static str = "Lua 5.4";
+
+pub const MULTRET: c_int = -1;
+
+pub const LUA_OK: c_int = 0;
+pub const LUA_YIELD: c_int = 1;
+pub const LUA_ERRRUN: c_int = 2;
+pub const LUA_ERRSYNTAX: c_int = 3;
+pub const LUA_ERRMEM: c_int = 4;
+
+pub enum lua_State {}
+
+pub type lua_Number = c_double;
+
+pub type lua_CFunction = extern "C-unwind" fn(L: *mut lua_State) -> c_int;
+pub type lua_Reader = extern "C" fn(L: *mut lua_State, ud: *mut c_void, sz: *mut size_t) -> *const c_char;
+pub type lua_Writer = extern "C" fn(L: *mut lua_State, p: *const c_void, sz: size_t, ud: *mut c_void) -> c_int;
+pub type lua_Alloc = extern "C" fn(ud: *mut c_void, ptr: *mut c_void, osize: size_t, nsize: size_t) -> *mut c_void;
+pub type lua_Hook = extern "C-unwind" fn(L: *mut lua_State, ar: *mut lua_Debug);
+
+pub const LUA_TNONE: c_int = -1;
+pub const LUA_TNIL: c_int = 0;
+pub const LUA_TBOOLEAN: c_int = 1;
+pub const LUA_TLIGHTUSERDATA: c_int = 2;
+pub const LUA_TNUMBER: c_int = 3;
+pub const LUA_TSTRING: c_int = 4;
+pub const LUA_TTABLE: c_int = 5;
+pub const LUA_TFUNCTION: c_int = 6;
+pub const LUA_TUSERDATA: c_int = 7;
+pub const LUA_TTHREAD: c_int = 8;
+
+pub const LUA_MINSTACK: c_int = 20;
+
+pub const LUA_OPEQ: c_int = 0;
+pub const LUA_OPLT: c_int = 1;
+pub const LUA_OPLE: c_int = 2;
+
+pub const LUA_GCSTOP: c_int = 0;
+pub const LUA_GCRESTART: c_int = 1;
+pub const LUA_GCCOLLECT: c_int = 2;
+pub const LUA_GCCOUNT: c_int = 3;
+pub const LUA_GCCOUNTB: c_int = 4;
+
+pub const LUA_HOOKCALL: c_int = 0;
+pub const LUA_HOOKRET: c_int = 1;
+pub const LUA_HOOKLINE: c_int = 2;
+pub const LUA_HOOKCOUNT: c_int = 3;
+pub const LUA_MASKCALL: c_int = 1 << LUA_HOOKCALL;
+pub const LUA_MASKRET: c_int = 1 << LUA_HOOKRET;
+pub const LUA_MASKLINE: c_int = 1 << LUA_HOOKLINE;
+pub const LUA_MASKCOUNT: c_int = 1 << LUA_HOOKCOUNT;
+
+pub const LUA_IDSIZE: usize = 60;
+
+// The functions with the same signature in all versions.
+extern "C-unwind" {
+    pub fn lua_newstate(f: lua_Alloc, ud: *mut c_void) -> *mut lua_State;
+    pub fn lua_close(L: *mut lua_State);
+    pub fn lua_newthread(L: *mut lua_State) -> *mut lua_State;
+    pub fn lua_atpanic(L: *mut lua_State, panicf: lua_CFunction) -> Option<lua_CFunction>;
+
+    pub fn lua_gettop(L: *mut lua_State) -> c_int;
+    pub fn lua_settop(L: *mut lua_State, idx: c_int);
+    pub fn lua_pushvalue(L: *mut lua_State, idx: c_int);
+    pub fn lua_checkstack(L: *mut lua_State, sz: c_int) -> c_int;
+    pub fn lua_xmove(from: *mut lua_State, to: *mut lua_State, n: c_int);
+
+    pub fn lua_isnumber(L: *mut lua_State, idx: c_int) -> c_int;
+    pub fn lua_isstring(L: *mut lua_State, idx: c_int) -> c_int;
+    pub fn lua_iscfunction(L: *mut lua_State, idx: c_int) -> c_int;
+    pub fn lua_isuserdata(L: *mut lua_State, idx: c_int) -> c_int;
+    pub fn lua_type(L: *mut lua_State, idx: c_int) -> c_int;
+    pub fn lua_typename(L: *mut lua_State, tp: c_int) -> *const c_char;
+
+    pub fn lua_toboolean(L: *mut lua_State, idx: c_int) -> c_int;
+    pub fn lua_tolstring(L: *mut lua_State, idx: c_int, len: *mut size_t) -> *const c_char;
+    pub fn lua_tocfunction(L: *mut lua_State, idx: c_int) -> Option<lua_CFunction>;
+    pub fn lua_touserdata(L: *mut lua_State, idx: c_int) -> *mut c_void;
+    pub fn lua_tothread(L: *mut lua_State, idx: c_int) -> *mut lua_State;
+    pub fn lua_topointer(L: *mut lua_State, idx: c_int) -> *const c_void;
+
+    pub fn lua_rawequal(L: *mut lua_State, idx1: c_int, idx2: c_int) -> c_int;
+
+    pub fn lua_pushnil(L: *mut lua_State);
+    pub fn lua_pushnumber(L: *mut lua_State, n: lua_Number);
+    pub fn lua_pushinteger(L: *mut lua_State, n: lua_Integer);
+    pub fn lua_pushfstring(L: *mut lua_State, fmt: *const c_char, ...) -> *const c_char;
+    pub fn lua_pushcclosure(L: *mut lua_State, f: lua_CFunction, n: c_int);
+    pub fn lua_pushboolean(L: *mut lua_State, b: c_int);
+    pub fn lua_pushlightuserdata(L: *mut lua_State, p: *mut c_void);
+    pub fn lua_pushthread(L: *mut lua_State) -> c_int;
+
+    pub fn lua_createtable(L: *mut lua_State, narr: c_int, nrec: c_int);
+    pub fn lua_getmetatable(L: *mut lua_State, objindex: c_int) -> c_int;
+
+    pub fn lua_settable(L: *mut lua_State, idx: c_int);
+    pub fn lua_setfield(L: *mut lua_State, idx: c_int, k: *const c_char);
+    pub fn lua_rawset(L: *mut lua_State, idx: c_int);
+    pub fn lua_setmetatable(L: *mut lua_State, objindex: c_int) -> c_int;
+
+    pub fn lua_status(L: *mut lua_State) -> c_int;
+
+    pub fn lua_error(L: *mut lua_State) -> c_int;
+    pub fn lua_next(L: *mut lua_State, idx: c_int) -> c_int;
+    pub fn lua_concat(L: *mut lua_State, n: c_int);
+
+    pub fn lua_getstack(L: *mut lua_State, level: c_int, ar: *mut lua_Debug) -> c_int;
+    pub fn lua_getinfo(L: *mut lua_State, what: *const c_char, ar: *mut lua_Debug) -> c_int;
+    pub fn lua_getlocal(L: *mut lua_State, ar: *const lua_Debug, n: c_int) -> *const c_char;
+    pub fn lua_setlocal(L: *mut lua_State, ar: *const lua_Debug, n: c_int) -> *const c_char;
+    pub fn lua_getupvalue(L: *mut lua_State, funcindex: c_int, n: c_int) -> *const c_char;
+    pub fn lua_setupvalue(L: *mut lua_State, funcindex: c_int, n: c_int) -> *const c_char;
+    pub fn lua_gethookmask(L: *mut lua_State) -> c_int;
+    pub fn lua_gethookcount(L: *mut lua_State) -> c_int;
+
+    pub fn luaopen_base(L: *mut lua_State) -> c_int;
+    pub fn luaopen_table(L: *mut lua_State) -> c_int;
+    pub fn luaopen_io(L: *mut lua_State) -> c_int;
+    pub fn luaopen_os(L: *mut lua_State) -> c_int;
+    pub fn luaopen_string(L: *mut lua_State) -> c_int;
+    pub fn luaopen_math(L: *mut lua_State) -> c_int;
+    pub fn luaopen_debug(L: *mut lua_State) -> c_int;
+    pub fn luaopen_package(L: *mut lua_State) -> c_int;
+    pub fn luaL_openlibs(L: *mut lua_State);
+}
+
+#[inline(always)]
+pub unsafe fn lua_tonumber(L: *mut lua_State, i: c_int) -> lua_Number { lua_tonumberx(L, i, ptr::null_mut()) }
+#[inline(always)]
+pub unsafe fn lua_tointeger(L: *mut lua_State, i: c_int) -> lua_Integer { lua_tointegerx(L, i, ptr::null_mut()) }
+#[inline(always)]
+pub unsafe fn lua_tounsigned(L: *mut lua_State, i: c_int) -> lua_Unsigned { lua_tounsignedx(L, i, ptr::null_mut()) }
+#[inline(always)]
+pub unsafe fn lua_pop(L: *mut lua_State, n: c_int) { lua_settop(L, -n - 1) }
+#[inline(always)]
+pub unsafe fn lua_newtable(L: *mut lua_State) { lua_createtable(L, 0, 0) }
+#[inline(always)]
+pub unsafe fn lua_pushcfunction(L: *mut lua_State, f: lua_CFunction) { lua_pushcclosure(L, f, 0) }
+#[inline(always)]
+pub unsafe fn lua_isfunction(L: *mut lua_State, n: c_int) -> bool { lua_type(L, n) == LUA_TFUNCTION }
+#[inline(always)]
+pub unsafe fn lua_istable(L: *mut lua_State, n: c_int) -> bool { lua_type(L, n) == LUA_TTABLE }
+#[inline(always)]
+pub unsafe fn lua_islightuserdata(L: *mut lua_State, n: c_int) -> bool { lua_type(L, n) == LUA_TLIGHTUSERDATA }
+#[inline(always)]
+pub unsafe fn lua_isnil(L: *mut lua_State, n: c_int) -> bool { lua_type(L, n) == LUA_TNIL }
+#[inline(always)]
+pub unsafe fn lua_isboolean(L: *mut lua_State, n: c_int) -> bool { lua_type(L, n) == LUA_TBOOLEAN }
+#[inline(always)]
+pub unsafe fn lua_isthread(L: *mut lua_State, n: c_int) -> bool { lua_type(L, n) == LUA_TTHREAD }
+#[inline(always)]
+pub unsafe fn lua_isnone(L: *mut lua_State, n: c_int) -> bool { lua_type(L, n) == LUA_TNONE }
+#[inline(always)]
+pub unsafe fn lua_isnoneornil(L: *mut lua_State, n: c_int) -> bool { lua_type(L, n) <= 0 }
+#[inline(always)]
+pub unsafe fn lua_tostring(L: *mut lua_State, i: c_int) -> *const c_char { lua_tolstring(L, i, ptr::null_mut()) }
@@ -0,0 +1,126 @@
+use std::cell::RefCell;
+use std::fmt;
+
+use ffi;
+use libc;
+use LuaContext;
+
+/// Tables nested deeper than this are abbreviated as `{...}`.
+const MAX_DEPTH: usize = 8;
+
+/// Formats the table at the given absolute stack index.
+///
+/// Only raw accesses are used, so no metamethods are invoked while formatting.
+pub struct TableFmt<'a> {
+    context: &'a LuaContext,
+    index: libc::c_int,
+    path: &'a RefCell<Vec<*const libc::c_void>>,
+}
+
+impl<'a> TableFmt<'a> {
+    pub fn new(context: &'a LuaContext,index: libc::c_int,path: &'a RefCell<Vec<*const libc::c_void>>) -> Self {
+        TableFmt { context: context, index: index, path: path }
+    }
+}
+
+impl<'a> fmt::Debug for TableFmt<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let l = self.context.l;
+        let ptr = unsafe { ffi::lua_topointer(l,self.index) };
+
+        if self.path.borrow().contains(&ptr) {
+            return f.write_str("<cycle>");
+        }
+        if self.path.borrow().len() >= MAX_DEPTH {
+            return f.write_str("{...}");
+        }
+
+        self.path.borrow_mut().push(ptr);
+        let mut map = f.debug_map();
+        unsafe {
+            ffi::lua_checkstack(l,3);
+
+            // Array part first, in order
+            let len = ffi::lua_rawlen(l,self.index) as libc::c_int;
+            for i in 1..len + 1 {
+                ffi::lua_rawgeti(l,self.index,i as _);
+                map.entry(&i,&ValueFmt::new(self.context,ffi::lua_gettop(l),self.path));
+                ffi::lua_pop(l,1);
+            }
+
+            // Remaining pairs
+            ffi::lua_pushnil(l);
+            while ffi::lua_next(l,self.index) != 0 {
+                let top = ffi::lua_gettop(l);
+                if !is_array_key(l,top - 1,len) {
+                    map.entry(&ValueFmt::new(self.context,top - 1,self.path),
+                              &ValueFmt::new(self.context,top,self.path));
+                }
+                ffi::lua_pop(l,1);
+            }
+        }
+        self.path.borrow_mut().pop();
+
+        map.finish()
+    }
+}
+
+/// Formats any value at the given absolute stack index.
+struct ValueFmt<'a> {
+    context: &'a LuaContext,
+    index: libc::c_int,
+    path: &'a RefCell<Vec<*const libc::c_void>>,
+}
+
+impl<'a> ValueFmt<'a> {
+    fn new(context: &'a LuaContext,index: libc::c_int,path: &'a RefCell<Vec<*const libc::c_void>>) -> Self {
+        ValueFmt { context: context, index: index, path: path }
+    }
+}
+
+impl<'a> fmt::Debug for ValueFmt<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let l = self.context.l;
+        unsafe {
+            match ffi::lua_type(l,self.index) {
+                ffi::LUA_TNIL     => f.write_str("nil"),
+                ffi::LUA_TBOOLEAN => fmt::Debug::fmt(&(ffi::lua_toboolean(l,self.index) != 0),f),
+                #[cfg(any(feature = "lua53", feature = "lua54"))]
+                ffi::LUA_TNUMBER if ffi::lua_isinteger(l,self.index) != 0 => write!(f,"{}",ffi::lua_tointegerx(l,self.index,std::ptr::null_mut())),
+                ffi::LUA_TNUMBER  => fmt_number(ffi::lua_tonumberx(l,self.index,std::ptr::null_mut()),f),
+                ffi::LUA_TSTRING  => {
+                    // Only called on actual strings, so lua_next is not confused by a conversion
+                    let mut len = 0;
+                    let ptr = ffi::lua_tolstring(l,self.index,&mut len);
+                    let bytes = std::slice::from_raw_parts(ptr as *const u8,len);
+                    fmt::Debug::fmt(&String::from_utf8_lossy(bytes),f)
+                },
+                ffi::LUA_TTABLE   => TableFmt::new(self.context,self.index,self.path).fmt(f),
+                tp => {
+                    let name = std::ffi::CStr::from_ptr(ffi::lua_typename(l,tp));
+                    write!(f,"{}: {:p}",name.to_string_lossy(),ffi::lua_topointer(l,self.index))
+                }
+            }
+        }
+    }
+}
+
+/// Writes integral numbers without a fractional part, like lua does before 5.3.
+///
+/// Since lua 5.3 only integers are written that way, floats keep their `.0`.
+fn fmt_number(n: f64, f: &mut fmt::Formatter) -> fmt::Result {
+    if cfg!(not(any(feature = "lua53", feature = "lua54"))) && n.fract() == 0.0 && n.abs() < 9007199254740992.0 {
+        write!(f,"{}",n as i64)
+    } else {
+        fmt::Debug::fmt(&n,f)
+    }
+}
+
+/// Checks if the key at `index` was already printed as part of the array part.
+unsafe fn is_array_key(l: *mut ffi::lua_State,index: libc::c_int,len: libc::c_int) -> bool {
+    if ffi::lua_type(l,index) != ffi::LUA_TNUMBER {
+        return false;
+    }
+    let n = ffi::lua_tonumberx(l,index,std::ptr::null_mut());
+    n.fract() == 0.0 && n >= 1.0 && n <= len as f64
+}
@@ -0,0 +1,562 @@
+use std::any::Any;
+use std::error::Error;
+use std::fmt;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::rc::Rc;
+
+#[cfg(any(feature = "lua51", feature = "lua52"))]
+use bytecode;
+use error::{self, LuaError};
+use ffi;
+use lauxlib;
+use libc;
+use profile;
+use protect;
+use stack;
+use {LuaContext, LuaRead, LuaRef, LuaValue, LuaWrite, State, Table};
+
+/// The signature of rust functions callable from lua.
+///
+/// The function receives the calling `State` and all arguments and returns the results.
+pub type Callback = dyn Fn(&State,Vec<LuaValue>) -> Result<Vec<LuaValue>,Box<dyn Error>>;
+
+// The addresses are the registry keys of the metatables of the userdata created by yuna
+static CALLBACK_METATABLE: u8 = 0;
+static PANIC_METATABLE: u8 = 0;
+static ERROR_METATABLE: u8 = 0;
+
+/// Holds a reference to a lua function.
+pub struct Function {
+    context: LuaContext,
+    refindex: libc::c_int,
+}
+
+impl Function {
+    /// Calls the function in protected mode and returns all results.
+    ///
+    /// If a rust callback panicked during the call the panic is resumed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use yuna::LuaIndex;
+    ///
+    /// let mut state = yuna::State::new();
+    /// state.do_string("function add(a,b) return a + b end").unwrap();
+    ///
+    /// let add : yuna::Function = state.read("add").unwrap();
+    /// let sum = add.call(vec![yuna::LuaValue::from_number(1),yuna::LuaValue::from_number(2)]).unwrap();
+    /// assert_eq!(sum,vec![yuna::LuaValue::from_number(3)]);
+    /// ```
+    pub fn call(&self,args: Vec<LuaValue>) -> Result<Vec<LuaValue>,LuaError> {
+        let l = self.context.l;
+        unsafe {
+            let base = ffi::lua_gettop(l);
+            ffi::lua_checkstack(l,args.len() as libc::c_int + 1);
+            self.write_self();
+            let nargs = args.len() as libc::c_int;
+            let pushed = protect::run(&self.context,0,ffi::MULTRET,|_| {
+                for arg in &args {
+                    LuaWrite::lua_write(&self.context,arg);
+                }
+                nargs
+            });
+            if let Err(err) = pushed {
+                ffi::lua_settop(l,base);
+                return Err(err);
+            }
+            drop(args);
+            error::pcall(&self.context,nargs,ffi::MULTRET)?;
+
+            let top = ffi::lua_gettop(l);
+            let mut results = Vec::with_capacity((top - base) as usize);
+            for i in base + 1..top + 1 {
+                match LuaRead::lua_read_index(&self.context,i) {
+                    Ok(result) => results.push(result),
+                    Err(()) => {
+                        ffi::lua_settop(l,base);
+                        return Err(LuaError::Memory);
+                    },
+                }
+            }
+            ffi::lua_settop(l,base);
+            Ok(results)
+        }
+    }
+
+    /// Returns the names and values of the upvalues of the function.
+    ///
+    /// Upvalues of C functions and rust callbacks have empty names.
+    pub fn upvalues(&self) -> Vec<(String,LuaValue)> {
+        unsafe {
+            self.write_self();
+            let upvalues = stack::upvalues(&self.context);
+            ffi::lua_pop(self.context.l,1);
+            upvalues
+        }
+    }
+
+    /// Returns the precompiled chunk of a lua function, as written by `luac`.
+    ///
+    /// With `strip` the debug information like line numbers and local names is left out.
+    /// Returns `None` for functions written in C or rust.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut state = yuna::State::new();
+    /// let f = state.load(b"return 6 * 7","=answer").unwrap();
+    /// let bytecode = f.dump(true).unwrap();
+    ///
+    /// let loaded = state.load(&bytecode,"=answer").unwrap();
+    /// assert_eq!(loaded.call(vec![]).unwrap(),vec![yuna::LuaValue::from_number(42)]);
+    /// ```
+    pub fn dump(&self,strip: bool) -> Option<Vec<u8>> {
+        unsafe {
+            self.write_self();
+            let chunk = dump_top(self.context.l,strip);
+            ffi::lua_pop(self.context.l,1);
+            chunk
+        }
+    }
+
+    /// Sets the table the function looks up globals in.
+    ///
+    /// This is the `_ENV` upvalue since lua 5.2 and the function environment in lua 5.1.
+    /// Returns `false` if the function has no `_ENV` upvalue as it uses no globals.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use yuna::LuaIndex;
+    ///
+    /// let mut state = yuna::State::new();
+    /// let f = state.load(b"return answer","=env").unwrap();
+    ///
+    /// let mut env = state.create_table();
+    /// env.set("answer",42);
+    /// assert!(f.set_env(&env));
+    /// assert_eq!(f.call(vec![]).unwrap(),vec![yuna::LuaValue::from_number(42)]);
+    /// ```
+    pub fn set_env(&self,env: &Table) -> bool {
+        let l = self.context.l;
+        unsafe {
+            self.write_self();
+            env.write_self();
+            let set = set_env_top(l);
+            ffi::lua_pop(l,1);
+            set
+        }
+    }
+}
+
+/// Pops the table on top of the stack and sets it as environment of the function below.
+#[cfg(any(feature = "lua51", feature = "luajit"))]
+unsafe fn set_env_top(l: *mut ffi::lua_State) -> bool {
+    ffi::lua_setfenv(l,-2) != 0
+}
+
+/// Pops the table on top of the stack and sets it as environment of the function below.
+///
+/// Stripped chunks have no upvalue names, their only upvalue is `_ENV`.
+#[cfg(not(any(feature = "lua51", feature = "luajit")))]
+unsafe fn set_env_top(l: *mut ffi::lua_State) -> bool {
+    use std::ffi::CStr;
+
+    let mut n = 1;
+    loop {
+        let name = ffi::lua_getupvalue(l,-2,n);
+        if name.is_null() {
+            break;
+        }
+        ffi::lua_pop(l,1);
+        if CStr::from_ptr(name).to_bytes() == b"_ENV" {
+            ffi::lua_setupvalue(l,-2,n);
+            return true;
+        }
+        n += 1;
+    }
+    let mut ar: ffi::lua_Debug = mem::zeroed();
+    ffi::lua_pushvalue(l,-2);
+    ffi::lua_getinfo(l,b">S\0".as_ptr() as *const libc::c_char,&mut ar);
+    if n == 2 && CStr::from_ptr(ar.what).to_bytes() == b"main" {
+        ffi::lua_setupvalue(l,-2,1);
+        return true;
+    }
+    ffi::lua_pop(l,1);
+    false
+}
+
+/// Dumps the function on top of the stack, `None` for C functions.
+#[cfg(any(feature = "lua53", feature = "lua54"))]
+pub unsafe fn dump_top(l: *mut ffi::lua_State,strip: bool) -> Option<Vec<u8>> {
+    let mut chunk = Vec::new();
+    match ffi::lua_dump(l,write_chunk,&mut chunk as *mut Vec<u8> as *mut libc::c_void,strip as libc::c_int) {
+        0 => Some(chunk),
+        _ => None,
+    }
+}
+
+/// Dumps the function on top of the stack, `None` for C functions.
+///
+/// `lua_dump` always includes debug information before lua 5.3, it is stripped afterwards.
+#[cfg(not(any(feature = "lua53", feature = "lua54")))]
+pub unsafe fn dump_top(l: *mut ffi::lua_State,strip: bool) -> Option<Vec<u8>> {
+    let mut chunk = Vec::new();
+    match ffi::lua_dump(l,write_chunk,&mut chunk as *mut Vec<u8> as *mut libc::c_void) {
+        0 if strip => Some(strip_chunk(&chunk).expect("lua_dump wrote an invalid chunk")),
+        0 => Some(chunk),
+        _ => None,
+    }
+}
+
+#[cfg(any(feature = "lua51", feature = "lua52"))]
+fn strip_chunk(chunk: &[u8]) -> Option<Vec<u8>> {
+    bytecode::strip(chunk)
+}
+
+/// LuaJIT only strips in `string.dump`, which is called in a separate state.
+#[cfg(feature = "luajit")]
+fn strip_chunk(chunk: &[u8]) -> Option<Vec<u8>> {
+    unsafe {
+        let l = lauxlib::luaL_newstate();
+        if l.is_null() {
+            return None;
+        }
+        let mut stripped = None;
+        if lauxlib::luaL_loadbuffer(l,chunk.as_ptr() as *const libc::c_char,chunk.len(),b"=strip\0".as_ptr() as *const libc::c_char) == ffi::LUA_OK {
+            ffi::luaL_openlibs(l);
+            ffi::lua_getglobal(l,b"string\0".as_ptr() as *const libc::c_char);
+            ffi::lua_getfield(l,-1,b"dump\0".as_ptr() as *const libc::c_char);
+            ffi::lua_pushvalue(l,-3);
+            ffi::lua_pushboolean(l,1);
+            if ffi::lua_pcall(l,2,1,0) == ffi::LUA_OK {
+                let mut len = 0;
+                let p = ffi::lua_tolstring(l,-1,&mut len);
+                stripped = Some(::std::slice::from_raw_parts(p as *const u8,len).to_vec());
+            }
+        }
+        ffi::lua_close(l);
+        stripped
+    }
+}
+
+/// The `lua_Writer` used by `Function::dump`, appends to a `Vec<u8>`.
+extern "C" fn write_chunk(_: *mut ffi::lua_State,p: *const libc::c_void,sz: libc::size_t,ud: *mut libc::c_void) -> libc::c_int {
+    unsafe {
+        let chunk = &mut *(ud as *mut Vec<u8>);
+        chunk.extend_from_slice(::std::slice::from_raw_parts(p as *const u8,sz));
+    }
+    0
+}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ptr = unsafe {
+            self.write_self();
+            let ptr = ffi::lua_topointer(self.context.l,-1);
+            ffi::lua_pop(self.context.l,1);
+            ptr
+        };
+        write!(f,"function: {:p}",ptr)
+    }
+}
+
+impl_lua_ref!(Function);
+
+impl PartialEq for Function {
+    fn eq(&self, other: &Function) -> bool {
+        unsafe {
+            self.write_self();
+            other.write_self();
+            let eq = ffi::lua_rawequal(self.context.l,-2,-1) != 0;
+            ffi::lua_pop(self.context.l,2);
+            eq
+        }
+    }
+}
+
+/// Returns true if the function is written in lua.
+pub fn is_lua(f: &Function) -> bool {
+    unsafe {
+        f.write_self();
+        let lua = ffi::lua_iscfunction(f.context.l,-1) == 0;
+        ffi::lua_pop(f.context.l,1);
+        lua
+    }
+}
+
+/// Creates a lua function calling the given rust function.
+///
+/// # Panics
+///
+/// Panics if lua runs out of memory.
+pub fn create(context: &LuaContext,f: Box<Callback>) -> Function {
+    unsafe {
+        let mut f = Some(Rc::<Callback>::from(f));
+        let mut refindex = 0;
+        let created = protect::run(context,0,0,|l| {
+            push_userdata(l,&mut f,&CALLBACK_METATABLE,&[]);
+            ffi::lua_pushcclosure(l,trampoline,1);
+            refindex = lauxlib::luaL_ref(l,ffi::LUA_REGISTRYINDEX);
+            0
+        });
+        if created.is_err() {
+            panic!("not enough memory");
+        }
+        Function::from_refindex(context,refindex)
+    }
+}
+
+/// Pushes a userdata with the metatable registered at `key` and moves the value out of
+/// `value` into it.
+///
+/// The userdata holds an `Option<T>`, which `__gc` takes, so the value is dropped once
+/// even if a script calls the metamethod itself.
+/// The metatable is created with a `__gc` metamethod and the given ones on first use.
+/// Both are allocated before the value is moved, so if lua runs out of memory it is still
+/// owned by the caller.
+unsafe fn push_userdata<T>(l: *mut ffi::lua_State,value: &mut Option<T>,key: &u8,methods: &[(&[u8],ffi::lua_CFunction)]) {
+    let key = key as *const u8 as *const libc::c_void;
+    ffi::lua_rawgetp(l,ffi::LUA_REGISTRYINDEX,key);
+    if ffi::lua_isnil(l,-1) {
+        ffi::lua_pop(l,1);
+        ffi::lua_newtable(l);
+        // The metatable is the upvalue of its metamethods, which check their argument with it
+        for &(method,f) in [(&b"__gc\0"[..],gc::<T> as ffi::lua_CFunction)].iter().chain(methods) {
+            ffi::lua_pushvalue(l,-1);
+            ffi::lua_pushcclosure(l,f,1);
+            ffi::lua_setfield(l,-2,method.as_ptr() as *const libc::c_char);
+        }
+        // Scripts can't get at the metamethods with `getmetatable`
+        ffi::lua_pushboolean(l,0);
+        ffi::lua_setfield(l,-2,b"__metatable\0".as_ptr() as *const libc::c_char);
+        // Only registered once complete, an error above leaves no half initialized metatable
+        ffi::lua_pushvalue(l,-1);
+        ffi::lua_rawsetp(l,ffi::LUA_REGISTRYINDEX,key);
+    }
+    let ud = ffi::lua_newuserdata(l,mem::size_of::<Option<T>>()) as *mut Option<T>;
+    ptr::write(ud,value.take());
+    ffi::lua_insert(l,-2);
+    ffi::lua_setmetatable(l,-2);
+}
+
+/// Returns the value of the userdata at `index` if its metatable is the table at `metatable`.
+///
+/// Only compares raw values, so it never raises an error.
+unsafe fn userdata_at<T>(l: *mut ffi::lua_State,index: libc::c_int,metatable: libc::c_int) -> Option<*mut Option<T>> {
+    let metatable = ffi::lua_absindex(l,metatable);
+    if ffi::lua_type(l,index) != ffi::LUA_TUSERDATA || ffi::lua_getmetatable(l,index) == 0 {
+        return None;
+    }
+    let same = ffi::lua_rawequal(l,-1,metatable) != 0;
+    ffi::lua_pop(l,1);
+    if same {
+        Some(ffi::lua_touserdata(l,index) as *mut Option<T>)
+    } else {
+        None
+    }
+}
+
+/// Returns the value of the userdata at `index` if it was created with the metatable at `key`.
+unsafe fn test_userdata<T>(l: *mut ffi::lua_State,index: libc::c_int,key: &u8) -> Option<*mut Option<T>> {
+    let index = ffi::lua_absindex(l,index);
+    ffi::lua_rawgetp(l,ffi::LUA_REGISTRYINDEX,key as *const u8 as *const libc::c_void);
+    let ud = userdata_at(l,index,-1);
+    ffi::lua_pop(l,1);
+    ud
+}
+
+/// Returns the value of the first argument of a metamethod, whose upvalue is the metatable.
+///
+/// Scripts can call metamethods with anything through `debug.getmetatable`, other values
+/// raise an error.
+unsafe fn check_userdata<T>(l: *mut ffi::lua_State) -> *mut Option<T> {
+    match userdata_at(l,1,ffi::lua_upvalueindex(1)) {
+        Some(ud) => ud,
+        None => {
+            lauxlib::luaL_argerror(l,1,b"value created by yuna expected\0".as_ptr() as *const libc::c_char);
+            unreachable!()
+        },
+    }
+}
+
+/// Drops the rust value stored in the userdata.
+extern "C-unwind" fn gc<T>(l: *mut ffi::lua_State) -> libc::c_int {
+    unsafe {
+        let ud = check_userdata::<T>(l);
+        // A panic while dropping must not unwind into lua
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop((*ud).take())));
+    }
+    0
+}
+
+/// Entry point of all rust callbacks.
+///
+/// Panics are caught and raised as a lua error holding the panic payload, which is resumed
+/// once the error reaches rust again. Nothing with a destructor may be alive when
+/// `lua_error` is called, as it never returns.
+extern "C-unwind" fn trampoline(l: *mut ffi::lua_State) -> libc::c_int {
+    unsafe {
+        // Finalizers run while the state is closed can't reach rust anymore
+        if ::handle_ptr(l).is_null() {
+            protect::push_bytes(l,b"cannot call a rust function while the state is closed");
+            ffi::lua_error(l);
+        }
+    }
+    unsafe { profile::callback(l,true) };
+    let outcome = panic::catch_unwind(AssertUnwindSafe(|| unsafe { invoke(l) }));
+    unsafe {
+        profile::callback(l,false);
+        match outcome {
+            Ok(Ok(nresults)) => return nresults,
+            Ok(Err(err)) => push_error(l,err),
+            Err(payload) => push_panic(l,payload),
+        };
+        ffi::lua_error(l)
+    }
+}
+
+/// Calls the callback, its results are left on the stack.
+///
+/// Arguments are read and results pushed in protected mode, so every value is dropped by
+/// the time `trampoline` raises an error.
+unsafe fn invoke(l: *mut ffi::lua_State) -> Result<libc::c_int,Box<dyn Error>> {
+    // Scripts can replace the upvalue or drop the callback with the debug library
+    let f = match test_userdata::<Rc<Callback>>(l,ffi::lua_upvalueindex(1),&CALLBACK_METATABLE) {
+        Some(ud) => match *ud {
+            Some(ref f) => f.clone(),
+            None => return Err(From::from("the rust function was dropped")),
+        },
+        None => return Err(From::from("the upvalue of the rust function was replaced")),
+    };
+    let state = State { context: LuaContext::from_raw(l) };
+
+    let nargs = ffi::lua_gettop(l);
+    let mut args = Vec::with_capacity(nargs as usize);
+    for i in 1..nargs + 1 {
+        args.push(LuaRead::lua_read_index(&state.context,i).map_err(|_| LuaError::Memory)?);
+    }
+
+    let results = f(&state,args)?;
+
+    let nresults = results.len() as libc::c_int;
+    ffi::lua_settop(l,0);
+    if ffi::lua_checkstack(l,nresults + 2) == 0 {
+        return Err(From::from("too many results"));
+    }
+    protect::run(&state.context,0,ffi::MULTRET,|_| {
+        for result in &results {
+            LuaWrite::lua_write(&state.context,result);
+        }
+        nresults
+    })?;
+    Ok(nresults)
+}
+
+/// Pushes the error returned by a callback.
+///
+/// A `LuaError` re-raises the original error object, any other error is wrapped in a userdata.
+/// If lua runs out of memory the memory error is pushed instead.
+unsafe fn push_error(l: *mut ffi::lua_State,err: Box<dyn Error>) -> libc::c_int {
+    let err = match err.downcast::<LuaError>() {
+        Ok(err) => match *err {
+            LuaError::Runtime { cause: Some(cause), .. } => cause,
+            LuaError::Runtime { value: Some(value), .. } => {
+                let context = LuaContext::from_raw(l);
+                return protect::run_raw(l,0,1,|_| {
+                    LuaWrite::lua_write(&context,&value);
+                    1
+                });
+            },
+            err => return protect::push_bytes(l,err.message().as_bytes()),
+        },
+        Err(err) => Rc::from(err),
+    };
+
+    let mut err = Some(err);
+    protect::run_raw(l,0,1,|l| {
+        push_userdata(l,&mut err,&ERROR_METATABLE,&[(b"__tostring\0",error_tostring)]);
+        1
+    })
+}
+
+extern "C-unwind" fn error_tostring(l: *mut ffi::lua_State) -> libc::c_int {
+    unsafe {
+        let status = {
+            let ud = check_userdata::<Rc<dyn Error>>(l);
+            let message = match *ud {
+                Some(ref err) => err.to_string(),
+                None => String::from("dropped rust error"),
+            };
+            protect::push_bytes(l,message.as_bytes())
+        };
+        if status != ffi::LUA_OK {
+            ffi::lua_error(l);
+        }
+    }
+    1
+}
+
+/// Returns true if the value at the index is a function created for a rust callback.
+///
+/// Never raises an error, the profiler calls it from the hook.
+pub unsafe fn is_callback(l: *mut ffi::lua_State,index: libc::c_int) -> bool {
+    if ffi::lua_iscfunction(l,index) == 0 || ffi::lua_getupvalue(l,index,1).is_null() {
+        return false;
+    }
+    let callback = test_userdata::<Rc<Callback>>(l,-1,&CALLBACK_METATABLE).is_some();
+    ffi::lua_pop(l,1);
+    callback
+}
+
+/// Returns the error of a rust callback if the value on top of the stack holds one.
+pub unsafe fn error_cause(l: *mut ffi::lua_State) -> Option<Rc<dyn Error>> {
+    match test_userdata::<Rc<dyn Error>>(l,-1,&ERROR_METATABLE) {
+        Some(ud) => (*ud).clone(),
+        None => None,
+    }
+}
+
+/// Pushes a userdata holding the payload of a caught panic.
+///
+/// If lua runs out of memory the payload is dropped and the memory error is pushed instead.
+pub unsafe fn push_panic(l: *mut ffi::lua_State,payload: Box<dyn Any + Send>) -> libc::c_int {
+    let mut payload = Some(payload);
+    protect::run_raw(l,0,1,|l| {
+        push_userdata(l,&mut payload,&PANIC_METATABLE,&[(b"__tostring\0",panic_tostring)]);
+        1
+    })
+}
+
+extern "C-unwind" fn panic_tostring(l: *mut ffi::lua_State) -> libc::c_int {
+    unsafe {
+        let ud = check_userdata::<Box<dyn Any + Send>>(l);
+        let message = match *ud {
+            Some(ref payload) => {
+                if let Some(s) = payload.downcast_ref::<&str>() {
+                    *s
+                } else if let Some(s) = payload.downcast_ref::<String>() {
+                    s.as_str()
+                } else {
+                    "Box<Any>"
+                }
+            },
+            None => "resumed",
+        };
+        ffi::lua_pushstring(l,b"rust panic: \0".as_ptr() as *const libc::c_char);
+        ffi::lua_pushlstring(l,message.as_ptr() as *const libc::c_char,message.len() as libc::size_t);
+        ffi::lua_concat(l,2);
+    }
+    1
+}
+
+/// Takes the panic payload if the value on top of the stack is a caught panic.
+pub unsafe fn take_panic(l: *mut ffi::lua_State) -> Option<Box<dyn Any + Send>> {
+    match test_userdata::<Box<dyn Any + Send>>(l,-1,&PANIC_METATABLE) {
+        Some(ud) => (*ud).take(),
+        None => None,
+    }
+}
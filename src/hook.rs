@@ -0,0 +1,252 @@
+//! Debug hooks calling rust functions.
+//!
+//! Lua allows one hook per thread, so a single dispatcher serves the hook set with
+//! `State::set_hook`, the instruction limit, the profiler and coverage.
+
+use std::cell::{Cell, RefCell};
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+
+use coverage;
+use error;
+use ffi;
+use function;
+use libc;
+use protect;
+use {Handle, LuaContext, State};
+
+/// The events a hook is called for.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default)]
+pub struct HookMask {
+    /// Called when a function is entered, including tail calls.
+    pub call: bool,
+    /// Called when a function returns.
+    pub ret: bool,
+    /// Called before a new line of code is executed.
+    pub line: bool,
+    /// Called every `count` instructions.
+    pub count: Option<u32>,
+}
+
+/// The event a hook is called for.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum HookEvent {
+    Call,
+    /// A call replacing the calling function, lua 5.1 and LuaJIT report them as `Call`.
+    TailCall,
+    Return,
+    Line,
+    Count,
+}
+
+/// Tells lua how to continue after a hook.
+#[derive(Debug,Clone,PartialEq)]
+pub enum HookAction {
+    Continue,
+    /// Raises an error with the message in the running code.
+    Error(String),
+}
+
+/// The signature of hook functions.
+pub type HookFn = dyn FnMut(&State,&DebugInfo) -> HookAction;
+
+/// Information about the function running when a hook is called.
+///
+/// The details are queried from lua on demand.
+pub struct DebugInfo {
+    l: *mut ffi::lua_State,
+    ar: *mut ffi::lua_Debug,
+}
+
+impl DebugInfo {
+    unsafe fn info(&self,what: &[u8]) -> &ffi::lua_Debug {
+        ffi::lua_getinfo(self.l,what.as_ptr() as *const libc::c_char,self.ar);
+        &*self.ar
+    }
+
+    /// The event the hook is called for.
+    pub fn event(&self) -> HookEvent {
+        match unsafe { (*self.ar).event } {
+            ffi::LUA_HOOKCALL => HookEvent::Call,
+            #[cfg(not(any(feature = "lua51", feature = "luajit")))]
+            ffi::LUA_HOOKTAILCALL => HookEvent::TailCall,
+            // The return of a function whose frame a tail call reused
+            #[cfg(any(feature = "lua51", feature = "luajit"))]
+            ffi::LUA_HOOKTAILRET => HookEvent::Return,
+            ffi::LUA_HOOKRET => HookEvent::Return,
+            ffi::LUA_HOOKLINE => HookEvent::Line,
+            _ => HookEvent::Count,
+        }
+    }
+
+    /// The line about to be executed or `None` for C functions.
+    pub fn line(&self) -> Option<u32> {
+        let line = unsafe { self.info(b"l\0").currentline };
+        if line > 0 { Some(line as u32) } else { None }
+    }
+
+    /// The chunk name, e.g. `@script.lua` for files or `=[C]` for C functions.
+    pub fn source(&self) -> String {
+        unsafe { error::read_cstr(self.info(b"S\0").source).unwrap_or_default() }
+    }
+
+    /// The chunk name as lua prints it in messages, e.g. `script.lua`.
+    pub fn short_source(&self) -> String {
+        unsafe { error::read_cstr(self.info(b"S\0").short_src.as_ptr()).unwrap_or_default() }
+    }
+
+    /// The line the running function is defined at or `None` for C functions.
+    pub fn line_defined(&self) -> Option<u32> {
+        let line = unsafe { self.info(b"S\0").linedefined };
+        if line > 0 { Some(line as u32) } else { None }
+    }
+
+    /// `"Lua"`, `"C"` or `"main"`.
+    pub fn what(&self) -> String {
+        unsafe { error::read_cstr(self.info(b"S\0").what).unwrap_or_default() }
+    }
+
+    /// The name of the running function if lua could determine it.
+    pub fn name(&self) -> Option<String> {
+        unsafe { error::read_cstr(self.info(b"n\0").name) }
+    }
+}
+
+/// The hook set with `State::set_hook`.
+pub struct Hook {
+    mask: HookMask,
+    callback: RefCell<Box<HookFn>>,
+    /// Instructions counted towards the next count event.
+    pending: Cell<u64>,
+}
+
+impl Hook {
+    pub fn new(mask: HookMask,callback: Box<HookFn>) -> Hook {
+        Hook { mask: mask, callback: RefCell::new(callback), pending: Cell::new(0) }
+    }
+}
+
+fn gcd(a: u64,b: u64) -> u64 {
+    if b == 0 { a } else { gcd(b,a % b) }
+}
+
+/// Installs the dispatcher with the events needed by the hook, the instruction limit, the
+/// profiler and coverage.
+///
+/// New coroutines inherit the hook of the main thread, existing ones are not changed.
+pub unsafe fn update(handle: &Handle) {
+    let hook = handle.hook.borrow();
+    let mut mask = 0;
+    let mut count = None;
+    if let Some(ref hook) = *hook {
+        if hook.mask.call { mask |= ffi::LUA_MASKCALL; }
+        if hook.mask.ret { mask |= ffi::LUA_MASKRET; }
+        if hook.mask.line { mask |= ffi::LUA_MASKLINE; }
+        count = hook.mask.count.filter(|&c| c > 0).map(u64::from);
+        hook.pending.set(0);
+    }
+    if handle.coverage.borrow().is_some() {
+        mask |= ffi::LUA_MASKLINE;
+    }
+    let step = handle.instructions.step();
+    let profiler = handle.profiler.borrow().as_ref().map(|profiler| profiler.interval());
+    let period = [count,step,profiler].iter().filter_map(|&c| c).fold(0,gcd);
+    handle.instructions.period.set(period);
+    if period > 0 {
+        mask |= ffi::LUA_MASKCOUNT;
+    }
+    // LuaJIT doesn't call hooks from compiled code, the compiler is off while they are set
+    #[cfg(feature = "luajit")]
+    {
+        let mode = if mask != 0 { ffi::LUAJIT_MODE_OFF } else { ffi::LUAJIT_MODE_ON };
+        ffi::luaJIT_setmode(handle.l,0,ffi::LUAJIT_MODE_ENGINE | ffi::LUAJIT_MODE_FLUSH);
+        ffi::luaJIT_setmode(handle.l,0,ffi::LUAJIT_MODE_ENGINE | mode);
+    }
+    ffi::lua_sethook(handle.l,dispatch,mask,period as libc::c_int);
+}
+
+/// What the dispatcher has to do after the rust side returned.
+enum Outcome {
+    Continue,
+    /// The error value is on the stack, it was pushed in protected mode.
+    Raise,
+}
+
+extern "C-unwind" fn dispatch(l: *mut ffi::lua_State,ar: *mut ffi::lua_Debug) {
+    let outcome = unsafe { run(l,ar) };
+    if let Outcome::Raise = outcome {
+        // Nothing with a destructor may be alive here, lua_error never returns
+        unsafe { ffi::lua_error(l); }
+    }
+}
+
+unsafe fn run(l: *mut ffi::lua_State,ar: *mut ffi::lua_Debug) -> Outcome {
+    let handle = ::handle_ptr(l);
+    // Finalizers run while the state is closed
+    if handle.is_null() {
+        return Outcome::Continue;
+    }
+    let handle = &*handle;
+    let mut call_hook = true;
+
+    if (*ar).event == ffi::LUA_HOOKCOUNT {
+        let period = handle.instructions.period.get();
+        if handle.instructions.count(period) {
+            protect::push_bytes(l,b"instruction limit exceeded");
+            return Outcome::Raise;
+        }
+        if let Some(ref mut profiler) = *handle.profiler.borrow_mut() {
+            profiler.count(l,period);
+        }
+        call_hook = match *handle.hook.borrow() {
+            Some(ref hook) => match hook.mask.count {
+                Some(count) if count > 0 => {
+                    let pending = hook.pending.get() + period;
+                    hook.pending.set(pending % count as u64);
+                    pending >= count as u64
+                },
+                _ => false,
+            },
+            None => false,
+        };
+    }
+    if (*ar).event == ffi::LUA_HOOKLINE {
+        coverage::line(&handle.coverage,l,ar);
+        call_hook = handle.hook.borrow().as_ref().is_some_and(|hook| hook.mask.line);
+    }
+    if !call_hook {
+        return Outcome::Continue;
+    }
+
+    // Keep the hook alive even if the callback replaces it
+    let hook = match *handle.hook.borrow() {
+        Some(ref hook) => hook.clone(),
+        None => return Outcome::Continue,
+    };
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let state = State { context: LuaContext::from_raw(l) };
+        let info = DebugInfo { l: l, ar: ar };
+        // Lua disables hooks while one runs, but other coroutines resumed by it still call it
+        match hook.callback.try_borrow_mut() {
+            Ok(mut callback) => callback(&state,&info),
+            Err(_) => HookAction::Continue,
+        }
+    }));
+    match result {
+        Ok(HookAction::Continue) => Outcome::Continue,
+        Ok(HookAction::Error(message)) => {
+            protect::push_bytes(l,message.as_bytes());
+            Outcome::Raise
+        },
+        Err(payload) => {
+            function::push_panic(l,payload);
+            Outcome::Raise
+        },
+    }
+}
+
+/// Sets or removes the hook of the state.
+pub fn set(context: &LuaContext,hook: Option<Hook>) {
+    *context.handle.hook.borrow_mut() = hook.map(Rc::new);
+    unsafe { update(&context.handle) };
+}
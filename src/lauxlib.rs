@@ -3,25 +3,17 @@
 #![allow(improper_ctypes)]
 
 extern crate libc;
-extern crate lua52_sys as raw;
+use ffi as raw;
 
 use libc::c_int;
 use self::raw::*;
 use std::ptr;
 
-#[allow(non_camel_case_types)]
-    #[repr(C)]
-    #[allow(missing_copy_implementations)]
-    pub struct luaL_Reg {
-        pub name: *const libc::c_char,
-        pub func: Option<lua_CFunction>
-    }
+pub const LUA_ERRFILE: c_int = LUA_ERRERR + 1;
 
-extern "C" {
-        pub fn luaL_register(L: *mut lua_State, libname: *const libc::c_char, l: *const luaL_Reg);
+extern "C-unwind" {
         pub fn luaL_getmetafield(L: *mut lua_State, obj: c_int, e: *const libc::c_char) -> c_int;
         pub fn luaL_callmeta(L: *mut lua_State, obj: c_int, e: *const libc::c_char) -> c_int;
-        pub fn luaL_typerror(L: *mut lua_State, narg: c_int, tname: *const libc::c_char) -> c_int;
         pub fn luaL_argerror(L: *mut lua_State, numarg: c_int, extramsg: *const libc::c_char) -> c_int;
         pub fn luaL_checklstring(L: *mut lua_State, numArg: c_int,
                                  l: *mut libc::size_t) -> *const libc::c_char;
@@ -40,7 +32,6 @@ extern "C" {
         pub fn luaL_newmetatable(L: *mut lua_State, tname: *const libc::c_char) -> c_int;
         pub fn luaL_checkudata(L: *mut lua_State, ud: c_int, tname: *const libc::c_char)
                               -> *mut libc::c_void;
-
         pub fn luaL_where(L: *mut lua_State, lvl: c_int);
         pub fn luaL_error(L: *mut lua_State, fmt: *const libc::c_char, ...) -> c_int;
 
@@ -50,9 +41,6 @@ extern "C" {
         pub fn luaL_ref(L: *mut lua_State, t: c_int) -> c_int;
         pub fn luaL_unref(L: *mut lua_State, t: c_int, refid: c_int);
 
-        pub fn luaL_loadfilex(L: *mut lua_State, filename: *const libc::c_char,mode: *const libc::c_char) -> c_int;
-        pub fn luaL_loadbuffer(L: *mut lua_State, buff: *const libc::c_char, sz: libc::size_t,
-                               name: *const libc::c_char) -> c_int;
         pub fn luaL_loadstring(L: *mut lua_State, s: *const libc::c_char) -> c_int;
 
         pub fn luaL_newstate() -> *mut lua_State;
@@ -61,6 +49,33 @@ extern "C" {
                         -> *const libc::c_char;
     }
 
+#[cfg(not(feature = "lua51"))]
+extern "C-unwind" {
+        pub fn luaL_loadfilex(L: *mut lua_State, filename: *const libc::c_char,mode: *const libc::c_char) -> c_int;
+        pub fn luaL_loadbufferx(L: *mut lua_State, buff: *const libc::c_char, sz: libc::size_t,
+                                name: *const libc::c_char, mode: *const libc::c_char) -> c_int;
+        pub fn luaL_testudata(L: *mut lua_State, ud: c_int, tname: *const libc::c_char)
+                              -> *mut libc::c_void;
+        pub fn luaL_traceback(L: *mut lua_State, L1: *mut lua_State, msg: *const libc::c_char, level: c_int);
+    }
+
+#[cfg(not(any(feature = "lua51", feature = "luajit")))]
+extern "C-unwind" {
+        pub fn luaL_requiref(L: *mut lua_State, modname: *const libc::c_char, openf: lua_CFunction, glb: c_int);
+    }
+
+#[cfg(any(feature = "lua51", feature = "luajit"))]
+extern "C-unwind" {
+        pub fn luaL_typerror(L: *mut lua_State, narg: c_int, tname: *const libc::c_char) -> c_int;
+    }
+
+#[cfg(feature = "lua51")]
+extern "C-unwind" {
+        pub fn luaL_loadfile(L: *mut lua_State, filename: *const libc::c_char) -> c_int;
+        pub fn luaL_loadbuffer(L: *mut lua_State, buff: *const libc::c_char, sz: libc::size_t,
+                               name: *const libc::c_char) -> c_int;
+    }
+
     // Some useful functions (macros in C)
     #[inline(always)]
     pub unsafe fn luaL_argcheck(L: *mut lua_State, cond: bool, numarg: c_int, extramsg: *const libc::c_char) {
@@ -104,10 +119,18 @@ extern "C" {
         raw::lua_typename(L, raw::lua_type(L, i))
     }
 
+    #[cfg(not(feature = "lua51"))]
     #[inline(always)]
     pub unsafe fn luaL_loadfile(L: *mut lua_State, filename: *const libc::c_char) -> c_int {
-        return luaL_loadfilex(L,filename,ptr::null());
+        luaL_loadfilex(L,filename,ptr::null())
     }
+    #[cfg(not(feature = "lua51"))]
+    #[inline(always)]
+    pub unsafe fn luaL_loadbuffer(L: *mut lua_State, buff: *const libc::c_char, sz: libc::size_t,
+                                  name: *const libc::c_char) -> c_int {
+        luaL_loadbufferx(L,buff,sz,name,ptr::null())
+    }
+
     #[inline(always)]
     pub unsafe fn luaL_dofile(L: *mut lua_State, filename: *const libc::c_char) -> c_int {
         ((luaL_loadfile(L, filename) != 0) || (raw::lua_pcall(L, 0, MULTRET, 0) != 0)) as c_int
@@ -120,7 +143,7 @@ extern "C" {
 
     #[inline(always)]
     pub unsafe fn luaL_getmetatable(L: *mut lua_State, name: *const libc::c_char) {
-        raw::lua_getfield(L, LUA_REGISTRYINDEX, name)
+        raw::lua_getfield(L, LUA_REGISTRYINDEX, name);
     }
 
     #[inline(always)]
@@ -133,3 +156,92 @@ extern "C" {
             f(L, n)
         }
     }
+
+    // The functions of lua 5.2 missing in lua 5.1 and LuaJIT
+
+    /// Rejects chunks not allowed by `mode` like lua 5.2 before loading them.
+    #[cfg(feature = "lua51")]
+    pub unsafe fn luaL_loadbufferx(L: *mut lua_State, buff: *const libc::c_char, sz: libc::size_t,
+                                   name: *const libc::c_char, mode: *const libc::c_char) -> c_int {
+        if !mode.is_null() {
+            let binary = sz > 0 && *buff == 27;
+            let kind = if binary { b'b' } else { b't' };
+            if !::std::ffi::CStr::from_ptr(mode).to_bytes().contains(&kind) {
+                // Pushing may raise a memory error, nothing with a destructor may be alive
+                let kind = if binary { b"binary\0".as_ptr() } else { b"text\0".as_ptr() };
+                lua_pushfstring(L, b"attempt to load a %s chunk (mode is '%s')\0".as_ptr() as *const libc::c_char,
+                                kind as *const libc::c_char, mode);
+                return LUA_ERRSYNTAX;
+            }
+        }
+        luaL_loadbuffer(L,buff,sz,name)
+    }
+
+    /// Files are loaded regardless of `mode`.
+    #[cfg(feature = "lua51")]
+    #[inline(always)]
+    pub unsafe fn luaL_loadfilex(L: *mut lua_State, filename: *const libc::c_char,_mode: *const libc::c_char) -> c_int {
+        luaL_loadfile(L,filename)
+    }
+
+    #[cfg(feature = "lua51")]
+    pub unsafe fn luaL_testudata(L: *mut lua_State, ud: c_int, tname: *const libc::c_char) -> *mut libc::c_void {
+        let p = raw::lua_touserdata(L, ud);
+        if p.is_null() || raw::lua_getmetatable(L, ud) == 0 {
+            return ptr::null_mut();
+        }
+        luaL_getmetatable(L, tname);
+        let same = raw::lua_rawequal(L, -1, -2) != 0;
+        raw::lua_pop(L, 2);
+        if same { p } else { ptr::null_mut() }
+    }
+
+    /// Pushes a traceback of `L1` in the format of lua 5.2.
+    ///
+    /// The message handler calls it on every error. It is pushed in pieces concatenated by
+    /// lua, as a memory error would skip the destructor of a rust string.
+    #[cfg(feature = "lua51")]
+    pub unsafe fn luaL_traceback(L: *mut lua_State, L1: *mut lua_State, msg: *const libc::c_char, level: c_int) {
+        let top = raw::lua_gettop(L);
+        if !msg.is_null() {
+            lua_pushfstring(L, b"%s\n\0".as_ptr() as *const libc::c_char, msg);
+        }
+        raw::lua_pushstring(L, b"stack traceback:\0".as_ptr() as *const libc::c_char);
+        let mut ar: lua_Debug = ::std::mem::zeroed();
+        let mut level = level;
+        while raw::lua_getstack(L1, level, &mut ar) != 0 {
+            raw::lua_getinfo(L1, b"Sln\0".as_ptr() as *const libc::c_char, &mut ar);
+            lua_pushfstring(L, b"\n\t%s:\0".as_ptr() as *const libc::c_char, ar.short_src.as_ptr());
+            if ar.currentline > 0 {
+                lua_pushfstring(L, b"%d:\0".as_ptr() as *const libc::c_char, ar.currentline);
+            }
+            if *ar.namewhat != 0 {
+                lua_pushfstring(L, b" in function '%s'\0".as_ptr() as *const libc::c_char, ar.name);
+            } else if *ar.what == b'm' as libc::c_char {
+                raw::lua_pushstring(L, b" in main chunk\0".as_ptr() as *const libc::c_char);
+            } else if *ar.what == b'C' as libc::c_char {
+                raw::lua_pushstring(L, b" ?\0".as_ptr() as *const libc::c_char);
+            } else {
+                lua_pushfstring(L, b" in function <%s:%d>\0".as_ptr() as *const libc::c_char,
+                                ar.short_src.as_ptr(), ar.linedefined);
+            }
+            raw::lua_concat(L, raw::lua_gettop(L) - top);
+            level += 1;
+        }
+        raw::lua_concat(L, raw::lua_gettop(L) - top);
+    }
+
+    #[cfg(any(feature = "lua51", feature = "luajit"))]
+    pub unsafe fn luaL_requiref(L: *mut lua_State, modname: *const libc::c_char, openf: lua_CFunction, glb: c_int) {
+        raw::lua_pushcfunction(L, openf);
+        raw::lua_pushstring(L, modname);
+        raw::lua_call(L, 1, 1);
+        raw::lua_getfield(L, LUA_REGISTRYINDEX, b"_LOADED\0".as_ptr() as *const libc::c_char);
+        raw::lua_pushvalue(L, -2);
+        raw::lua_setfield(L, -2, modname);
+        raw::lua_pop(L, 1);
+        if glb != 0 {
+            raw::lua_pushvalue(L, -1);
+            raw::lua_setglobal(L, modname);
+        }
+    }
@@ -1,39 +1,218 @@
+// Field names and lifetimes are spelled out throughout the crate
+#![allow(clippy::redundant_field_names, clippy::redundant_static_lifetimes)]
+// Reading follows `LuaRead`, which has no details about why a value can't be read
+#![allow(clippy::result_unit_err)]
+
 extern crate libc;
-extern crate lua52_sys as ffi;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(feature = "derive")]
+extern crate yuna_derive;
+#[cfg(feature = "dap")]
+#[macro_use]
+extern crate serde_json;
+
+/// Implements `LuaRef`, `Drop` and `Clone` for a type with `context` and `refindex` fields.
+macro_rules! impl_lua_ref(
+    ($t:ident) => (
+        impl $crate::LuaRef for $t {
+            fn get_context(&self) -> &$crate::LuaContext {
+                &self.context
+            }
+
+            fn get_refindex(&self) -> ::libc::c_int {
+                self.refindex
+            }
+
+            unsafe fn from_refindex(context: &$crate::LuaContext,refindex: ::libc::c_int) -> Self {
+                $t { context: context.clone(), refindex: refindex }
+            }
+        }
+
+        impl Drop for $t {
+            fn drop(&mut self) {
+                unsafe { $crate::lauxlib::luaL_unref(self.context.l,::ffi::LUA_REGISTRYINDEX,self.refindex) };
+            }
+        }
 
+        impl Clone for $t {
+            fn clone(&self) -> Self {
+                unsafe {
+                    $crate::LuaRef::write_self(self);
+                    $crate::LuaRef::ref_from_stack(&self.context)
+                }
+            }
+        }
+    );
+);
+
+mod ffi;
 mod lauxlib;
+mod fmt;
+mod error;
+mod protect;
+#[cfg(any(feature = "lua51", feature = "lua52"))]
+mod bytecode;
+#[cfg(not(any(feature = "lua51", feature = "lua52")))]
+mod lineinfo;
+mod function;
+mod userdata;
+mod thread;
+mod source;
+mod package;
+mod output;
+mod limits;
+mod libs;
+mod reload;
+mod hook;
+mod stack;
+mod profile;
+mod coverage;
+#[cfg(feature = "serde")]
+mod lua_serde;
+
+#[cfg(feature = "serde")]
+pub use lua_serde::{to_lua,from_lua,Error as SerdeError,PathSegment};
+#[cfg(feature = "derive")]
+pub use yuna_derive::{LuaRead,LuaWrite,embed_scripts};
+
+use std::cell::{Cell, RefCell};
+use std::ffi::CString;
+use std::io::Write;
+use std::panic;
+use std::path::{Path,PathBuf};
+use std::rc::{Rc, Weak};
+
+pub use error::{Diagnostic,LuaError,TraceFrame,Traceback};
+pub use function::{Function,Callback};
+pub use userdata::UserData;
+pub use thread::Thread;
+pub use package::PackagePathError;
+pub use libs::Library;
+pub use reload::{HotReload, Reload};
+pub use hook::{DebugInfo, HookAction, HookEvent, HookMask};
+pub use stack::StackFrame;
+pub use profile::{FrameKind, FunctionProfile, Profile};
+pub use coverage::Coverage;
+pub use source::{ScriptSource,DirectorySource,MemorySource,LayeredSource,EmbeddedSource};
+
+pub mod raw;
+#[cfg(feature = "dap")]
+pub mod dap;
+
+/// Closes the `lua_State` once the last `LuaContext` referring to it is dropped.
+///
+/// Also holds the rust side data shared by everything using the state.
+struct Handle {
+    l: *mut ffi::lua_State,
+    output: output::Sinks,
+    memory: Box<limits::Memory>,
+    instructions: limits::Instructions,
+    reload: RefCell<Weak<reload::Files>>,
+    hook: RefCell<Option<Rc<hook::Hook>>>,
+    profiler: RefCell<Option<profile::Profiler>>,
+    coverage: RefCell<Option<coverage::Collector>>,
+    /// The traceback filled by the message handler of the innermost `error::pcall`.
+    traceback: Cell<*mut error::Traceback>,
+}
 
-use std::cell::Cell;
+impl Drop for Handle {
+    fn drop(&mut self) {
+        unsafe {
+            // Finalizers run by lua_close may call rust functions, which must not find the
+            // handle anymore
+            ffi::lua_pushnil(self.l);
+            ffi::lua_rawsetp(self.l,ffi::LUA_REGISTRYINDEX,&HANDLE_KEY as *const u8 as *const libc::c_void);
+            ffi::lua_close(self.l);
+        }
+    }
+}
+
+/// Registry key under which the `Handle` of a state is stored.
+static HANDLE_KEY: u8 = 0;
 
 /// Holds the raw `lua_State`.
 pub struct LuaContext {
-    /// The raw lua state
-    pub l: *mut ffi::lua_State,
-    refcount: Cell<usize>
+    l: *mut ffi::lua_State,
+    handle: Rc<Handle>,
+}
+
+/// Returns the `Handle` of a state created by `LuaContext::new` or null.
+///
+/// The handle is also null while the state is closed.
+unsafe fn handle_ptr(l: *mut ffi::lua_State) -> *const Handle {
+    ffi::lua_rawgetp(l,ffi::LUA_REGISTRYINDEX,&HANDLE_KEY as *const u8 as *const libc::c_void);
+    let handle = ffi::lua_touserdata(l,-1) as *const Handle;
+    ffi::lua_pop(l,1);
+    handle
 }
 
 impl LuaContext {
     pub fn new() -> Self {
-        let l = unsafe { lauxlib::luaL_newstate() };
-        let refcount = Cell::new(1);
-        LuaContext { l: l, refcount: refcount }
+        let memory = limits::Memory::new();
+        let l = unsafe { ffi::lua_newstate(limits::alloc,&*memory as *const limits::Memory as *mut libc::c_void) };
+        assert!(!l.is_null(),"not enough memory to create lua state");
+        let handle = Rc::new(Handle {
+            l: l,
+            output: Default::default(),
+            memory: memory,
+            instructions: limits::Instructions::new(),
+            reload: Default::default(),
+            hook: Default::default(),
+            profiler: Default::default(),
+            coverage: Default::default(),
+            traceback: Cell::new(std::ptr::null_mut()),
+        });
+        unsafe {
+            ffi::lua_atpanic(l,limits::at_panic);
+            assert!(protect::register(l),"not enough memory to create lua state");
+            // Older versions keep the free list of `luaL_unref` at index 0, created now
+            // dropping a reference never allocates
+            ffi::lua_pushinteger(l,0);
+            ffi::lua_rawseti(l,ffi::LUA_REGISTRYINDEX,0);
+            ffi::lua_pushlightuserdata(l,&*handle as *const Handle as *mut libc::c_void);
+            ffi::lua_rawsetp(l,ffi::LUA_REGISTRYINDEX,&HANDLE_KEY as *const u8 as *const libc::c_void);
+        }
+        LuaContext { l: l, handle: handle }
+    }
+
+    /// Creates a context for a `lua_State` created by `LuaContext::new`, e.g. inside a callback.
+    ///
+    /// The pointer may also be a thread of such a state.
+    ///
+    /// # Safety
+    ///
+    /// `l` has to point to a live `lua_State`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the state was not created by yuna or is being closed.
+    pub unsafe fn from_raw(l: *mut ffi::lua_State) -> Self {
+        let handle = handle_ptr(l);
+        assert!(!handle.is_null(),"lua_State not created by yuna");
+        Rc::increment_strong_count(handle);
+        LuaContext { l: l, handle: Rc::from_raw(handle) }
+    }
+
+    /// Returns the raw lua state.
+    ///
+    /// Using the pointer is unsafe, see the `raw` module.
+    pub fn as_ptr(&self) -> *mut ffi::lua_State {
+        self.l
     }
 }
 
-impl Clone for LuaContext {
-    fn clone(&self) -> Self {
-        let refcount = self.refcount.clone();
-        refcount.set(refcount.get() + 1);
-        LuaContext { l: self.l, refcount: refcount }
+impl Default for LuaContext {
+    fn default() -> Self {
+        LuaContext::new()
     }
 }
 
-impl Drop for LuaContext {
-    fn drop(&mut self) {
-        self.refcount.set(self.refcount.get() - 1);
-        if self.refcount.get() == 0 {
-            unsafe { ffi::lua_close(self.l); }
-        }
+/// Clones always refer to the main thread, so references stay valid when a coroutine dies.
+impl Clone for LuaContext {
+    fn clone(&self) -> Self {
+        LuaContext { l: self.handle.l, handle: self.handle.clone() }
     }
 }
 
@@ -42,6 +221,12 @@ pub struct State {
     context: LuaContext,
 }
 
+impl Default for State {
+    fn default() -> Self {
+        State::new()
+    }
+}
+
 impl State {
 
     /// Creates a new State.
@@ -53,19 +238,128 @@ impl State {
 
     /// Loads and runs the given string.
     ///
+    /// The code runs in protected mode, errors are returned with a traceback.
+    ///
     /// # Examples
     ///
     /// ```
     /// let mut state = yuna::State::new();
     /// state.openlibs();
     ///
-    /// state.do_string("print(\"yuna rocks!\")"); // prints "yuna rocks!"
+    /// state.do_string("print(\"yuna rocks!\")").unwrap(); // prints "yuna rocks!"
+    ///
+    /// let err = state.do_string("error(\"oops\")").unwrap_err();
+    /// assert!(err.traceback().unwrap().starts_with("stack traceback:"));
+    /// ```
+    pub fn do_string<S: AsRef<str>>(&mut self,code: S) -> Result<(),LuaError> {
+        let code = code.as_ref();
+        // Like luaL_loadstring the code itself is used as chunk name
+        let name = CString::new(code.split('\0').next().unwrap()).unwrap();
+        unsafe {
+            let status = lauxlib::luaL_loadbuffer(self.context.l,code.as_ptr() as *const libc::c_char,
+                                                  code.len() as libc::size_t,name.as_ptr());
+            self.call_loaded(status)
+        }
+    }
+
+    /// Loads and runs the given file.
+    ///
+    /// The code runs in protected mode, errors are returned with a traceback.
+    pub fn do_file<P: AsRef<Path>>(&mut self,path: P) -> Result<(),LuaError> {
+        reload::track_script(&self.context,path.as_ref());
+        self.load_file(path)?.call(vec![]).map(|_| ())
+    }
+
+    /// Compiles the given file into a function without running it.
+    ///
+    /// Like the standalone interpreter a first line starting with `#` is skipped.
+    pub fn load_file<P: AsRef<Path>>(&self,path: P) -> Result<Function,LuaError> {
+        let path = path.as_ref();
+        let cpath = path.to_str()
+                        .and_then(|p| CString::new(p).ok())
+                        .ok_or_else(|| LuaError::File(format!("cannot open {}",path.display())))?;
+        unsafe {
+            // luaL_loadfile pushes the chunk name before loading in protected mode itself
+            let mut status = ffi::LUA_OK;
+            protect::run(&self.context,0,1,|l| {
+                status = lauxlib::luaL_loadfile(l,cpath.as_ptr());
+                1
+            })?;
+            if status != ffi::LUA_OK {
+                return Err(error::pop_error(&self.context,status,Traceback::default()));
+            }
+            let refindex = protect::reference(&self.context)?;
+            Ok(Function::from_refindex(&self.context,refindex))
+        }
+    }
+
+    /// Compiles the given code into a function without running it.
+    ///
+    /// The chunk name follows the lua conventions: `@path` for files and `=name` for
+    /// names which are printed as is.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let state = yuna::State::new();
+    ///
+    /// let chunk = state.load(b"return 1 + 1","=example").unwrap();
+    /// assert_eq!(chunk.call(vec![]).unwrap(),vec![yuna::LuaValue::from_number(2)]);
+    /// ```
+    pub fn load(&self,code: &[u8],chunk_name: &str) -> Result<Function,LuaError> {
+        let name = CString::new(chunk_name.split('\0').next().unwrap()).unwrap();
+        unsafe {
+            let status = lauxlib::luaL_loadbuffer(self.context.l,code.as_ptr() as *const libc::c_char,
+                                                  code.len() as libc::size_t,name.as_ptr());
+            if status != ffi::LUA_OK {
+                return Err(error::pop_error(&self.context,status,Traceback::default()));
+            }
+            let refindex = protect::reference(&self.context)?;
+            Ok(Function::from_refindex(&self.context,refindex))
+        }
+    }
+
+    /// Compiles the given code without running it.
+    ///
+    /// `chunk_name` is used as source in the diagnostics. Lua stops at the first syntax
+    /// error, so at most one diagnostic is returned. Precompiled chunks are rejected, lua
+    /// doesn't verify bytecode.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let state = yuna::State::new();
+    ///
+    /// assert!(state.check_syntax("x = 1","init.lua").is_ok());
+    ///
+    /// let diagnostics = state.check_syntax("x = 1\nif x then","init.lua").unwrap_err();
+    /// assert_eq!(diagnostics[0].source,"init.lua");
+    /// assert_eq!(diagnostics[0].line,Some(2));
+    /// assert!(diagnostics[0].message.starts_with("'end' expected near"));
     /// ```
-    pub fn do_string<S: AsRef<str>>(&mut self,code: S) {
-        let cstr = std::ffi::CString::new(code.as_ref()).unwrap().as_ptr();
+    pub fn check_syntax<S: AsRef<[u8]>>(&self,source: S,chunk_name: &str) -> Result<(),Vec<Diagnostic>> {
+        let source = source.as_ref();
+        // A leading '=' makes lua use the name as is in messages
+        let name = CString::new(format!("={}",chunk_name.split('\0').next().unwrap())).unwrap();
         unsafe {
-            lauxlib::luaL_dostring(self.context.l,cstr);
+            let status = lauxlib::luaL_loadbufferx(self.context.l,source.as_ptr() as *const libc::c_char,
+                                                   source.len() as libc::size_t,name.as_ptr(),
+                                                   b"t\0".as_ptr() as *const libc::c_char);
+            if status == ffi::LUA_OK {
+                ffi::lua_pop(self.context.l,1);
+                return Ok(());
+            }
+            let err = error::pop_error(&self.context,status,Traceback::default());
+            Err(vec![Diagnostic::from_message(err.message())])
+        }
+    }
+
+    /// Calls the chunk on top of the stack if loading it was successful.
+    unsafe fn call_loaded(&self,status: libc::c_int) -> Result<(),LuaError> {
+        if status != ffi::LUA_OK {
+            return Err(error::pop_error(&self.context,status,Traceback::default()));
         }
+        error::pcall(&self.context,0,0)
     }
 
     /// Opens all standard Lua libraries.
@@ -73,6 +367,182 @@ impl State {
         unsafe { ffi::luaL_openlibs(self.context.l); }
     }
 
+    /// Opens the given standard libraries.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use yuna::{Library, LuaIndex};
+    ///
+    /// let mut state = yuna::State::new();
+    /// state.open_libs(&[Library::Base,Library::String]);
+    ///
+    /// assert!(state.get("string") != yuna::LuaValue::Nil);
+    /// assert_eq!(state.get("io"),yuna::LuaValue::Nil);
+    /// ```
+    pub fn open_libs(&mut self,libs: &[Library]) {
+        for &lib in libs {
+            libs::open(self,lib);
+        }
+    }
+
+    /// Opens only the libraries of `Library::SAFE` and removes `dofile` and `loadfile`.
+    ///
+    /// `load` only accepts source code, as malformed bytecode can crash lua. Libraries
+    /// opened afterwards are not restricted, the base library brings back `dofile`.
+    pub fn sandbox(&mut self) {
+        self.open_libs(Library::SAFE);
+        self.do_string(libs::SANDBOX).expect("sandbox setup failed");
+    }
+
+    /// Limits the memory lua may allocate in bytes, `None` removes the limit.
+    ///
+    /// Allocations exceeding the limit fail with `LuaError::Memory`. Memory already in use
+    /// is not freed if it exceeds a new limit.
+    pub fn set_memory_limit(&mut self,limit: Option<usize>) {
+        self.context.handle.memory.limit.set(limit.unwrap_or(usize::MAX));
+    }
+
+    /// Returns the memory allocated by lua in bytes.
+    pub fn memory_used(&self) -> usize {
+        self.context.handle.memory.used.get()
+    }
+
+    /// Limits the number of vm instructions executed from now on, `None` removes the limit.
+    ///
+    /// Exceeding the limit raises the error "instruction limit exceeded". The limit is
+    /// checked every 1000 instructions.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut state = yuna::State::new();
+    /// state.set_instruction_limit(Some(100000));
+    ///
+    /// let err = state.do_string("while true do end").unwrap_err();
+    /// assert_eq!(err.message(),"instruction limit exceeded");
+    /// ```
+    pub fn set_instruction_limit(&mut self,limit: Option<u64>) {
+        limits::set_instruction_limit(&self.context.handle,limit);
+    }
+
+    /// Calls the function for the events in the mask, replacing any previous hook.
+    ///
+    /// Returning `HookAction::Error` raises an error in the running code. The hook applies to
+    /// the main thread and coroutines created after it was set. It works together with
+    /// `set_instruction_limit`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::cell::RefCell;
+    /// use std::rc::Rc;
+    /// use yuna::{HookAction, HookMask};
+    ///
+    /// let mut state = yuna::State::new();
+    /// let lines = Rc::new(RefCell::new(Vec::new()));
+    /// let seen = lines.clone();
+    /// state.set_hook(HookMask { line: true, ..HookMask::default() },move |_,info| {
+    ///     seen.borrow_mut().push(info.line().unwrap());
+    ///     HookAction::Continue
+    /// });
+    ///
+    /// state.do_string("local x = 1\nx = x + 1").unwrap();
+    /// assert_eq!(*lines.borrow(),vec![1,2]);
+    /// ```
+    pub fn set_hook<F>(&mut self,mask: HookMask,hook: F)
+        where F: FnMut(&State,&DebugInfo) -> HookAction + 'static {
+        hook::set(&self.context,Some(hook::Hook::new(mask,Box::new(hook))));
+    }
+
+    /// Returns the functions active on the call stack, starting with the running one.
+    ///
+    /// Inside a rust callback the first frame is the callback itself, inside a hook it is
+    /// the function the hook was called for. Outside of any call the stack is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use yuna::LuaIndex;
+    ///
+    /// let mut state = yuna::State::new();
+    /// let inspect = state.create_function(|state,_| {
+    ///     let frames = state.stack_frames();
+    ///     assert_eq!(frames[1].name,Some(String::from("spawn")));
+    ///     assert_eq!(frames[1].line,Some(3));
+    ///     assert_eq!(frames[1].locals()[0].0,"enemy");
+    ///     Ok(vec![])
+    /// });
+    /// state.set("inspect",&inspect);
+    ///
+    /// state.do_string("function spawn(enemy)\n  local hp = 10\n  inspect()\nend\nspawn('orc')").unwrap();
+    /// ```
+    pub fn stack_frames(&self) -> Vec<StackFrame> {
+        stack::frames(&self.context)
+    }
+
+    /// Removes the hook set with `set_hook`.
+    pub fn remove_hook(&mut self) {
+        hook::set(&self.context,None);
+    }
+
+    /// Starts sampling the call stack every `interval` lua instructions and around rust callbacks.
+    ///
+    /// A running profiler is restarted, its samples are dropped. The profiler works together
+    /// with `set_hook` and `set_instruction_limit`, coroutines are sampled if they were
+    /// created after it was started.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut state = yuna::State::new();
+    /// state.start_profiler(100);
+    /// state.do_string("local function f(n) return n < 2 and n or f(n - 1) + f(n - 2) end f(15)").unwrap();
+    /// let profile = state.stop_profiler();
+    /// assert!(profile.samples() > 0);
+    /// assert!(profile.collapsed().contains("f ([string"));
+    /// ```
+    pub fn start_profiler(&mut self,interval: u32) {
+        *self.context.handle.profiler.borrow_mut() = Some(profile::Profiler::new(interval));
+        unsafe { hook::update(&self.context.handle) };
+    }
+
+    /// Stops the profiler and returns the samples taken, an empty profile if it was not running.
+    pub fn stop_profiler(&mut self) -> Profile {
+        let profiler = self.context.handle.profiler.borrow_mut().take();
+        unsafe { hook::update(&self.context.handle) };
+        profiler.unwrap_or_else(|| profile::Profiler::new(1)).finish()
+    }
+
+    /// Starts counting how often each line of lua code runs.
+    ///
+    /// Running collection is restarted, its counts are dropped. Coverage works together with
+    /// `set_hook`, coroutines are included if they were created after it was started.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut state = yuna::State::new();
+    /// state.start_coverage();
+    /// let f = state.load(b"local x = 1\nif x > 1 then\n  x = 0\nend","@mod.lua").unwrap();
+    /// f.call(vec![]).unwrap();
+    /// let coverage = state.stop_coverage();
+    /// assert_eq!(coverage.lines("@mod.lua"),vec![(1,1),(2,1),(3,0),(4,1)]);
+    /// assert!(coverage.lcov().contains("SF:mod.lua\nDA:1,1\nDA:2,1\nDA:3,0\n"));
+    /// ```
+    pub fn start_coverage(&mut self) {
+        *self.context.handle.coverage.borrow_mut() = Some(Default::default());
+        unsafe { hook::update(&self.context.handle) };
+    }
+
+    /// Stops collecting coverage and returns the hit counts, an empty coverage if it was not
+    /// collected.
+    pub fn stop_coverage(&mut self) -> Coverage {
+        let coverage = self.context.handle.coverage.borrow_mut().take();
+        unsafe { hook::update(&self.context.handle) };
+        coverage.map(coverage::Collector::finish).unwrap_or_default()
+    }
+
     /// Returns the global Table.
     pub fn global(&self) -> Table {
         unsafe {
@@ -81,28 +551,317 @@ impl State {
         }
 
     }
+
+    /// Creates a new empty Table.
+    pub fn create_table(&self) -> Table {
+        Table::new(&self.context)
+    }
+
+    /// Creates a lua string.
+    pub fn create_string<S: AsRef<str>>(&self,s: S) -> LuaValue {
+        LuaValue::LuaString(s.as_ref().to_owned())
+    }
+
+    /// Converts any value which can be written to lua into a `LuaValue`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let state = yuna::State::new();
+    ///
+    /// assert_eq!(state.pack(42),yuna::LuaValue::LuaNumber(42.0));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if lua runs out of memory.
+    pub fn pack<T: LuaWrite>(&self,value: T) -> LuaValue {
+        unsafe {
+            if protect::write(&self.context,value).is_err() {
+                panic!("not enough memory");
+            }
+            let packed = LuaRead::lua_read_index(&self.context,-1);
+            ffi::lua_pop(self.context.l,1);
+            packed.expect("not enough memory")
+        }
+    }
+
+    /// Converts a `LuaValue` into any type which can be read from lua.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let state = yuna::State::new();
+    ///
+    /// let answer : i32 = state.unpack(yuna::LuaValue::from_number(42)).unwrap();
+    /// assert_eq!(answer,42);
+    /// ```
+    pub fn unpack<T: LuaRead>(&self,value: LuaValue) -> Result<T,()> {
+        unsafe {
+            protect::write(&self.context,&value).map_err(|_| ())?;
+            let unpacked = LuaRead::lua_read_index(&self.context,-1);
+            ffi::lua_pop(self.context.l,1);
+            unpacked
+        }
+    }
+
+    /// Creates a lua function which calls the given rust function.
+    ///
+    /// Errors returned by the function are raised as lua errors, panics are caught and
+    /// resumed once the error reaches rust again.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use yuna::LuaIndex;
+    ///
+    /// let mut state = yuna::State::new();
+    /// state.openlibs();
+    /// let double = state.create_function(|state,args| {
+    ///     let n : f64 = state.unpack(args[0].clone()).map_err(|_| "number expected")?;
+    ///     Ok(vec![state.pack(n * 2.0)])
+    /// });
+    /// state.set("double",&double);
+    ///
+    /// state.do_string("assert(double(21) == 42)").unwrap();
+    /// ```
+    pub fn create_function<F>(&self,f: F) -> Function
+        where F: Fn(&State,Vec<LuaValue>) -> Result<Vec<LuaValue>,Box<dyn std::error::Error>> + 'static {
+        function::create(&self.context,Box::new(f))
+    }
+
+    /// Makes a module built by rust available to `require`.
+    ///
+    /// The builder runs on the first `require` of the module, the returned table is
+    /// cached in `package.loaded` by lua.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the package library is not opened.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use yuna::LuaIndex;
+    ///
+    /// let mut state = yuna::State::new();
+    /// state.openlibs();
+    /// state.register_module("game.physics",|state| {
+    ///     let mut physics = state.create_table();
+    ///     physics.set("gravity",9.81);
+    ///     physics
+    /// });
+    ///
+    /// state.do_string("assert(require('game.physics').gravity == 9.81)").unwrap();
+    /// ```
+    pub fn register_module<F>(&mut self,name: &str,builder: F)
+        where F: Fn(&State) -> Table + 'static {
+        let loader = self.create_function(move |state,_| Ok(vec![LuaValue::LuaTable(builder(state))]));
+        let mut preload = self.package_table("preload").expect("package library not opened");
+        preload.set(name,&loader);
+    }
+
+    /// Lets `require` find modules in the given source.
+    ///
+    /// The source is searched right after `package.preload`, before `package.path`.
+    /// Chunks are named `@virtual/path.lua` after the path returned by the source.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the package library is not opened.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut state = yuna::State::new();
+    /// state.openlibs();
+    ///
+    /// let mut scripts = yuna::MemorySource::new();
+    /// scripts.insert("ourgame/physics.lua","return { gravity = 9.81 }");
+    /// state.add_script_source(scripts);
+    ///
+    /// state.do_string("assert(require('ourgame.physics').gravity == 9.81)").unwrap();
+    /// ```
+    pub fn add_script_source<S: ScriptSource + 'static>(&mut self,source: S) {
+        let searcher = self.create_function(move |state,args| {
+            let module : String = match args.into_iter().next() {
+                Some(LuaValue::LuaString(module)) => module,
+                _ => return Err(From::from("module name expected")),
+            };
+            match source.find(&module) {
+                Some((code,path)) => {
+                    let loader = state.load(&code,&format!("@{}",path)).map_err(|err| {
+                        format!("error loading module '{}' from '{}':\n\t{}",module,path,err.message())
+                    })?;
+                    Ok(vec![LuaValue::LuaFunction(loader),LuaValue::LuaString(path)])
+                },
+                None => Ok(vec![state.pack(format!("\n\tno module '{}' in script source",module))]),
+            }
+        });
+
+        let mut searchers = self.package_table(package::SEARCHERS).expect("package library not opened");
+        // Same as table.insert(searchers,2,searcher)
+        for i in (2..searchers.len() as i32 + 1).rev() {
+            let value = searchers.get(i);
+            searchers.set(i + 1,value);
+        }
+        searchers.set(2,&searcher);
+    }
+
+    /// Returns a table of the package library, e.g. `package.loaded`.
+    fn package_table(&self,name: &str) -> Option<Table> {
+        let package : Option<Table> = self.read("package").unwrap_or(None);
+        package.and_then(|package| package.read(name).unwrap_or(None))
+    }
+
+    /// Replaces `package.path` with the given paths.
+    ///
+    /// Paths containing `?` are used as templates as is, directories are searched for
+    /// `dir/?.lua` and `dir/?/init.lua`. Lua has no way to escape `;` or `?`, paths
+    /// containing `;` and directories containing `?` return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut state = yuna::State::new();
+    /// state.openlibs();
+    ///
+    /// state.set_package_path(&["scripts","/opt/game data/?.lua"]).unwrap();
+    ///
+    /// state.do_string("assert(package.path == 'scripts/?.lua;scripts/?/init.lua;/opt/game data/?.lua')").unwrap();
+    /// ```
+    pub fn set_package_path<P: AsRef<Path>>(&mut self,paths: &[P]) -> Result<(),PackagePathError> {
+        let path = package::join(paths,package::Kind::Lua)?;
+        self.set_package_field("path",path,false)
+    }
+
+    /// Appends a path to `package.path`, see `set_package_path`.
+    pub fn add_package_path<P: AsRef<Path>>(&mut self,path: P) -> Result<(),PackagePathError> {
+        let path = package::templates(path.as_ref(),package::Kind::Lua)?;
+        self.set_package_field("path",path,true)
+    }
+
+    /// Replaces `package.cpath` with the given paths.
+    ///
+    /// Directories are searched for `dir/?.so`, or `dir\?.dll` on windows.
+    pub fn set_package_cpath<P: AsRef<Path>>(&mut self,paths: &[P]) -> Result<(),PackagePathError> {
+        let path = package::join(paths,package::Kind::C)?;
+        self.set_package_field("cpath",path,false)
+    }
+
+    /// Appends a path to `package.cpath`, see `set_package_cpath`.
+    pub fn add_package_cpath<P: AsRef<Path>>(&mut self,path: P) -> Result<(),PackagePathError> {
+        let path = package::templates(path.as_ref(),package::Kind::C)?;
+        self.set_package_field("cpath",path,true)
+    }
+
+    fn set_package_field(&mut self,field: &str,value: String,append: bool) -> Result<(),PackagePathError> {
+        let package : Option<Table> = self.read("package").unwrap_or(None);
+        let mut package = package.ok_or(PackagePathError::NoPackageLibrary)?;
+        let value = match package.read::<_,Option<String>>(field) {
+            Ok(Some(ref current)) if append && !current.is_empty() => format!("{};{}",current,value),
+            _ => value,
+        };
+        package.try_set(field,value).map_err(|_| PackagePathError::NoPackageLibrary)
+    }
+
+    /// Returns the file `require` would load the module from using `package.path`.
+    ///
+    /// This uses `package.searchpath` and returns `None` if no file exists. With lua 5.1 and
+    /// LuaJIT the templates are tried directly.
+    pub fn search_path(&self,name: &str) -> Option<PathBuf> {
+        let package : Option<Table> = self.read("package").unwrap_or(None);
+        let package = package?;
+        #[cfg(any(feature = "lua51", feature = "luajit"))]
+        {
+            let path : String = package.read::<_,Option<String>>("path").ok()??;
+            package::search_path(name,&path)
+        }
+        #[cfg(not(any(feature = "lua51", feature = "luajit")))]
+        {
+            let searchpath : Function = package.read::<_,Option<Function>>("searchpath").ok()??;
+            let path = package.get("path");
+            match searchpath.call(vec![self.create_string(name),path]).ok()?.into_iter().next() {
+                Some(LuaValue::LuaString(found)) => Some(PathBuf::from(found)),
+                _ => None,
+            }
+        }
+    }
+
+    /// Redirects the output of `print`, `io.write` and `io.stdout` to the given writer.
+    ///
+    /// The functions of the base and io libraries are replaced, so this has to be called
+    /// after `openlibs`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut state = yuna::State::new();
+    /// state.openlibs();
+    /// state.set_output(std::io::sink());
+    ///
+    /// state.do_string("print('nobody sees this')").unwrap();
+    /// ```
+    pub fn set_output<W: Write + 'static>(&mut self,output: W) {
+        *self.context.handle.output.stdout.borrow_mut() = Some(Box::new(output));
+        output::install(self);
+    }
+
+    /// Redirects the output of `io.stderr` to the given writer, see `set_output`.
+    pub fn set_error_output<W: Write + 'static>(&mut self,output: W) {
+        *self.context.handle.output.stderr.borrow_mut() = Some(Box::new(output));
+        output::install(self);
+    }
+
+    /// Runs the function and returns everything written to the standard output by scripts.
+    ///
+    /// The previous output is restored afterwards, see `set_output`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut state = yuna::State::new();
+    /// state.openlibs();
+    ///
+    /// let output = state.capture_output(|state| {
+    ///     state.do_string("print('hello', 42) io.write('a', 1.5, '\\n')").unwrap();
+    /// });
+    /// assert_eq!(output,"hello\t42\na1.5\n");
+    /// ```
+    pub fn capture_output<F: FnOnce(&mut State)>(&mut self,f: F) -> String {
+        let buffer = Rc::new(RefCell::new(Vec::new()));
+        let capture : Box<dyn Write> = Box::new(output::Buffer(buffer.clone()));
+        let previous = self.context.handle.output.stdout.replace(Some(capture));
+        output::install(self);
+
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| f(self)));
+        *self.context.handle.output.stdout.borrow_mut() = previous;
+        if let Err(payload) = result {
+            panic::resume_unwind(payload);
+        }
+
+        let output = buffer.borrow();
+        String::from_utf8_lossy(&output).into_owned()
+    }
+
+    /// Returns the underlying context for use with the `raw` module.
+    pub fn context(&self) -> &LuaContext {
+        &self.context
+    }
 }
 
 impl LuaIndex for State {
     fn read<K: LuaWrite,V: LuaRead>(&self,key: K) -> Result<V,()> {
-        let result;
         unsafe {
             ffi::lua_pushglobaltable(self.context.l);
-            LuaWrite::lua_write(&self.context,key);
-            ffi::lua_gettable(self.context.l,-2);
-            result = LuaRead::lua_read_index(&self.context,-1);
-            ffi::lua_pop(self.context.l,2);
+            protect::get(&self.context,key)
         }
-        result
     }
 
-    fn set<K: LuaWrite,V: LuaWrite>(&mut self,key: K,value: V) {
+    fn try_set<K: LuaWrite,V: LuaWrite>(&mut self,key: K,value: V) -> Result<(),LuaError> {
         unsafe {
             ffi::lua_pushglobaltable(self.context.l);
-            LuaWrite::lua_write(&self.context,key);
-            LuaWrite::lua_write(&self.context,value);
-            ffi::lua_settable(self.context.l,-3);
-            ffi::lua_pop(self.context.l,1);
+            protect::set(&self.context,key,value)
         }
     }
 }
@@ -112,35 +871,58 @@ pub trait LuaRef : Sized {
     fn get_context(&self) -> &LuaContext;
     fn get_refindex(&self) -> libc::c_int;
 
+    /// Takes ownership of a reference in the registry.
+    ///
+    /// # Safety
+    ///
+    /// The reference has to hold a value of the type and must not be owned by anything else.
     unsafe fn from_refindex(context: &LuaContext,refindex: libc::c_int) -> Self;
 
+    /// Pops the value on top of the stack and references it.
+    ///
+    /// # Safety
+    ///
+    /// The value on top of the stack has to be of the type.
+    ///
+    /// # Panics
+    ///
+    /// Panics if lua runs out of memory.
     unsafe fn ref_from_stack(context: &LuaContext) -> Self {
-        let refindex = lauxlib::luaL_ref(context.l,ffi::LUA_REGISTRYINDEX);
+        let refindex = protect::reference(context).expect("not enough memory");
         Self::from_refindex(context,refindex)
     }
 
+    /// Pushes the value of a reference.
+    ///
+    /// # Safety
+    ///
+    /// The stack needs room for one more value.
     unsafe fn push_reference(context: &LuaContext,refid: libc::c_int) {
-        ffi::lua_rawgeti(context.l, ffi::LUA_REGISTRYINDEX, refid);
+        ffi::lua_rawgeti(context.l, ffi::LUA_REGISTRYINDEX, refid as _);
     }
 
+    /// Pushes the referenced value.
+    ///
+    /// # Safety
+    ///
+    /// The stack needs room for one more value.
     unsafe fn write_self(&self) {
         Self::push_reference(self.get_context(),self.get_refindex());
     }
 }
 
 impl<T: LuaRef> LuaRead for T {
-    fn lua_read_index(context: &LuaContext,index: i32) -> Result<Self,()> {
-        let value = unsafe {
-            ffi::lua_pushvalue(context.l, index);
-            LuaRef::ref_from_stack(context)
-        };
-
-        Ok(value)
+    unsafe fn lua_read_index(context: &LuaContext,index: i32) -> Result<Self,()> {
+        ffi::lua_pushvalue(context.l, index);
+        match protect::reference(context) {
+            Ok(refindex) => Ok(Self::from_refindex(context,refindex)),
+            Err(_) => Err(()),
+        }
     }
 }
 
 
-impl<'a,T: LuaRef> LuaWrite for &'a T {
+impl<T: LuaRef> LuaWrite for &T {
     unsafe fn lua_write(context: &LuaContext,value: Self) {
         T::push_reference(context,value.get_refindex());
     }
@@ -153,82 +935,111 @@ pub struct Table {
     refindex: libc::c_int,
 }
 
-//TODO: proper implementation
+/// Prints the contents of the table, array part first.
+///
+/// Nested tables are printed recursively up to a fixed depth and cycles are shown as `<cycle>`.
+/// Metamethods are never invoked.
 impl std::fmt::Debug for Table {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "Table {{ {} }}", self.refindex)
+        let path = std::cell::RefCell::new(Vec::new());
+        unsafe { self.write_self() };
+        let index = unsafe { ffi::lua_gettop(self.context.l) };
+        let result = fmt::TableFmt::new(&self.context,index,&path).fmt(f);
+        unsafe { ffi::lua_pop(self.context.l,1) };
+        result
     }
 }
 
 impl Table {
 
     /// Creates a new Table and returns a reference to it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if lua runs out of memory.
     pub fn new(context: &LuaContext) -> Self {
-        unsafe { ffi::lua_newtable(context.l) };
-        let t = LuaRead::lua_read_index(context,-1).unwrap();
-        unsafe { ffi::lua_pop(context.l,1) };
-        t
+        unsafe {
+            let mut refindex = 0;
+            let created = protect::run(context,0,0,|l| {
+                ffi::lua_newtable(l);
+                refindex = lauxlib::luaL_ref(l,ffi::LUA_REGISTRYINDEX);
+                0
+            });
+            if created.is_err() {
+                panic!("not enough memory");
+            }
+            Table::from_refindex(context,refindex)
+        }
     }
-}
 
-impl LuaRef for Table {
-    fn get_context(&self) -> &LuaContext {
-        &self.context
+    /// Returns the length of the table without invoking `__len`.
+    pub fn len(&self) -> usize {
+        unsafe {
+            self.write_self();
+            let len = ffi::lua_rawlen(self.context.l,-1);
+            ffi::lua_pop(self.context.l,1);
+            len as usize
+        }
     }
 
-    fn get_refindex(&self) -> libc::c_int {
-        self.refindex
+    /// Returns true if the array part of the table is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
     }
 
-    unsafe fn from_refindex(context: &LuaContext,refindex: libc::c_int) -> Self {
-        Table {context: context.clone(), refindex: refindex }
+    /// Returns all key-value pairs of the table in the order of `next`.
+    ///
+    /// Fails if lua runs out of memory while reading them.
+    pub fn pairs(&self) -> Result<Vec<(LuaValue,LuaValue)>,()> {
+        let l = self.context.l;
+        let mut pairs = Vec::new();
+        unsafe {
+            self.write_self();
+            ffi::lua_pushnil(l);
+            while ffi::lua_next(l,-2) != 0 {
+                let key = LuaRead::lua_read_index(&self.context,-2);
+                let value = LuaRead::lua_read_index(&self.context,-1);
+                ffi::lua_pop(l,1);
+                match (key,value) {
+                    (Ok(key),Ok(value)) => pairs.push((key,value)),
+                    _ => {
+                        ffi::lua_pop(l,2);
+                        return Err(());
+                    },
+                }
+            }
+            ffi::lua_pop(l,1);
+        }
+        Ok(pairs)
     }
 }
 
+impl_lua_ref!(Table);
 
 impl LuaIndex for Table {
     fn read<K: LuaWrite,V: LuaRead>(&self,key: K) -> Result<V,()> {
-        let result;
-        unsafe {
-            LuaWrite::lua_write(&self.context,self);
-            LuaWrite::lua_write(&self.context,key);
-            ffi::lua_gettable(self.context.l,-2);
-            result = LuaRead::lua_read_index(&self.context,-1);
-            ffi::lua_pop(self.context.l,2);
-        }
-        result
-    }
-
-    fn set<K: LuaWrite,V: LuaWrite>(&mut self,key: K,value: V) {
         unsafe {
             self.write_self();
-            LuaWrite::lua_write(&self.context,key);
-            LuaWrite::lua_write(&self.context,value);
-            ffi::lua_settable(self.context.l,-3);
-            ffi::lua_pop(self.context.l,1);
+            protect::get(&self.context,key)
         }
     }
-}
 
-//TODO: find a way to implement these generally for LuaRef
-impl Clone for Table {
-    fn clone(&self) -> Self {
+    fn try_set<K: LuaWrite,V: LuaWrite>(&mut self,key: K,value: V) -> Result<(),LuaError> {
         unsafe {
             self.write_self();
-            Self::ref_from_stack(&self.context)
+            protect::set(&self.context,key,value)
         }
     }
 }
 
+/// Compares with `==`, which may invoke `__eq`.
 impl PartialEq for Table {
     fn eq(&self, other: &Table) -> bool {
-        // Push both references
-        unsafe { LuaWrite::lua_write(&self.context,self) };
-        unsafe { LuaWrite::lua_write(&self.context,other) };
-
-        // Compare both references on the stack lua_compare
-        let comp = unsafe { ffi::lua_compare(self.context.l,-2,-1,ffi::LUA_OPEQ) };
-        comp == 1
+        unsafe {
+            self.write_self();
+            other.write_self();
+            protect::equal_top(&self.context)
+        }
     }
 }
 
@@ -239,6 +1050,9 @@ pub enum LuaValue {
     LuaNumber(f64),
     LuaString(String),
     LuaTable(Table),
+    LuaFunction(Function),
+    LuaUserData(UserData),
+    LuaThread(Thread),
     Nil
 }
 
@@ -261,16 +1075,19 @@ impl LuaValue {
 }
 
 impl LuaRead for LuaValue {
-    fn lua_read_index(context: &LuaContext,index: i32) -> Result<Self,()> {
-        let tp = unsafe { ffi::lua_type(context.l,index) };
+    unsafe fn lua_read_index(context: &LuaContext,index: i32) -> Result<Self,()> {
+        let tp = ffi::lua_type(context.l,index);
+        // Reading references fails if lua runs out of memory
         Ok(match tp {
-            ffi::LUA_TBOOLEAN  => LuaValue::LuaBoolean(LuaRead::lua_read_index(context,index).unwrap()),
-            ffi::LUA_TNUMBER   => LuaValue::LuaNumber(LuaRead::lua_read_index(context,index).unwrap()),
-            ffi::LUA_TSTRING   => LuaValue::LuaString(LuaRead::lua_read_index(context,index).unwrap()),
-            ffi::LUA_TTABLE    => LuaValue::LuaTable(LuaRead::lua_read_index(context,index).unwrap()),
-            ffi::LUA_TFUNCTION => unimplemented!(),
-            ffi::LUA_TUSERDATA => unimplemented!(),
-            ffi::LUA_TNIL      => LuaValue::Nil,
+            ffi::LUA_TBOOLEAN  => LuaValue::LuaBoolean(LuaRead::lua_read_index(context,index)?),
+            ffi::LUA_TNUMBER   => LuaValue::LuaNumber(LuaRead::lua_read_index(context,index)?),
+            ffi::LUA_TSTRING   => LuaValue::LuaString(LuaRead::lua_read_index(context,index)?),
+            ffi::LUA_TTABLE    => LuaValue::LuaTable(LuaRead::lua_read_index(context,index)?),
+            ffi::LUA_TFUNCTION => LuaValue::LuaFunction(LuaRead::lua_read_index(context,index)?),
+            ffi::LUA_TUSERDATA | ffi::LUA_TLIGHTUSERDATA => LuaValue::LuaUserData(LuaRead::lua_read_index(context,index)?),
+            ffi::LUA_TTHREAD   => LuaValue::LuaThread(LuaRead::lua_read_index(context,index)?),
+            // Acceptable indices above the top are none
+            ffi::LUA_TNIL | ffi::LUA_TNONE => LuaValue::Nil,
             i => panic!("Unknown lua type \"{}\"",i)
         })
     }
@@ -278,11 +1095,21 @@ impl LuaRead for LuaValue {
 
 impl LuaWrite for LuaValue {
     unsafe fn lua_write(context: &LuaContext,value: Self) {
-        match value {
+        LuaWrite::lua_write(context,&value);
+    }
+}
+
+/// Writes without taking ownership, so nothing is dropped if lua raises an error.
+impl LuaWrite for &LuaValue {
+    unsafe fn lua_write(context: &LuaContext,value: Self) {
+        match *value {
             LuaValue::LuaBoolean(b) => LuaWrite::lua_write(context,b),
-            LuaValue::LuaNumber(n)  => LuaWrite::lua_write(context,n),
-            LuaValue::LuaString(st) => LuaWrite::lua_write(context,st.as_str()),
-            LuaValue::LuaTable(t)   => LuaWrite::lua_write(context,&t),
+            LuaValue::LuaNumber(n)  => write_number(context,n),
+            LuaValue::LuaString(ref st) => LuaWrite::lua_write(context,st.as_str()),
+            LuaValue::LuaTable(ref t)   => LuaWrite::lua_write(context,t),
+            LuaValue::LuaFunction(ref f) => LuaWrite::lua_write(context,f),
+            LuaValue::LuaUserData(ref u) => LuaWrite::lua_write(context,u),
+            LuaValue::LuaThread(ref t)  => LuaWrite::lua_write(context,t),
             LuaValue::Nil           => ffi::lua_pushnil(context.l),
         }
     }
@@ -290,17 +1117,29 @@ impl LuaWrite for LuaValue {
 
 /// A Trait which represent types which can be read from the lua context
 pub trait LuaRead: Sized {
-    fn lua_read_index(context: &LuaContext,index: i32) -> Result<Self,()>;
+    /// Reads the value at `index`, failing if it has the wrong type or lua runs out of memory.
+    ///
+    /// # Safety
+    ///
+    /// `index` has to be valid or acceptable for the stack of the context, references are
+    /// read with another value pushed, so the stack needs room for it.
+    unsafe fn lua_read_index(context: &LuaContext,index: i32) -> Result<Self,()>;
 }
 
 /// A Trait which represent types which can be pushed to the lua context
 pub trait LuaWrite {
+    /// Pushes the value.
+    ///
+    /// # Safety
+    ///
+    /// The stack needs room for the value and lua may raise memory errors, so this has to
+    /// run in protected mode unless the value is pushed without allocating.
     unsafe fn lua_write(context: &LuaContext,value: Self);
 }
 
 impl LuaRead for bool {
-    fn lua_read_index(context: &LuaContext,index: i32) -> Result<Self,()> {
-        let b = unsafe { ffi::lua_toboolean(context.l,index) };
+    unsafe fn lua_read_index(context: &LuaContext,index: i32) -> Result<Self,()> {
+        let b = ffi::lua_toboolean(context.l,index);
         Ok(b != 0)
     }
 }
@@ -313,26 +1152,79 @@ impl LuaWrite for bool {
 }
 
 impl LuaRead for String {
-    fn lua_read_index(context: &LuaContext,index: i32) -> Result<Self,()> {
-        let cstr = unsafe { ffi::lua_tostring(context.l,index) };
-        let s = unsafe { std::ffi::CStr::from_ptr(cstr).to_string_lossy().into_owned() };
-        Ok(s)
+    unsafe fn lua_read_index(context: &LuaContext,index: i32) -> Result<Self,()> {
+        // Numbers are converted into a new string, which allocates
+        if ffi::lua_type(context.l,index) == ffi::LUA_TNUMBER {
+            ffi::lua_pushvalue(context.l,index);
+            protect::run(context,1,1,|l| {
+                ffi::lua_tostring(l,1);
+                1
+            }).map_err(|_| ())?;
+            let s = LuaRead::lua_read_index(context,-1);
+            ffi::lua_pop(context.l,1);
+            return s;
+        }
+        let cstr = ffi::lua_tostring(context.l,index);
+        if cstr.is_null() {
+            return Err(());
+        }
+        Ok(std::ffi::CStr::from_ptr(cstr).to_string_lossy().into_owned())
     }
 }
 
-impl<'s> LuaWrite for &'s str {
+impl LuaWrite for &str {
     unsafe fn lua_write(context: &LuaContext,value: Self) {
-        let cstr = std::ffi::CString::new(value).unwrap();;
-        ffi::lua_pushstring(context.l,cstr.as_ptr());
+        ffi::lua_pushlstring(context.l,value.as_ptr() as *const libc::c_char,value.len() as libc::size_t);
     }
 }
 
+impl LuaWrite for String {
+    unsafe fn lua_write(context: &LuaContext,value: Self) {
+        LuaWrite::lua_write(context,value.as_str());
+    }
+}
+
+impl<T: LuaRead> LuaRead for Option<T> {
+    unsafe fn lua_read_index(context: &LuaContext,index: i32) -> Result<Self,()> {
+        let isnil = ffi::lua_isnoneornil(context.l,index);
+        if isnil {
+            Ok(None)
+        } else {
+            LuaRead::lua_read_index(context,index).map(Some)
+        }
+    }
+}
+
+impl<T: LuaWrite> LuaWrite for Option<T> {
+    unsafe fn lua_write(context: &LuaContext,value: Self) {
+        match value {
+            Some(v) => LuaWrite::lua_write(context,v),
+            None    => ffi::lua_pushnil(context.l),
+        }
+    }
+}
+
+/// Writes integral numbers as integers since lua 5.3, so they are printed without `.0`.
+#[cfg(any(feature = "lua53", feature = "lua54"))]
+unsafe fn write_number(context: &LuaContext,n: f64) {
+    if n.fract() == 0.0 && (-9223372036854775808.0..9223372036854775808.0).contains(&n) {
+        ffi::lua_pushinteger(context.l,n as ffi::lua_Integer);
+    } else {
+        ffi::lua_pushnumber(context.l,n);
+    }
+}
+
+#[cfg(not(any(feature = "lua53", feature = "lua54")))]
+unsafe fn write_number(context: &LuaContext,n: f64) {
+    ffi::lua_pushnumber(context.l,n);
+}
+
 macro_rules! impl_integer(
     ($t:ident) => (
         impl LuaRead for $t {
-            fn lua_read_index(context: &LuaContext,index: i32) -> Result<Self,()> {
+            unsafe fn lua_read_index(context: &LuaContext,index: i32) -> Result<Self,()> {
                 let mut isnum = 0;
-                let i = unsafe { ffi::lua_tointegerx(context.l,index,&mut isnum) };
+                let i = ffi::lua_tointegerx(context.l,index,&mut isnum);
                 match isnum {
                     0 => Err(()),
                     _ => Ok(i as $t),
@@ -355,9 +1247,9 @@ impl_integer!(i32);
 macro_rules! impl_unsigned(
     ($t:ident) => (
         impl LuaRead for $t {
-            fn lua_read_index(context: &LuaContext,index: i32) -> Result<Self,()> {
+            unsafe fn lua_read_index(context: &LuaContext,index: i32) -> Result<Self,()> {
                 let mut isnum = 0;
-                let u = unsafe { ffi::lua_tounsignedx(context.l,index,&mut isnum) };
+                let u = ffi::lua_tounsignedx(context.l,index,&mut isnum);
                 match isnum {
                     0 => Err(()),
                     _ => Ok(u as $t),
@@ -380,9 +1272,9 @@ impl_unsigned!(u32);
 macro_rules! impl_float(
     ($t:ident) => (
         impl LuaRead for $t {
-            fn lua_read_index(context: &LuaContext,index: i32) -> Result<Self,()> {
+            unsafe fn lua_read_index(context: &LuaContext,index: i32) -> Result<Self,()> {
                 let mut isnum = 0;
-                let f = unsafe { ffi::lua_tonumberx(context.l,index,&mut isnum) };
+                let f = ffi::lua_tonumberx(context.l,index,&mut isnum);
                 match isnum {
                     0 => Err(()),
                     _ => Ok(f as $t),
@@ -403,8 +1295,22 @@ impl_float!(f64);
 
 /// A trait which represents objects which can be indexed like e.g. a lua table.
 pub trait LuaIndex {
+    /// Reads `self[key]`, errors raised by metamethods are returned as `Err(())`.
     fn read<K: LuaWrite,V: LuaRead>(&self,key: K) -> Result<V,()>;
-    fn set<K: LuaWrite,V: LuaWrite>(&mut self,key: K,value: V);
+
+    /// Sets `self[key] = value` and returns errors raised by metamethods.
+    fn try_set<K: LuaWrite,V: LuaWrite>(&mut self,key: K,value: V) -> Result<(),LuaError>;
+
+    /// Sets `self[key] = value`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a metamethod raises an error.
+    fn set<K: LuaWrite,V: LuaWrite>(&mut self,key: K,value: V) {
+        if let Err(err) = self.try_set(key,value) {
+            panic!("error while setting value: {}",err);
+        }
+    }
 
     fn get<K: LuaWrite>(&self,key: K) -> LuaValue {
         self.read(key).unwrap()
@@ -0,0 +1,228 @@
+//! Opening single standard libraries and restricted environments.
+
+use std::ffi::CString;
+
+use ffi;
+use lauxlib;
+use libc;
+use State;
+
+macro_rules! open_functions(
+    ($($(#[$attr:meta])* $name:ident => $open:ident),*) => (
+        $(
+            $(#[$attr])*
+            extern "C-unwind" fn $name(l: *mut ffi::lua_State) -> libc::c_int {
+                unsafe { ffi::$open(l) }
+            }
+        )*
+    );
+);
+
+open_functions!(open_base => luaopen_base,
+                open_package => luaopen_package,
+                #[cfg(not(any(feature = "lua51", feature = "luajit")))]
+                open_coroutine => luaopen_coroutine,
+                open_table => luaopen_table,
+                open_io => luaopen_io,
+                open_os => luaopen_os,
+                open_string => luaopen_string,
+                #[cfg(feature = "lua52")]
+                open_bit32 => luaopen_bit32,
+                open_math => luaopen_math,
+                #[cfg(any(feature = "lua53", feature = "lua54"))]
+                open_utf8 => luaopen_utf8,
+                open_debug => luaopen_debug,
+                #[cfg(feature = "luajit")]
+                open_bit => luaopen_bit,
+                #[cfg(feature = "luajit")]
+                open_jit => luaopen_jit,
+                #[cfg(feature = "luajit")]
+                open_ffi => luaopen_ffi);
+
+/// The standard libraries of the selected lua version.
+///
+/// In lua 5.1 and LuaJIT the base library includes `coroutine`.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Library {
+    Base,
+    Package,
+    #[cfg(not(any(feature = "lua51", feature = "luajit")))]
+    Coroutine,
+    Table,
+    Io,
+    Os,
+    String,
+    #[cfg(feature = "lua52")]
+    Bit32,
+    Math,
+    #[cfg(any(feature = "lua53", feature = "lua54"))]
+    Utf8,
+    Debug,
+    #[cfg(feature = "luajit")]
+    Bit,
+    #[cfg(feature = "luajit")]
+    Jit,
+    /// The foreign function interface of LuaJIT, only opened on request.
+    #[cfg(feature = "luajit")]
+    Ffi,
+}
+
+impl Library {
+    /// All libraries in the order `luaL_openlibs` opens them.
+    #[cfg(feature = "lua51")]
+    pub const ALL: &'static [Library] = &[Library::Base,Library::Package,Library::Table,Library::Io,
+                                          Library::Os,Library::String,Library::Math,Library::Debug];
+    #[cfg(feature = "lua52")]
+    pub const ALL: &'static [Library] = &[Library::Base,Library::Package,Library::Coroutine,Library::Table,
+                                          Library::Io,Library::Os,Library::String,Library::Bit32,
+                                          Library::Math,Library::Debug];
+    #[cfg(any(feature = "lua53", feature = "lua54"))]
+    pub const ALL: &'static [Library] = &[Library::Base,Library::Package,Library::Coroutine,Library::Table,
+                                          Library::Io,Library::Os,Library::String,Library::Math,
+                                          Library::Utf8,Library::Debug];
+    #[cfg(feature = "luajit")]
+    pub const ALL: &'static [Library] = &[Library::Base,Library::Package,Library::Table,Library::Io,
+                                          Library::Os,Library::String,Library::Math,Library::Debug,
+                                          Library::Bit,Library::Jit];
+
+    /// The libraries which give no access to the file system, the process or the debug API.
+    #[cfg(feature = "lua51")]
+    pub const SAFE: &'static [Library] = &[Library::Base,Library::Table,Library::String,Library::Math];
+    #[cfg(feature = "lua52")]
+    pub const SAFE: &'static [Library] = &[Library::Base,Library::Coroutine,Library::Table,
+                                           Library::String,Library::Bit32,Library::Math];
+    #[cfg(any(feature = "lua53", feature = "lua54"))]
+    pub const SAFE: &'static [Library] = &[Library::Base,Library::Coroutine,Library::Table,
+                                           Library::String,Library::Math,Library::Utf8];
+    #[cfg(feature = "luajit")]
+    pub const SAFE: &'static [Library] = &[Library::Base,Library::Table,Library::String,
+                                           Library::Math,Library::Bit];
+
+    /// The name of the global table of the library, `_G` for the base library.
+    pub fn name(self) -> &'static str {
+        match self {
+            Library::Base      => "_G",
+            Library::Package   => "package",
+            #[cfg(not(any(feature = "lua51", feature = "luajit")))]
+            Library::Coroutine => "coroutine",
+            Library::Table     => "table",
+            Library::Io        => "io",
+            Library::Os        => "os",
+            Library::String    => "string",
+            #[cfg(feature = "lua52")]
+            Library::Bit32     => "bit32",
+            Library::Math      => "math",
+            #[cfg(any(feature = "lua53", feature = "lua54"))]
+            Library::Utf8      => "utf8",
+            Library::Debug     => "debug",
+            #[cfg(feature = "luajit")]
+            Library::Bit       => "bit",
+            #[cfg(feature = "luajit")]
+            Library::Jit       => "jit",
+            #[cfg(feature = "luajit")]
+            Library::Ffi       => "ffi",
+        }
+    }
+
+    /// Looks up a library by its name, the base library is also called `base`.
+    pub fn from_name(name: &str) -> Option<Library> {
+        match name {
+            "base" => Some(Library::Base),
+            #[cfg(feature = "luajit")]
+            "ffi" => Some(Library::Ffi),
+            _ => Library::ALL.iter().cloned().find(|lib| lib.name() == name),
+        }
+    }
+
+    fn open_function(self) -> ffi::lua_CFunction {
+        match self {
+            Library::Base      => open_base,
+            Library::Package   => open_package,
+            #[cfg(not(any(feature = "lua51", feature = "luajit")))]
+            Library::Coroutine => open_coroutine,
+            Library::Table     => open_table,
+            Library::Io        => open_io,
+            Library::Os        => open_os,
+            Library::String    => open_string,
+            #[cfg(feature = "lua52")]
+            Library::Bit32     => open_bit32,
+            Library::Math      => open_math,
+            #[cfg(any(feature = "lua53", feature = "lua54"))]
+            Library::Utf8      => open_utf8,
+            Library::Debug     => open_debug,
+            #[cfg(feature = "luajit")]
+            Library::Bit       => open_bit,
+            #[cfg(feature = "luajit")]
+            Library::Jit       => open_jit,
+            #[cfg(feature = "luajit")]
+            Library::Ffi       => open_ffi,
+        }
+    }
+}
+
+/// Opens the library like `luaL_openlibs` does.
+pub fn open(state: &State,lib: Library) {
+    let name = CString::new(lib.name()).unwrap();
+    unsafe {
+        lauxlib::luaL_requiref(state.context.l,name.as_ptr(),lib.open_function(),1);
+        ffi::lua_pop(state.context.l,1);
+    }
+}
+
+/// Removes the functions of the base library which access files or load bytecode.
+#[cfg(not(any(feature = "lua51", feature = "luajit")))]
+pub const SANDBOX: &'static str = "
+    local load = load
+    dofile, loadfile = nil, nil
+    function _G.load(chunk, name, mode, ...)
+        return load(chunk, name, 't', ...)
+    end
+";
+// LuaJIT only reports a wrong mode, binary strings are rejected first like in lua 5.1
+#[cfg(feature = "luajit")]
+pub const SANDBOX: &'static str = "
+    local load, loadstring, byte, type = load, loadstring, string.byte, type
+    dofile, loadfile = nil, nil
+    local binary = \"attempt to load a binary chunk (mode is 't')\"
+    function _G.load(chunk, name, mode, ...)
+        if type(chunk) == 'string' and byte(chunk, 1) == 27 then
+            return nil, binary
+        end
+        return load(chunk, name, 't', ...)
+    end
+    function _G.loadstring(chunk, name)
+        if byte(chunk, 1) == 27 then
+            return nil, binary
+        end
+        return loadstring(chunk, name, 't')
+    end
+";
+// Lua 5.1 loads any chunk, binary ones are rejected before loading them
+#[cfg(feature = "lua51")]
+pub const SANDBOX: &'static str = "
+    local loadstring, byte, concat, type = loadstring, string.byte, table.concat, type
+    dofile, loadfile = nil, nil
+    local function load_text(chunk, name)
+        if byte(chunk, 1) == 27 then
+            return nil, \"attempt to load a binary chunk (mode is 't')\"
+        end
+        return loadstring(chunk, name)
+    end
+    function _G.loadstring(chunk, name)
+        return load_text(chunk, name)
+    end
+    function _G.load(reader, name)
+        if type(reader) == 'string' then
+            return load_text(reader, name)
+        end
+        local parts = {}
+        while true do
+            local part = reader()
+            if part == nil or part == '' then
+                break
+            end
+            parts[#parts + 1] = part
+        end
+        return load_text(concat(parts), name or '=(load)')
+    end
+";
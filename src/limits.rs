@@ -0,0 +1,104 @@
+//! Memory and instruction limits of a state.
+
+use std::cell::Cell;
+use std::ptr;
+
+use ffi;
+use hook;
+use libc;
+use Handle;
+
+/// Bookkeeping of the allocator, the address is passed to lua as userdata.
+pub struct Memory {
+    pub used: Cell<usize>,
+    pub limit: Cell<usize>,
+}
+
+impl Memory {
+    pub fn new() -> Box<Memory> {
+        Box::new(Memory { used: Cell::new(0), limit: Cell::new(usize::MAX) })
+    }
+}
+
+/// The allocator of all states, fails allocations exceeding the memory limit.
+pub extern "C" fn alloc(ud: *mut libc::c_void,block: *mut libc::c_void,osize: libc::size_t,nsize: libc::size_t) -> *mut libc::c_void {
+    let memory = unsafe { &*(ud as *const Memory) };
+    // For new blocks osize encodes the type of the object
+    let old = if block.is_null() { 0 } else { osize };
+
+    if nsize == 0 {
+        if !block.is_null() {
+            unsafe { libc::free(block) };
+            memory.used.set(memory.used.get() - old);
+        }
+        return ptr::null_mut();
+    }
+
+    // Shrinking must never fail
+    if nsize > old && memory.used.get() - old + nsize > memory.limit.get() {
+        return ptr::null_mut();
+    }
+    let new = unsafe { libc::realloc(block,nsize as libc::size_t) };
+    if !new.is_null() {
+        memory.used.set(memory.used.get() - old + nsize);
+    }
+    new
+}
+
+/// Called on errors outside of any protected call.
+///
+/// Yuna makes every API call which can raise errors in protected mode, so this is only a last
+/// resort, e.g. for code using the raw API. Unwinding is not possible, so it aborts.
+pub extern "C-unwind" fn at_panic(l: *mut ffi::lua_State) -> libc::c_int {
+    unsafe {
+        let message = ffi::lua_tostring(l,-1);
+        let message = if message.is_null() {
+            String::from("error object is not a string")
+        } else {
+            ::std::ffi::CStr::from_ptr(message).to_string_lossy().into_owned()
+        };
+        eprintln!("PANIC: unprotected error in call to Lua API ({})",message);
+        libc::abort()
+    }
+}
+
+/// Instructions between two checks of the instruction limit.
+const COUNT_STEP: u64 = 1000;
+
+/// The state of the instruction limit.
+pub struct Instructions {
+    pub executed: Cell<u64>,
+    pub limit: Cell<Option<u64>>,
+    /// The instructions between two count events of the hook dispatcher.
+    pub period: Cell<u64>,
+}
+
+impl Instructions {
+    pub fn new() -> Instructions {
+        Instructions { executed: Cell::new(0), limit: Cell::new(None), period: Cell::new(0) }
+    }
+
+    /// The instructions between two checks the limit needs, `None` without a limit.
+    pub fn step(&self) -> Option<u64> {
+        self.limit.get().map(|limit| limit.clamp(1,COUNT_STEP))
+    }
+
+    /// Counts executed instructions and returns true once the limit is exceeded.
+    pub fn count(&self,instructions: u64) -> bool {
+        match self.limit.get() {
+            Some(limit) => {
+                let executed = self.executed.get() + instructions;
+                self.executed.set(executed);
+                executed > limit
+            },
+            None => false,
+        }
+    }
+}
+
+/// Sets or removes the instruction limit and resets the executed instructions.
+pub fn set_instruction_limit(handle: &Handle,limit: Option<u64>) {
+    handle.instructions.executed.set(0);
+    handle.instructions.limit.set(limit);
+    unsafe { hook::update(handle) };
+}
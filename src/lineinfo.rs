@@ -0,0 +1,402 @@
+//! Reading of the line information of chunks dumped by lua 5.3, 5.4 and LuaJIT.
+//!
+//! The lines of all functions in a chunk are collected the same way `lua_getinfo` with the
+//! `L` option reports them for a single function. Lua 5.1 and 5.2 chunks are read by the
+//! `bytecode` module, which also strips them.
+
+/// Reads the values of a chunk, numbers are stored in the byte order of the dumping host.
+struct Reader<'a> {
+    input: &'a [u8],
+    pos: usize,
+    #[cfg(not(feature = "lua54"))]
+    little_endian: bool,
+    lines: Vec<u32>,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self,n: usize) -> Option<&'a [u8]> {
+        let input = self.input;
+        let bytes = input.get(self.pos..self.pos.checked_add(n)?)?;
+        self.pos += n;
+        Some(bytes)
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        self.take(1).map(|bytes| bytes[0])
+    }
+
+    #[cfg(not(feature = "lua54"))]
+    fn read_raw(&mut self,size: usize) -> Option<u64> {
+        let bytes = self.take(size)?;
+        let mut value: u64 = 0;
+        for i in 0..size {
+            let byte = if self.little_endian { bytes[size - 1 - i] } else { bytes[i] };
+            value = value.checked_mul(256)? | byte as u64;
+        }
+        Some(value)
+    }
+
+    /// Checks that a count or size isn't larger than the chunk.
+    fn check_size(&self,value: u64) -> Option<usize> {
+        if value > self.input.len() as u64 {
+            return None;
+        }
+        Some(value as usize)
+    }
+}
+
+#[cfg(feature = "lua53")]
+const SIGNATURE: &'static [u8] = b"\x1bLua\x53\x00\x19\x93\r\n\x1a\n";
+
+/// Lua 5.3 writes `int` and `size_t` values as they are in memory.
+#[cfg(feature = "lua53")]
+struct Chunk<'a> {
+    reader: Reader<'a>,
+    int_size: usize,
+    size_t_size: usize,
+    instruction_size: usize,
+    integer_size: usize,
+    number_size: usize,
+}
+
+#[cfg(feature = "lua53")]
+impl<'a> Chunk<'a> {
+    fn new(chunk: &'a [u8]) -> Option<Chunk<'a>> {
+        if chunk.len() < SIGNATURE.len() + 5 || !chunk.starts_with(SIGNATURE) {
+            return None;
+        }
+        let sizes = &chunk[SIGNATURE.len()..SIGNATURE.len() + 5];
+        if sizes.iter().any(|&size| size == 0 || size > 8) {
+            return None;
+        }
+        let mut reader = Reader { input: chunk, pos: SIGNATURE.len() + 5, little_endian: true, lines: Vec::new() };
+        // `LUAC_INT` is 0x5678
+        reader.little_endian = *chunk.get(reader.pos)? == 0x78;
+        reader.take(sizes[3] as usize + sizes[4] as usize + 1)?;
+        Some(Chunk {
+            reader: reader,
+            int_size: sizes[0] as usize,
+            size_t_size: sizes[1] as usize,
+            instruction_size: sizes[2] as usize,
+            integer_size: sizes[3] as usize,
+            number_size: sizes[4] as usize,
+        })
+    }
+
+    fn read_int(&mut self) -> Option<usize> {
+        let value = self.reader.read_raw(self.int_size)?;
+        self.reader.check_size(value)
+    }
+
+    fn skip_string(&mut self) -> Option<()> {
+        let size = match self.reader.byte()? {
+            0xFF => {
+                let size = self.reader.read_raw(self.size_t_size)?;
+                self.reader.check_size(size)?
+            },
+            size => size as usize,
+        };
+        if size > 0 {
+            self.reader.take(size - 1)?;
+        }
+        Some(())
+    }
+
+    fn function(&mut self) -> Option<()> {
+        // Source, linedefined, lastlinedefined, numparams, is_vararg, maxstacksize
+        self.skip_string()?;
+        self.reader.take(2 * self.int_size + 3)?;
+
+        let code = self.read_int()?;
+        self.reader.take(code.checked_mul(self.instruction_size)?)?;
+
+        let constants = self.read_int()?;
+        for _ in 0..constants {
+            match self.reader.byte()? {
+                // Nil, boolean, float, integer, short and long string
+                0 => {},
+                1 => { self.reader.take(1)?; },
+                3 => { self.reader.take(self.number_size)?; },
+                0x13 => { self.reader.take(self.integer_size)?; },
+                4 | 0x14 => self.skip_string()?,
+                _ => return None,
+            }
+        }
+        let upvalues = self.read_int()?;
+        self.reader.take(upvalues.checked_mul(2)?)?;
+        let protos = self.read_int()?;
+        for _ in 0..protos {
+            self.function()?;
+        }
+
+        // Debug information: line info, local variables and upvalue names
+        let lines = self.read_int()?;
+        for _ in 0..lines {
+            let line = self.reader.read_raw(self.int_size)?;
+            self.reader.lines.push(line as u32);
+        }
+        let locals = self.read_int()?;
+        for _ in 0..locals {
+            self.skip_string()?;
+            self.reader.take(2 * self.int_size)?;
+        }
+        let names = self.read_int()?;
+        for _ in 0..names {
+            self.skip_string()?;
+        }
+        Some(())
+    }
+
+    fn read(mut self) -> Option<Vec<u32>> {
+        self.function()?;
+        if self.reader.pos != self.reader.input.len() {
+            return None;
+        }
+        Some(self.reader.lines)
+    }
+}
+
+#[cfg(feature = "lua54")]
+const SIGNATURE: &'static [u8] = b"\x1bLua\x54\x00\x19\x93\r\n\x1a\n";
+
+/// Marks instructions whose line is stored in the absolute line info.
+#[cfg(feature = "lua54")]
+const ABSLINEINFO: i8 = -0x80;
+
+/// Lua 5.4 writes sizes and `int` values as variable length numbers, with the most
+/// significant group first.
+#[cfg(feature = "lua54")]
+struct Chunk<'a> {
+    reader: Reader<'a>,
+    instruction_size: usize,
+    integer_size: usize,
+    number_size: usize,
+}
+
+#[cfg(feature = "lua54")]
+impl<'a> Chunk<'a> {
+    fn new(chunk: &'a [u8]) -> Option<Chunk<'a>> {
+        if chunk.len() < SIGNATURE.len() + 3 || !chunk.starts_with(SIGNATURE) {
+            return None;
+        }
+        let sizes = &chunk[SIGNATURE.len()..SIGNATURE.len() + 3];
+        if sizes.iter().any(|&size| size == 0 || size > 8) {
+            return None;
+        }
+        // The integer and number checking the format are skipped, line info has no byte order
+        let mut reader = Reader { input: chunk, pos: SIGNATURE.len() + 3, lines: Vec::new() };
+        reader.take(sizes[1] as usize + sizes[2] as usize + 1)?;
+        Some(Chunk {
+            reader: reader,
+            instruction_size: sizes[0] as usize,
+            integer_size: sizes[1] as usize,
+            number_size: sizes[2] as usize,
+        })
+    }
+
+    fn read_number(&mut self) -> Option<u64> {
+        let mut value: u64 = 0;
+        loop {
+            let byte = self.reader.byte()?;
+            value = value.checked_mul(128)? | u64::from(byte & 0x7f);
+            if byte & 0x80 != 0 {
+                return Some(value);
+            }
+        }
+    }
+
+    fn read_size(&mut self) -> Option<usize> {
+        let value = self.read_number()?;
+        self.reader.check_size(value)
+    }
+
+    fn skip_string(&mut self) -> Option<()> {
+        let size = self.read_size()?;
+        if size > 0 {
+            self.reader.take(size - 1)?;
+        }
+        Some(())
+    }
+
+    fn function(&mut self) -> Option<()> {
+        self.skip_string()?;
+        let linedefined = self.read_number()?;
+        self.read_number()?;
+        let header = self.reader.take(3)?;
+        let is_vararg = header[1] != 0;
+
+        let code = self.read_size()?;
+        self.reader.take(code.checked_mul(self.instruction_size)?)?;
+
+        let constants = self.read_size()?;
+        for _ in 0..constants {
+            match self.reader.byte()? {
+                // Nil, false, true, float, integer, short and long string
+                0 | 1 | 0x11 => {},
+                0x13 => { self.reader.take(self.number_size)?; },
+                3 => { self.reader.take(self.integer_size)?; },
+                4 | 0x14 => self.skip_string()?,
+                _ => return None,
+            }
+        }
+        let upvalues = self.read_size()?;
+        self.reader.take(upvalues.checked_mul(3)?)?;
+        let protos = self.read_size()?;
+        for _ in 0..protos {
+            self.function()?;
+        }
+
+        // Debug information: relative and absolute line info, local variables and upvalue names
+        let size = self.read_size()?;
+        let lineinfo = self.reader.take(size)?;
+        let absolute = self.read_size()?;
+        let mut abslineinfo = Vec::with_capacity(absolute);
+        for _ in 0..absolute {
+            abslineinfo.push((self.read_size()?,self.read_number()?));
+        }
+        self.lines(linedefined,is_vararg,lineinfo,&abslineinfo)?;
+        let locals = self.read_size()?;
+        for _ in 0..locals {
+            self.skip_string()?;
+            self.read_number()?;
+            self.read_number()?;
+        }
+        let names = self.read_size()?;
+        for _ in 0..names {
+            self.skip_string()?;
+        }
+        Some(())
+    }
+
+    /// Adds the line of every instruction, except the one preparing the arguments of vararg
+    /// functions like `lua_getinfo` does.
+    fn lines(&mut self,linedefined: u64,is_vararg: bool,lineinfo: &[u8],abslineinfo: &[(usize,u64)]) -> Option<()> {
+        let mut line = linedefined as i64;
+        for (pc,&delta) in lineinfo.iter().enumerate() {
+            if delta as i8 == ABSLINEINFO {
+                line = abslineinfo.iter().find(|&&(abspc,_)| abspc == pc)?.1 as i64;
+            } else {
+                line += i64::from(delta as i8);
+            }
+            if pc > 0 || !is_vararg {
+                self.reader.lines.push(line as u32);
+            }
+        }
+        Some(())
+    }
+
+    fn read(mut self) -> Option<Vec<u32>> {
+        self.function()?;
+        if self.reader.pos != self.reader.input.len() {
+            return None;
+        }
+        Some(self.reader.lines)
+    }
+}
+
+#[cfg(feature = "luajit")]
+const SIGNATURE: &'static [u8] = b"\x1bLJ";
+
+#[cfg(feature = "luajit")]
+const FLAG_BIG_ENDIAN: u64 = 0x01;
+#[cfg(feature = "luajit")]
+const FLAG_STRIP: u64 = 0x02;
+
+/// LuaJIT writes the functions of a chunk one after another, nested ones first, each
+/// prefixed with its size. Counts are unsigned LEB128 numbers.
+#[cfg(feature = "luajit")]
+struct Chunk<'a> {
+    reader: Reader<'a>,
+}
+
+#[cfg(feature = "luajit")]
+impl<'a> Chunk<'a> {
+    fn new(chunk: &'a [u8]) -> Option<Chunk<'a>> {
+        if !chunk.starts_with(SIGNATURE) {
+            return None;
+        }
+        let mut chunk = Chunk { reader: Reader { input: chunk, pos: SIGNATURE.len() + 1, little_endian: true, lines: Vec::new() } };
+        let flags = chunk.read_uleb128()?;
+        if flags & FLAG_STRIP != 0 {
+            return None;
+        }
+        chunk.reader.little_endian = flags & FLAG_BIG_ENDIAN == 0;
+        let name = chunk.read_size()?;
+        chunk.reader.take(name)?;
+        Some(chunk)
+    }
+
+    fn read_uleb128(&mut self) -> Option<u64> {
+        let mut value: u64 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.reader.byte()?;
+            if shift >= 64 {
+                return None;
+            }
+            value |= u64::from(byte & 0x7f) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+    }
+
+    fn read_size(&mut self) -> Option<usize> {
+        let value = self.read_uleb128()?;
+        self.reader.check_size(value)
+    }
+
+    fn function(&mut self,size: usize) -> Option<()> {
+        let end = self.reader.pos.checked_add(size)?;
+        // Flags, numparams, framesize, number of upvalues, of gc and number constants
+        self.reader.take(4)?;
+        self.read_size()?;
+        self.read_size()?;
+        let instructions = self.read_size()?;
+        let debug = self.read_size()?;
+        if debug > 0 {
+            let first = self.read_uleb128()?;
+            let count = self.read_uleb128()?;
+            let size = if count < 256 { 1 } else if count < 65536 { 2 } else { 4 };
+            // The line info starts the debug information at the end of the function
+            self.reader.pos = end.checked_sub(debug)?;
+            if instructions.checked_mul(size)? > debug {
+                return None;
+            }
+            for _ in 0..instructions {
+                let line = first.checked_add(self.reader.read_raw(size)?)?;
+                self.reader.lines.push(line as u32);
+            }
+        }
+        if end > self.reader.input.len() {
+            return None;
+        }
+        self.reader.pos = end;
+        Some(())
+    }
+
+    fn read(mut self) -> Option<Vec<u32>> {
+        loop {
+            match self.read_size()? {
+                0 => break,
+                size => self.function(size)?,
+            }
+        }
+        if self.reader.pos != self.reader.input.len() {
+            return None;
+        }
+        Some(self.reader.lines)
+    }
+}
+
+/// Returns the lines with code of all functions in a chunk dumped by lua, sorted and
+/// without duplicates.
+///
+/// Returns `None` if the bytes are no valid chunk or have no debug information.
+pub fn lines(chunk: &[u8]) -> Option<Vec<u32>> {
+    let mut lines = Chunk::new(chunk)?.read()?;
+    lines.sort();
+    lines.dedup();
+    Some(lines)
+}
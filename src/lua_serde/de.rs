@@ -0,0 +1,278 @@
+use std::vec;
+
+use serde::de::{self, DeserializeSeed, IntoDeserializer, Visitor};
+
+use super::{segment, Error, PathSegment};
+use {LuaIndex, LuaValue, Table};
+
+/// Deserializes rust values from a `LuaValue`.
+pub struct Deserializer {
+    value: LuaValue,
+}
+
+impl Deserializer {
+    pub fn new(value: LuaValue) -> Self {
+        Deserializer { value: value }
+    }
+
+    fn invalid_type<E: de::Expected>(&self,exp: &E) -> Error {
+        de::Error::invalid_type(unexpected(&self.value),exp)
+    }
+}
+
+/// Describes a lua value for error messages.
+fn unexpected<'a>(value: &'a LuaValue) -> de::Unexpected<'a> {
+    match *value {
+        LuaValue::LuaBoolean(b)     => de::Unexpected::Bool(b),
+        LuaValue::LuaNumber(n)      => de::Unexpected::Float(n),
+        LuaValue::LuaString(ref s)  => de::Unexpected::Str(s),
+        LuaValue::LuaTable(_)       => de::Unexpected::Map,
+        LuaValue::LuaFunction(_)    => de::Unexpected::Other("function"),
+        LuaValue::LuaUserData(_)    => de::Unexpected::Other("userdata"),
+        LuaValue::LuaThread(_)      => de::Unexpected::Other("thread"),
+        LuaValue::Nil               => de::Unexpected::Unit,
+    }
+}
+
+/// Returns all pairs of the table.
+fn pairs(table: &Table) -> Result<Vec<(LuaValue,LuaValue)>,Error> {
+    table.pairs().map_err(|()| Error::new("not enough memory"))
+}
+
+/// Checks if all keys of the table are 1..n.
+fn is_sequence(table: &Table) -> bool {
+    let len = table.len();
+    len > 0 && table.pairs().map(|pairs| pairs.len()) == Ok(len)
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self,visitor: V) -> Result<V::Value,Error> {
+        match self.value {
+            LuaValue::Nil              => visitor.visit_unit(),
+            LuaValue::LuaBoolean(b)    => visitor.visit_bool(b),
+            // Integers written by `to_lua` are exact up to 2^64, not only up to 2^53
+            LuaValue::LuaNumber(n)     => {
+                if n.fract() != 0.0 || !(-9223372036854775808.0..18446744073709551616.0).contains(&n) {
+                    visitor.visit_f64(n)
+                } else if n < 9223372036854775808.0 {
+                    visitor.visit_i64(n as i64)
+                } else {
+                    visitor.visit_u64(n as u64)
+                }
+            },
+            LuaValue::LuaString(s)     => visitor.visit_string(s),
+            LuaValue::LuaTable(t)      => {
+                if is_sequence(&t) {
+                    visitor.visit_seq(SeqAccess::new(t))
+                } else {
+                    visitor.visit_map(MapAccess::new(t)?)
+                }
+            },
+            ref value                  => Err(de::Error::invalid_type(unexpected(value),&visitor)),
+        }
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self,visitor: V) -> Result<V::Value,Error> {
+        self.deserialize_f64(visitor)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self,visitor: V) -> Result<V::Value,Error> {
+        match self.value {
+            LuaValue::LuaNumber(n) => visitor.visit_f64(n),
+            _ => Err(self.invalid_type(&visitor)),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self,visitor: V) -> Result<V::Value,Error> {
+        match self.value {
+            LuaValue::Nil => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self,visitor: V) -> Result<V::Value,Error> {
+        match self.value {
+            LuaValue::Nil => visitor.visit_unit(),
+            _ => Err(self.invalid_type(&visitor)),
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self,_name: &'static str,visitor: V) -> Result<V::Value,Error> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self,_name: &'static str,visitor: V) -> Result<V::Value,Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self,visitor: V) -> Result<V::Value,Error> {
+        match self.value {
+            LuaValue::LuaTable(t) => visitor.visit_seq(SeqAccess::new(t)),
+            _ => Err(self.invalid_type(&visitor)),
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self,_len: usize,visitor: V) -> Result<V::Value,Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(self,_name: &'static str,_len: usize,visitor: V) -> Result<V::Value,Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self,visitor: V) -> Result<V::Value,Error> {
+        match self.value {
+            LuaValue::LuaTable(t) => visitor.visit_map(MapAccess::new(t)?),
+            _ => Err(self.invalid_type(&visitor)),
+        }
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(self,_name: &'static str,_fields: &'static [&'static str],visitor: V) -> Result<V::Value,Error> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self,_name: &'static str,_variants: &'static [&'static str],visitor: V) -> Result<V::Value,Error> {
+        match self.value {
+            LuaValue::LuaString(variant) => visitor.visit_enum(variant.into_deserializer()),
+            LuaValue::LuaTable(t) => {
+                let mut pairs = pairs(&t)?;
+                if pairs.len() != 1 {
+                    return Err(de::Error::invalid_length(pairs.len(),&"a table with exactly one key"));
+                }
+                let (variant,value) = pairs.remove(0);
+                match variant {
+                    LuaValue::LuaString(variant) => visitor.visit_enum(EnumAccess { variant: variant, value: value }),
+                    other => Err(de::Error::invalid_type(unexpected(&other),&"a variant name")),
+                }
+            },
+            _ => Err(self.invalid_type(&visitor)),
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self,visitor: V) -> Result<V::Value,Error> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 char str string bytes byte_buf identifier
+    }
+}
+
+/// Iterates over the array part of a table.
+struct SeqAccess {
+    table: Table,
+    index: i64,
+    len: i64,
+}
+
+impl SeqAccess {
+    fn new(table: Table) -> Self {
+        let len = table.len() as i64;
+        SeqAccess { table: table, index: 0, len: len }
+    }
+}
+
+impl<'de> de::SeqAccess<'de> for SeqAccess {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self,seed: T) -> Result<Option<T::Value>,Error> {
+        if self.index >= self.len {
+            return Ok(None);
+        }
+        self.index += 1;
+
+        let value = self.table.get(self.index as f64);
+        let index = self.index;
+        seed.deserialize(Deserializer::new(value))
+            .map(Some)
+            .map_err(|e| e.within(PathSegment::Index(index)))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some((self.len - self.index) as usize)
+    }
+}
+
+/// Iterates over all pairs of a table.
+struct MapAccess {
+    pairs: vec::IntoIter<(LuaValue,LuaValue)>,
+    value: Option<(LuaValue,LuaValue)>,
+}
+
+impl MapAccess {
+    fn new(table: Table) -> Result<Self,Error> {
+        Ok(MapAccess { pairs: pairs(&table)?.into_iter(), value: None })
+    }
+}
+
+impl<'de> de::MapAccess<'de> for MapAccess {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self,seed: K) -> Result<Option<K::Value>,Error> {
+        match self.pairs.next() {
+            Some((key,value)) => {
+                let result = seed.deserialize(Deserializer::new(key.clone()))
+                                 .map_err(|e| e.within(segment(&key)));
+                self.value = Some((key,value));
+                result.map(Some)
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self,seed: V) -> Result<V::Value,Error> {
+        let (key,value) = self.value.take().expect("next_value_seed called before next_key_seed");
+        seed.deserialize(Deserializer::new(value))
+            .map_err(|e| e.within(segment(&key)))
+    }
+}
+
+/// Accesses the content of a tagged enum table.
+struct EnumAccess {
+    variant: String,
+    value: LuaValue,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumAccess {
+    type Error = Error;
+    type Variant = VariantAccess;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self,seed: V) -> Result<(V::Value,VariantAccess),Error> {
+        let segment = PathSegment::Field(self.variant.clone());
+        let variant = seed.deserialize(self.variant.into_deserializer())?;
+        Ok((variant,VariantAccess { value: self.value, segment: segment }))
+    }
+}
+
+struct VariantAccess {
+    value: LuaValue,
+    segment: PathSegment,
+}
+
+impl<'de> de::VariantAccess<'de> for VariantAccess {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(),Error> {
+        match self.value {
+            LuaValue::Nil => Ok(()),
+            other => Err(de::Error::invalid_type(unexpected(&other),&"unit variant")),
+        }
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self,seed: T) -> Result<T::Value,Error> {
+        let segment = self.segment;
+        seed.deserialize(Deserializer::new(self.value)).map_err(|e| e.within(segment))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self,_len: usize,visitor: V) -> Result<V::Value,Error> {
+        let segment = self.segment;
+        de::Deserializer::deserialize_seq(Deserializer::new(self.value),visitor).map_err(|e| e.within(segment))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self,_fields: &'static [&'static str],visitor: V) -> Result<V::Value,Error> {
+        let segment = self.segment;
+        de::Deserializer::deserialize_map(Deserializer::new(self.value),visitor).map_err(|e| e.within(segment))
+    }
+}
@@ -0,0 +1,130 @@
+//! Conversion between serde data structures and lua values.
+//!
+//! Structs and maps become tables, sequences become 1-based arrays, `Option::None` becomes nil
+//! and enums are externally tagged: unit variants become strings and all other variants a
+//! table with the variant name as the only key.
+
+mod ser;
+mod de;
+
+use std::error;
+use std::fmt;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use {LuaContext, LuaValue};
+
+/// Converts a serializable value into a `LuaValue`, creating tables in the given context.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate yuna;
+/// # fn main() {
+/// let context = yuna::LuaContext::new();
+///
+/// let value = yuna::to_lua(&context,&vec![1,2,3]).unwrap();
+/// let numbers : Vec<i32> = yuna::from_lua(value).unwrap();
+/// assert_eq!(numbers,vec![1,2,3]);
+/// # }
+/// ```
+pub fn to_lua<T: ?Sized + Serialize>(context: &LuaContext,value: &T) -> Result<LuaValue,Error> {
+    value.serialize(ser::Serializer::new(context))
+}
+
+/// Converts a `LuaValue` into any deserializable type.
+pub fn from_lua<T: DeserializeOwned>(value: LuaValue) -> Result<T,Error> {
+    T::deserialize(de::Deserializer::new(value))
+}
+
+/// One step in the path to the value which caused an `Error`.
+#[derive(Debug,Clone,PartialEq)]
+pub enum PathSegment {
+    /// A string key, e.g. a struct field.
+    Field(String),
+    /// An array index or numeric key.
+    Index(i64),
+    /// Any other key.
+    Key(String),
+}
+
+/// Error returned by `to_lua` and `from_lua`.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Error {
+    path: Vec<PathSegment>,
+    message: String,
+}
+
+impl Error {
+    fn new<S: Into<String>>(message: S) -> Self {
+        Error { path: Vec::new(), message: message.into() }
+    }
+
+    /// Prepends a path segment, used while the error bubbles up through nested values.
+    fn within(mut self,segment: PathSegment) -> Self {
+        self.path.insert(0,segment);
+        self
+    }
+
+    /// The path to the offending value, e.g. `enemies[3].hp`.
+    pub fn path(&self) -> String {
+        let mut path = String::new();
+        for segment in &self.path {
+            match *segment {
+                PathSegment::Field(ref name) => {
+                    if !path.is_empty() {
+                        path.push('.');
+                    }
+                    path.push_str(name);
+                },
+                PathSegment::Index(i) => path.push_str(&format!("[{}]",i)),
+                PathSegment::Key(ref key) => path.push_str(&format!("[{}]",key)),
+            }
+        }
+        path
+    }
+
+    /// The individual segments of the path.
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.path
+    }
+
+    /// The error message without the path.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f,"{}",self.message)
+        } else {
+            write!(f,"{}: {}",self.path(),self.message)
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl ::serde::ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::new(msg.to_string())
+    }
+}
+
+impl ::serde::de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::new(msg.to_string())
+    }
+}
+
+/// Returns the path segment which describes the given key.
+fn segment(key: &LuaValue) -> PathSegment {
+    match *key {
+        LuaValue::LuaString(ref s) => PathSegment::Field(s.clone()),
+        LuaValue::LuaNumber(n) if n.fract() == 0.0 => PathSegment::Index(n as i64),
+        ref other => PathSegment::Key(format!("{:?}",other)),
+    }
+}
@@ -0,0 +1,323 @@
+use serde::ser::{self, Serialize};
+
+use super::{segment, Error, PathSegment};
+use {LuaContext, LuaIndex, LuaValue, Table};
+
+/// Serializes rust values into `LuaValue`s.
+pub struct Serializer<'a> {
+    context: &'a LuaContext,
+}
+
+impl<'a> Serializer<'a> {
+    pub fn new(context: &'a LuaContext) -> Self {
+        Serializer { context: context }
+    }
+
+    /// Wraps a value in a single-key table, used for non-unit enum variants.
+    fn tagged(&self,variant: &str,value: LuaValue) -> LuaValue {
+        let mut table = Table::new(self.context);
+        table.set(variant,value);
+        LuaValue::LuaTable(table)
+    }
+}
+
+/// The error for integers which would lose precision as lua number.
+fn inexact<T: ::std::fmt::Display>(v: T) -> Error {
+    ser::Error::custom(format!("integer {} can't be represented exactly as a lua number",v))
+}
+
+impl<'a> ser::Serializer for Serializer<'a> {
+    type Ok = LuaValue;
+    type Error = Error;
+
+    type SerializeSeq = SerializeArray<'a>;
+    type SerializeTuple = SerializeArray<'a>;
+    type SerializeTupleStruct = SerializeArray<'a>;
+    type SerializeTupleVariant = SerializeVariant<SerializeArray<'a>>;
+    type SerializeMap = SerializeTable<'a>;
+    type SerializeStruct = SerializeTable<'a>;
+    type SerializeStructVariant = SerializeVariant<SerializeTable<'a>>;
+
+    fn serialize_bool(self,v: bool) -> Result<LuaValue,Error> {
+        Ok(LuaValue::LuaBoolean(v))
+    }
+
+    fn serialize_i8(self,v: i8) -> Result<LuaValue,Error> {
+        Ok(LuaValue::from_number(v))
+    }
+
+    fn serialize_i16(self,v: i16) -> Result<LuaValue,Error> {
+        Ok(LuaValue::from_number(v))
+    }
+
+    fn serialize_i32(self,v: i32) -> Result<LuaValue,Error> {
+        Ok(LuaValue::from_number(v))
+    }
+
+    fn serialize_i64(self,v: i64) -> Result<LuaValue,Error> {
+        let n = v as f64;
+        // 2^63 is rounded back to i64::MAX by the cast, so it is excluded separately
+        if n >= 9223372036854775808.0 || n as i64 != v {
+            return Err(inexact(v));
+        }
+        Ok(LuaValue::LuaNumber(n))
+    }
+
+    fn serialize_u8(self,v: u8) -> Result<LuaValue,Error> {
+        Ok(LuaValue::from_number(v))
+    }
+
+    fn serialize_u16(self,v: u16) -> Result<LuaValue,Error> {
+        Ok(LuaValue::from_number(v))
+    }
+
+    fn serialize_u32(self,v: u32) -> Result<LuaValue,Error> {
+        Ok(LuaValue::from_number(v))
+    }
+
+    fn serialize_u64(self,v: u64) -> Result<LuaValue,Error> {
+        let n = v as f64;
+        if n >= 18446744073709551616.0 || n as u64 != v {
+            return Err(inexact(v));
+        }
+        Ok(LuaValue::LuaNumber(n))
+    }
+
+    fn serialize_f32(self,v: f32) -> Result<LuaValue,Error> {
+        Ok(LuaValue::from_number(v))
+    }
+
+    fn serialize_f64(self,v: f64) -> Result<LuaValue,Error> {
+        Ok(LuaValue::from_number(v))
+    }
+
+    fn serialize_char(self,v: char) -> Result<LuaValue,Error> {
+        Ok(LuaValue::LuaString(v.to_string()))
+    }
+
+    fn serialize_str(self,v: &str) -> Result<LuaValue,Error> {
+        Ok(LuaValue::from_string(v))
+    }
+
+    fn serialize_bytes(self,v: &[u8]) -> Result<LuaValue,Error> {
+        let mut table = Table::new(self.context);
+        for (i,b) in v.iter().enumerate() {
+            table.set(i as i32 + 1,*b);
+        }
+        Ok(LuaValue::LuaTable(table))
+    }
+
+    fn serialize_none(self) -> Result<LuaValue,Error> {
+        Ok(LuaValue::Nil)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self,value: &T) -> Result<LuaValue,Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<LuaValue,Error> {
+        Ok(LuaValue::Nil)
+    }
+
+    fn serialize_unit_struct(self,_name: &'static str) -> Result<LuaValue,Error> {
+        Ok(LuaValue::Nil)
+    }
+
+    fn serialize_unit_variant(self,_name: &'static str,_index: u32,variant: &'static str) -> Result<LuaValue,Error> {
+        Ok(LuaValue::from_string(variant))
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(self,_name: &'static str,value: &T) -> Result<LuaValue,Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(self,_name: &'static str,_index: u32,variant: &'static str,value: &T) -> Result<LuaValue,Error> {
+        let inner = value.serialize(Serializer::new(self.context))
+                         .map_err(|e| e.within(PathSegment::Field(variant.to_string())))?;
+        Ok(self.tagged(variant,inner))
+    }
+
+    fn serialize_seq(self,_len: Option<usize>) -> Result<SerializeArray<'a>,Error> {
+        Ok(SerializeArray::new(self.context))
+    }
+
+    fn serialize_tuple(self,_len: usize) -> Result<SerializeArray<'a>,Error> {
+        Ok(SerializeArray::new(self.context))
+    }
+
+    fn serialize_tuple_struct(self,_name: &'static str,_len: usize) -> Result<SerializeArray<'a>,Error> {
+        Ok(SerializeArray::new(self.context))
+    }
+
+    fn serialize_tuple_variant(self,_name: &'static str,_index: u32,variant: &'static str,_len: usize) -> Result<Self::SerializeTupleVariant,Error> {
+        Ok(SerializeVariant { variant: variant, inner: SerializeArray::new(self.context) })
+    }
+
+    fn serialize_map(self,_len: Option<usize>) -> Result<SerializeTable<'a>,Error> {
+        Ok(SerializeTable::new(self.context))
+    }
+
+    fn serialize_struct(self,_name: &'static str,_len: usize) -> Result<SerializeTable<'a>,Error> {
+        Ok(SerializeTable::new(self.context))
+    }
+
+    fn serialize_struct_variant(self,_name: &'static str,_index: u32,variant: &'static str,_len: usize) -> Result<Self::SerializeStructVariant,Error> {
+        Ok(SerializeVariant { variant: variant, inner: SerializeTable::new(self.context) })
+    }
+}
+
+/// Builds a 1-based array.
+pub struct SerializeArray<'a> {
+    context: &'a LuaContext,
+    table: Table,
+    next: i64,
+}
+
+impl<'a> SerializeArray<'a> {
+    fn new(context: &'a LuaContext) -> Self {
+        SerializeArray { context: context, table: Table::new(context), next: 1 }
+    }
+
+    fn push<T: ?Sized + Serialize>(&mut self,value: &T) -> Result<(),Error> {
+        let value = value.serialize(Serializer::new(self.context))
+                         .map_err(|e| e.within(PathSegment::Index(self.next)))?;
+        self.table.set(self.next as f64,value);
+        self.next += 1;
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeSeq for SerializeArray<'a> {
+    type Ok = LuaValue;
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self,value: &T) -> Result<(),Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<LuaValue,Error> {
+        Ok(LuaValue::LuaTable(self.table))
+    }
+}
+
+impl<'a> ser::SerializeTuple for SerializeArray<'a> {
+    type Ok = LuaValue;
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self,value: &T) -> Result<(),Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<LuaValue,Error> {
+        Ok(LuaValue::LuaTable(self.table))
+    }
+}
+
+impl<'a> ser::SerializeTupleStruct for SerializeArray<'a> {
+    type Ok = LuaValue;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self,value: &T) -> Result<(),Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<LuaValue,Error> {
+        Ok(LuaValue::LuaTable(self.table))
+    }
+}
+
+/// Builds a table with arbitrary keys.
+pub struct SerializeTable<'a> {
+    context: &'a LuaContext,
+    table: Table,
+    key: Option<LuaValue>,
+}
+
+impl<'a> SerializeTable<'a> {
+    fn new(context: &'a LuaContext) -> Self {
+        SerializeTable { context: context, table: Table::new(context), key: None }
+    }
+
+    fn insert<T: ?Sized + Serialize>(&mut self,key: LuaValue,value: &T) -> Result<(),Error> {
+        let value = value.serialize(Serializer::new(self.context))
+                         .map_err(|e| e.within(segment(&key)))?;
+        self.table.set(key,value);
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeMap for SerializeTable<'a> {
+    type Ok = LuaValue;
+    type Error = Error;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self,key: &T) -> Result<(),Error> {
+        let key = key.serialize(Serializer::new(self.context))?;
+        match key {
+            LuaValue::Nil => Err(Error::new("table keys must not be nil")),
+            LuaValue::LuaNumber(n) if n.is_nan() => Err(Error::new("table keys must not be NaN")),
+            key => {
+                self.key = Some(key);
+                Ok(())
+            }
+        }
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self,value: &T) -> Result<(),Error> {
+        let key = self.key.take().expect("serialize_value called before serialize_key");
+        self.insert(key,value)
+    }
+
+    fn end(self) -> Result<LuaValue,Error> {
+        Ok(LuaValue::LuaTable(self.table))
+    }
+}
+
+impl<'a> ser::SerializeStruct for SerializeTable<'a> {
+    type Ok = LuaValue;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self,key: &'static str,value: &T) -> Result<(),Error> {
+        self.insert(LuaValue::from_string(key),value)
+    }
+
+    fn end(self) -> Result<LuaValue,Error> {
+        Ok(LuaValue::LuaTable(self.table))
+    }
+}
+
+/// Wraps the serialized variant content in a table keyed by the variant name.
+pub struct SerializeVariant<S> {
+    variant: &'static str,
+    inner: S,
+}
+
+impl<'a> ser::SerializeTupleVariant for SerializeVariant<SerializeArray<'a>> {
+    type Ok = LuaValue;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self,value: &T) -> Result<(),Error> {
+        let variant = self.variant;
+        self.inner.push(value).map_err(|e| e.within(PathSegment::Field(variant.to_string())))
+    }
+
+    fn end(self) -> Result<LuaValue,Error> {
+        let serializer = Serializer::new(self.inner.context);
+        Ok(serializer.tagged(self.variant,LuaValue::LuaTable(self.inner.table)))
+    }
+}
+
+impl<'a> ser::SerializeStructVariant for SerializeVariant<SerializeTable<'a>> {
+    type Ok = LuaValue;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self,key: &'static str,value: &T) -> Result<(),Error> {
+        let variant = self.variant;
+        self.inner.insert(LuaValue::from_string(key),value)
+                  .map_err(|e| e.within(PathSegment::Field(variant.to_string())))
+    }
+
+    fn end(self) -> Result<LuaValue,Error> {
+        let serializer = Serializer::new(self.inner.context);
+        Ok(serializer.tagged(self.variant,LuaValue::LuaTable(self.inner.table)))
+    }
+}
@@ -0,0 +1,299 @@
+//! Redirection of the output of `print`, `io.write` and the `write` method of `io.stdout` and
+//! `io.stderr`.
+//!
+//! The standard file objects stay in place, only the functions writing to them are wrapped.
+//! The wrappers call the original functions unless a writer is set for the stream, so files
+//! opened by scripts and `io.output(file)` work as usual.
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::slice;
+
+use ffi;
+use function;
+use lauxlib;
+use libc;
+use protect;
+use State;
+
+/// The writers script output is redirected to, `None` is the process' stream.
+#[derive(Default)]
+pub struct Sinks {
+    pub stdout: RefCell<Option<Box<dyn Write>>>,
+    pub stderr: RefCell<Option<Box<dyn Write>>>,
+}
+
+#[derive(Clone,Copy)]
+enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// Returns the writer of the stream if it is redirected.
+unsafe fn redirected<'a>(l: *mut ffi::lua_State,stream: Stream) -> Option<&'a RefCell<Option<Box<dyn Write>>>> {
+    let handle = ::handle_ptr(l);
+    if handle.is_null() {
+        return None;
+    }
+    let sink = match stream {
+        Stream::Stdout => &(*handle).output.stdout,
+        Stream::Stderr => &(*handle).output.stderr,
+    };
+    match sink.try_borrow() {
+        Ok(ref writer) if writer.is_some() => Some(sink),
+        _ => None,
+    }
+}
+
+/// Returns the stream if the value at the index is the file object of `io.stdout` or
+/// `io.stderr`, which are the upvalues 2 and 3.
+unsafe fn file_stream(l: *mut ffi::lua_State,index: libc::c_int) -> Option<Stream> {
+    if ffi::lua_rawequal(l,index,ffi::lua_upvalueindex(2)) != 0 {
+        Some(Stream::Stdout)
+    } else if ffi::lua_rawequal(l,index,ffi::lua_upvalueindex(3)) != 0 {
+        Some(Stream::Stderr)
+    } else {
+        None
+    }
+}
+
+/// Calls the original function, the first upvalue, with all arguments.
+unsafe fn call_original(l: *mut ffi::lua_State) -> libc::c_int {
+    ffi::lua_pushvalue(l,ffi::lua_upvalueindex(1));
+    ffi::lua_insert(l,1);
+    ffi::lua_call(l,ffi::lua_gettop(l) - 1,ffi::MULTRET);
+    ffi::lua_gettop(l)
+}
+
+/// Raises the usual error of `write` for arguments from `first` on which are neither
+/// strings nor numbers.
+unsafe fn check_args(l: *mut ffi::lua_State,first: libc::c_int) {
+    for i in first..ffi::lua_gettop(l) + 1 {
+        match ffi::lua_type(l,i) {
+            ffi::LUA_TSTRING | ffi::LUA_TNUMBER => {},
+            _ => { lauxlib::luaL_checklstring(l,i,::std::ptr::null_mut()); },
+        }
+    }
+}
+
+/// Formats a number like `io.write`, which unlike `tostring` prints floats without `.0`.
+unsafe fn format_number(l: *mut ffi::lua_State,index: libc::c_int,buf: &mut [u8; 64]) -> &[u8] {
+    #[cfg(any(feature = "lua53", feature = "lua54"))]
+    {
+        if ffi::lua_isinteger(l,index) != 0 {
+            let n = libc::snprintf(buf.as_mut_ptr() as *mut libc::c_char,buf.len(),b"%lld\0".as_ptr() as *const libc::c_char,
+                                   ffi::lua_tointeger(l,index) as libc::c_longlong);
+            return &buf[..n as usize];
+        }
+    }
+    let n = libc::snprintf(buf.as_mut_ptr() as *mut libc::c_char,buf.len(),b"%.14g\0".as_ptr() as *const libc::c_char,
+                           ffi::lua_tonumber(l,index));
+    &buf[..n as usize]
+}
+
+/// Writes the raw bytes of the strings and numbers from `first` on, separated by `separator`.
+///
+/// Everything is written at once, so writers see whole lines of `print`. No lua function
+/// raising errors is called, so the borrow of the writer is always released.
+unsafe fn write_args(l: *mut ffi::lua_State,first: libc::c_int,separator: &[u8],end: &[u8],sink: &RefCell<Option<Box<dyn Write>>>) -> io::Result<()> {
+    let mut data = Vec::new();
+    for i in first..ffi::lua_gettop(l) + 1 {
+        if i > first {
+            data.extend_from_slice(separator);
+        }
+        let mut buf = [0; 64];
+        let bytes = match ffi::lua_type(l,i) {
+            ffi::LUA_TNUMBER => format_number(l,i,&mut buf),
+            _ => {
+                let mut len = 0;
+                let p = ffi::lua_tolstring(l,i,&mut len);
+                slice::from_raw_parts(p as *const u8,len)
+            },
+        };
+        data.extend_from_slice(bytes);
+    }
+    data.extend_from_slice(end);
+
+    let mut sink = sink.try_borrow_mut().map_err(|_| io::Error::other("output is already in use"))?;
+    match *sink {
+        Some(ref mut writer) => writer.write_all(&data),
+        None => Ok(()),
+    }
+}
+
+/// Runs the rust side of a wrapper, which may panic in the writer.
+///
+/// Pushes the file at `file` on success and `nil, message, code` like lua on io errors.
+/// A panic is raised as lua error, nothing is alive anymore once that happens.
+unsafe fn finish<F: FnOnce() -> io::Result<()>>(l: *mut ffi::lua_State,file: libc::c_int,f: F) -> libc::c_int {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => {
+            ffi::lua_pushvalue(l,file);
+            return 1;
+        },
+        Ok(Err(err)) => {
+            ffi::lua_pushnil(l);
+            if protect::push_bytes(l,err.to_string().as_bytes()) == ffi::LUA_OK {
+                ffi::lua_pushinteger(l,err.raw_os_error().unwrap_or(0) as ffi::lua_Integer);
+                return 3;
+            }
+        },
+        Err(payload) => {
+            function::push_panic(l,payload);
+        },
+    }
+    // The memory error or the panic is on top
+    ffi::lua_error(l)
+}
+
+/// `print` writing to the redirected standard output.
+///
+/// Like lua before 5.4 the arguments are converted with the global `tostring`.
+extern "C-unwind" fn print(l: *mut ffi::lua_State) -> libc::c_int {
+    unsafe {
+        let sink = match redirected(l,Stream::Stdout) {
+            Some(sink) => sink,
+            None => return call_original(l),
+        };
+        for i in 1..ffi::lua_gettop(l) + 1 {
+            ffi::lua_getglobal(l,b"tostring\0".as_ptr() as *const libc::c_char);
+            ffi::lua_pushvalue(l,i);
+            ffi::lua_call(l,1,1);
+            if ffi::lua_type(l,-1) != ffi::LUA_TSTRING {
+                protect::push_bytes(l,b"'tostring' must return a string to 'print'");
+                ffi::lua_error(l);
+            }
+            ffi::lua_replace(l,i);
+        }
+        finish(l,1,|| write_args(l,1,b"\t",b"\n",sink));
+        0
+    }
+}
+
+/// `io.write` writing to the redirected standard output while it is the default output.
+///
+/// The upvalues are the original `io.write`, `io.output` and `io.stdout`.
+extern "C-unwind" fn io_write(l: *mut ffi::lua_State) -> libc::c_int {
+    unsafe {
+        let sink = match redirected(l,Stream::Stdout) {
+            Some(sink) => sink,
+            None => return call_original(l),
+        };
+        ffi::lua_pushvalue(l,ffi::lua_upvalueindex(2));
+        ffi::lua_call(l,0,1);
+        let default = ffi::lua_rawequal(l,-1,ffi::lua_upvalueindex(3)) != 0;
+        ffi::lua_pop(l,1);
+        if !default {
+            return call_original(l);
+        }
+        check_args(l,1);
+        finish(l,ffi::lua_upvalueindex(3),|| write_args(l,1,b"",b"",sink))
+    }
+}
+
+/// The `write` method of files, redirected for `io.stdout` and `io.stderr`.
+///
+/// The upvalues are the original method and the two file objects.
+extern "C-unwind" fn file_write(l: *mut ffi::lua_State) -> libc::c_int {
+    unsafe {
+        let sink = match file_stream(l,1).and_then(|stream| redirected(l,stream)) {
+            Some(sink) => sink,
+            None => return call_original(l),
+        };
+        check_args(l,2);
+        finish(l,1,|| write_args(l,2,b"",b"",sink))
+    }
+}
+
+/// The `flush` method of files, redirected like `file_write`.
+extern "C-unwind" fn file_flush(l: *mut ffi::lua_State) -> libc::c_int {
+    unsafe {
+        let sink = match file_stream(l,1).and_then(|stream| redirected(l,stream)) {
+            Some(sink) => sink,
+            None => return call_original(l),
+        };
+        finish(l,1,|| {
+            let mut sink = sink.try_borrow_mut().map_err(|_| io::Error::other("output is already in use"))?;
+            match *sink {
+                Some(ref mut writer) => writer.flush(),
+                None => Ok(()),
+            }
+        })
+    }
+}
+
+/// Replaces the field at the key of the table at `index` with the wrapper `f`, whose
+/// upvalues are the original value and the `nupvalues - 1` values on top of the stack.
+///
+/// Missing functions and functions which are wrapped already are left alone.
+unsafe fn wrap(l: *mut ffi::lua_State,index: libc::c_int,key: &[u8],f: ffi::lua_CFunction,nupvalues: libc::c_int) {
+    let index = ffi::lua_absindex(l,index);
+    ffi::lua_getfield(l,index,key.as_ptr() as *const libc::c_char);
+    let wrapped = ffi::lua_tocfunction(l,-1).map(|g| g as usize) == Some(f as usize);
+    if !ffi::lua_isfunction(l,-1) || wrapped {
+        ffi::lua_pop(l,nupvalues);
+        return;
+    }
+    ffi::lua_insert(l,-nupvalues);
+    ffi::lua_pushcclosure(l,f,nupvalues);
+    ffi::lua_setfield(l,index,key.as_ptr() as *const libc::c_char);
+}
+
+/// Wraps `print` and the writing functions of the io library.
+///
+/// Only functions of opened libraries are wrapped. Runs in protected mode.
+unsafe fn install_wrappers(l: *mut ffi::lua_State) -> libc::c_int {
+    ffi::lua_pushglobaltable(l);
+    wrap(l,-1,b"print\0",print,1);
+
+    ffi::lua_getfield(l,-1,b"io\0".as_ptr() as *const libc::c_char);
+    if !ffi::lua_istable(l,-1) {
+        return 0;
+    }
+    let io = ffi::lua_gettop(l);
+    ffi::lua_getfield(l,io,b"stdout\0".as_ptr() as *const libc::c_char);
+    ffi::lua_getfield(l,io,b"stderr\0".as_ptr() as *const libc::c_char);
+    let stdout = io + 1;
+    let stderr = io + 2;
+
+    ffi::lua_getfield(l,io,b"output\0".as_ptr() as *const libc::c_char);
+    ffi::lua_pushvalue(l,stdout);
+    wrap(l,io,b"write\0",io_write,3);
+
+    // The methods are shared by all files
+    if lauxlib::luaL_getmetafield(l,stdout,b"__index\0".as_ptr() as *const libc::c_char) != 0 && ffi::lua_istable(l,-1) {
+        for &(method,f) in &[(&b"write\0"[..],file_write as ffi::lua_CFunction),(&b"flush\0"[..],file_flush)] {
+            ffi::lua_pushvalue(l,stdout);
+            ffi::lua_pushvalue(l,stderr);
+            wrap(l,-3,method,f,3);
+        }
+    }
+    0
+}
+
+/// Makes `print`, `io.write` and the standard files write to the writers set on the state.
+///
+/// Only functions of opened libraries are replaced.
+pub fn install(state: &mut State) {
+    unsafe {
+        let top = ffi::lua_gettop(state.context.l);
+        // Without memory for the wrappers the output is just not redirected
+        let _ = protect::run(&state.context,0,0,|l| install_wrappers(l));
+        ffi::lua_settop(state.context.l,top);
+    }
+}
+
+/// A writer appending to a shared buffer.
+pub struct Buffer(pub ::std::rc::Rc<RefCell<Vec<u8>>>);
+
+impl Write for Buffer {
+    fn write(&mut self,data: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,86 @@
+//! Building `package.path` and `package.cpath` from file system paths.
+
+use std::error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Errors of the functions configuring `package.path` and `package.cpath`.
+#[derive(Debug,Clone,PartialEq)]
+pub enum PackagePathError {
+    /// The path contains `;`, which lua uses to separate templates.
+    Separator(PathBuf),
+    /// The path is a directory containing `?`, which lua replaces with the module name.
+    Placeholder(PathBuf),
+    /// The path is not valid unicode.
+    NotUnicode(PathBuf),
+    /// The package library is not opened.
+    NoPackageLibrary,
+}
+
+impl fmt::Display for PackagePathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PackagePathError::Separator(ref path) => write!(f,"path contains ';': {}",path.display()),
+            PackagePathError::Placeholder(ref path) => write!(f,"directory contains '?': {}",path.display()),
+            PackagePathError::NotUnicode(ref path) => write!(f,"path is not valid unicode: {}",path.display()),
+            PackagePathError::NoPackageLibrary => f.write_str("package library not opened"),
+        }
+    }
+}
+
+impl error::Error for PackagePathError {}
+
+/// The field of the package library holding the searchers, `loaders` before lua 5.2.
+#[cfg(any(feature = "lua51", feature = "luajit"))]
+pub const SEARCHERS: &'static str = "loaders";
+#[cfg(not(any(feature = "lua51", feature = "luajit")))]
+pub const SEARCHERS: &'static str = "searchers";
+
+/// The kind of modules a search path is used for.
+#[derive(Clone,Copy)]
+pub enum Kind {
+    Lua,
+    C,
+}
+
+/// Converts a path into the templates lua searches.
+///
+/// Paths containing `?` are used as template, directories are expanded to the usual file names.
+/// Lua can't escape `;` and `?`, a directory containing either is an error.
+pub fn templates(path: &Path,kind: Kind) -> Result<String,PackagePathError> {
+    let s = path.to_str().ok_or_else(|| PackagePathError::NotUnicode(path.to_owned()))?;
+    if s.contains(';') {
+        return Err(PackagePathError::Separator(path.to_owned()));
+    }
+    if s.contains('?') {
+        if path.is_dir() {
+            return Err(PackagePathError::Placeholder(path.to_owned()));
+        }
+        return Ok(s.to_owned());
+    }
+    let dir = s.trim_end_matches(['/', '\\']);
+    let sep = if dir.contains('\\') && !dir.contains('/') { '\\' } else { '/' };
+    Ok(match kind {
+        Kind::Lua => format!("{0}{1}?.lua;{0}{1}?{1}init.lua",dir,sep),
+        Kind::C if cfg!(windows) => format!("{}{}?.dll",dir,sep),
+        Kind::C => format!("{}{}?.so",dir,sep),
+    })
+}
+
+/// Joins the templates of all paths with `;`.
+pub fn join<P: AsRef<Path>>(paths: &[P],kind: Kind) -> Result<String,PackagePathError> {
+    let templates = paths.iter()
+                         .map(|path| templates(path.as_ref(),kind))
+                         .collect::<Result<Vec<_>,_>>()?;
+    Ok(templates.join(";"))
+}
+
+/// Finds the file of a module like `package.searchpath`, for lua versions whose searchers
+/// don't return it.
+#[cfg(any(feature = "lua51", feature = "luajit"))]
+pub fn search_path(name: &str,path: &str) -> Option<PathBuf> {
+    let name = name.replace('.',"/");
+    path.split(';')
+        .map(|template| PathBuf::from(template.replace('?',&name)))
+        .find(|file| file.is_file())
+}
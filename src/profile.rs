@@ -0,0 +1,285 @@
+//! A sampling profiler driven by the count hook.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use error;
+use ffi;
+use function;
+use libc;
+
+/// The kind of function a frame belongs to.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Hash)]
+pub enum FrameKind {
+    Lua,
+    /// A function created with `State::create_function` or similar.
+    Rust,
+    /// Any other C function, e.g. of the standard library.
+    C,
+}
+
+/// Identifies a function across samples.
+#[derive(Clone,PartialEq,Eq,Hash)]
+enum FrameKey {
+    Lua(String,i32),
+    Native(FrameKind,String),
+}
+
+/// A function seen while sampling.
+#[derive(Debug,Clone)]
+struct Frame {
+    name: Option<String>,
+    kind: FrameKind,
+    source: String,
+    line: Option<u32>,
+}
+
+impl Frame {
+    /// The name used in collapsed stacks, e.g. `update (game.lua:12)` or `spawn [rust]`.
+    fn label(&self) -> String {
+        let name = self.name.as_deref().unwrap_or("?");
+        let label = match (self.kind,self.line) {
+            (FrameKind::Lua,Some(line)) => format!("{} ({}:{})",name,self.source,line),
+            (FrameKind::Lua,None) => format!("main chunk ({})",self.source),
+            (FrameKind::Rust,_) => format!("{} [rust]",name),
+            (FrameKind::C,_) => format!("{} [C]",name),
+        };
+        // Semicolons separate frames and newlines samples in the collapsed format
+        label.replace(';',":").replace('\n'," ")
+    }
+}
+
+/// How often a stack was sampled.
+#[derive(Clone,Copy,Default)]
+struct Samples {
+    count: u64,
+    time: Duration,
+}
+
+/// Samples taken while the profiler runs.
+pub struct Profiler {
+    interval: u64,
+    /// Instructions counted towards the next sample.
+    pending: u64,
+    last: Instant,
+    frames: Vec<Frame>,
+    keys: HashMap<FrameKey,usize>,
+    stacks: HashMap<Vec<usize>,Samples>,
+}
+
+impl Profiler {
+    pub fn new(interval: u32) -> Profiler {
+        Profiler {
+            interval: u64::from(interval.max(1)),
+            pending: 0,
+            last: Instant::now(),
+            frames: Vec::new(),
+            keys: HashMap::new(),
+            stacks: HashMap::new(),
+        }
+    }
+
+    pub fn interval(&self) -> u64 {
+        self.interval
+    }
+
+    /// Counts executed instructions, sampling the stack of `l` once the interval is reached.
+    pub unsafe fn count(&mut self,l: *mut ffi::lua_State,instructions: u64) {
+        self.pending += instructions;
+        if self.pending < self.interval {
+            return;
+        }
+        self.pending %= self.interval;
+        self.sample(l,0);
+    }
+
+    /// Charges the time since the previous sample to the stack of `l` from `level` on.
+    unsafe fn sample(&mut self,l: *mut ffi::lua_State,level: libc::c_int) {
+        let now = Instant::now();
+        let elapsed = now - self.last;
+        self.last = now;
+
+        let stack = self.stack(l,level);
+        // Time before a callback called directly from rust is not spent in lua
+        if stack.is_empty() {
+            return;
+        }
+        let samples = self.stacks.entry(stack).or_default();
+        samples.count += 1;
+        samples.time += elapsed;
+    }
+
+    /// Returns the frames active on `l` from `level` on, the outermost first.
+    ///
+    /// Runs in the hook while the profiler is borrowed, so it only calls lua functions that
+    /// can't raise errors.
+    unsafe fn stack(&mut self,l: *mut ffi::lua_State,mut level: libc::c_int) -> Vec<usize> {
+        let mut stack = Vec::new();
+        let mut ar: ffi::lua_Debug = ::std::mem::zeroed();
+        while ffi::lua_getstack(l,level,&mut ar) != 0 {
+            ffi::lua_getinfo(l,b"Snf\0".as_ptr() as *const libc::c_char,&mut ar);
+            let rust = function::is_callback(l,-1);
+            ffi::lua_pop(l,1);
+            let what = error::read_cstr(ar.what).unwrap_or_default();
+            let name = error::read_cstr(ar.name);
+            let (key,frame) = if what == "C" {
+                let kind = if rust { FrameKind::Rust } else { FrameKind::C };
+                let name = name.unwrap_or_else(|| String::from("?"));
+                (FrameKey::Native(kind,name.clone()),Frame { name: Some(name), kind: kind, source: String::from("[C]"), line: None })
+            } else {
+                let source = error::read_cstr(ar.short_src.as_ptr()).unwrap_or_default();
+                let line = if what == "main" { None } else { Some(ar.linedefined.max(0) as u32) };
+                (FrameKey::Lua(source.clone(),ar.linedefined),Frame { name: name, kind: FrameKind::Lua, source: source, line: line })
+            };
+            let id = match self.keys.get(&key) {
+                Some(&id) => {
+                    // The name depends on the caller, keep the first one lua could determine
+                    if self.frames[id].name.is_none() {
+                        self.frames[id].name = frame.name;
+                    }
+                    id
+                },
+                None => {
+                    self.frames.push(frame);
+                    self.keys.insert(key,self.frames.len() - 1);
+                    self.frames.len() - 1
+                },
+            };
+            stack.push(id);
+            level += 1;
+        }
+        stack.reverse();
+        stack
+    }
+
+    pub fn finish(self) -> Profile {
+        let mut stacks: Vec<(Vec<usize>,Samples)> = self.stacks.into_iter().collect();
+        stacks.sort_by(|a,b| a.0.cmp(&b.0));
+        Profile { interval: self.interval, frames: self.frames, stacks: stacks }
+    }
+}
+
+/// Samples the stack when the rust callback running on `l` is entered or left.
+///
+/// On entry the time since the previous sample is charged to the caller, on return to the
+/// callback itself, so rust code shows up with its own time.
+pub unsafe fn callback(l: *mut ffi::lua_State,entered: bool) {
+    let handle = ::handle_ptr(l);
+    if handle.is_null() {
+        return;
+    }
+    if let Ok(mut profiler) = (*handle).profiler.try_borrow_mut() {
+        if let Some(ref mut profiler) = *profiler {
+            profiler.sample(l,if entered { 1 } else { 0 });
+        }
+    }
+}
+
+/// The time spent in a function, see `Profile::functions`.
+#[derive(Debug,Clone,PartialEq)]
+pub struct FunctionProfile {
+    /// The name of the function if lua could determine it.
+    pub name: Option<String>,
+    pub kind: FrameKind,
+    /// The chunk name as lua prints it, `[C]` for rust and C functions.
+    pub source: String,
+    /// The line the function is defined at, `None` for main chunks, rust and C functions.
+    pub line: Option<u32>,
+    /// Samples taken while the function itself was running.
+    pub self_samples: u64,
+    /// Samples taken while the function was on the stack.
+    pub total_samples: u64,
+    pub self_time: Duration,
+    pub total_time: Duration,
+}
+
+/// The result of `State::stop_profiler`.
+///
+/// Samples are taken every `interval` lua instructions and weighted with the time since
+/// the previous sample. Rust callbacks are also sampled when they are called and when they
+/// return, time spent in other C functions is counted for the next sample.
+pub struct Profile {
+    interval: u64,
+    frames: Vec<Frame>,
+    stacks: Vec<(Vec<usize>,Samples)>,
+}
+
+impl Profile {
+    /// The number of instructions between samples.
+    pub fn interval(&self) -> u64 {
+        self.interval
+    }
+
+    /// The number of samples taken.
+    pub fn samples(&self) -> u64 {
+        self.stacks.iter().map(|&(_,samples)| samples.count).sum()
+    }
+
+    /// Returns the samples in the collapsed stack format read by flamegraph tools.
+    ///
+    /// Every line holds the frames of a stack from the outermost one, separated by `;`,
+    /// followed by the number of samples.
+    pub fn collapsed(&self) -> String {
+        let mut collapsed = String::new();
+        for &(ref stack,samples) in &self.stacks {
+            let labels: Vec<String> = stack.iter().map(|&id| self.frames[id].label()).collect();
+            collapsed.push_str(&format!("{} {}\n",labels.join(";"),samples.count));
+        }
+        collapsed
+    }
+
+    /// Returns the sampled functions, the ones with the most self samples first.
+    pub fn functions(&self) -> Vec<FunctionProfile> {
+        let mut functions: Vec<FunctionProfile> = self.frames.iter().map(|frame| FunctionProfile {
+            name: frame.name.clone(),
+            kind: frame.kind,
+            source: frame.source.clone(),
+            line: frame.line,
+            self_samples: 0,
+            total_samples: 0,
+            self_time: Duration::default(),
+            total_time: Duration::default(),
+        }).collect();
+        for &(ref stack,samples) in &self.stacks {
+            if let Some(&id) = stack.last() {
+                functions[id].self_samples += samples.count;
+                functions[id].self_time += samples.time;
+            }
+            // Recursive functions count once per sample
+            let mut seen = Vec::with_capacity(stack.len());
+            for &id in stack {
+                if !seen.contains(&id) {
+                    seen.push(id);
+                    functions[id].total_samples += samples.count;
+                    functions[id].total_time += samples.time;
+                }
+            }
+        }
+        functions.retain(|function| function.total_samples > 0);
+        functions.sort_by(|a,b| b.self_samples.cmp(&a.self_samples).then(b.total_samples.cmp(&a.total_samples)));
+        functions
+    }
+}
+
+/// Prints the table of `functions`.
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f,"{:>8} {:>8} {:>10} {:>10}  function","self","total","self ms","total ms")?;
+        for function in self.functions() {
+            let location = match function.line {
+                Some(line) => format!("{}:{}",function.source,line),
+                None => function.source.clone(),
+            };
+            let name = match (function.kind,function.line) {
+                (FrameKind::Lua,None) => String::from("main chunk"),
+                _ => function.name.clone().unwrap_or_else(|| String::from("?")),
+            };
+            writeln!(f,"{:>8} {:>8} {:>10.3} {:>10.3}  {} ({})",
+                     function.self_samples,function.total_samples,
+                     function.self_time.as_secs_f64() * 1000.0,function.total_time.as_secs_f64() * 1000.0,
+                     name,location)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+//! Protected versions of lua API calls which can raise errors.
+//!
+//! Lua raises errors with `longjmp` and LuaJIT by unwinding, neither may pass through rust
+//! frames other than the callbacks lua calls.
+//! Every call which can invoke a metamethod or allocate, e.g. pushing a string or creating a
+//! reference, is therefore run inside a C function called with `lua_pcall`, so errors only
+//! ever unwind through that function.
+
+#[cfg(not(feature = "luajit"))]
+use std::panic::{self, AssertUnwindSafe};
+
+use ffi;
+#[cfg(not(feature = "luajit"))]
+use function;
+use lauxlib;
+use libc;
+use error::{self, LuaError};
+use {LuaContext, LuaRead, LuaWrite};
+
+/// The rust side of a protected call, returns the number of its results.
+type Protected<'a> = &'a mut dyn FnMut(*mut ffi::lua_State) -> libc::c_int;
+
+/// Calls the closure whose pointer is on top of the stack.
+extern "C-unwind" fn protected(l: *mut ffi::lua_State) -> libc::c_int {
+    unsafe {
+        let f = &mut *(ffi::lua_touserdata(l,-1) as *mut Protected);
+        ffi::lua_pop(l,1);
+        call(l,f)
+    }
+}
+
+/// Raises panics as lua errors, they are resumed by `error::pop_error`.
+#[cfg(not(feature = "luajit"))]
+unsafe fn call(l: *mut ffi::lua_State,f: Protected) -> libc::c_int {
+    match panic::catch_unwind(AssertUnwindSafe(|| f(l))) {
+        Ok(nresults) => nresults,
+        Err(payload) => {
+            function::push_panic(l,payload);
+            ffi::lua_error(l)
+        },
+    }
+}
+
+/// LuaJIT raises errors as foreign exceptions, which `catch_unwind` can't pass through, so
+/// panics abort instead.
+#[cfg(feature = "luajit")]
+unsafe fn call(l: *mut ffi::lua_State,f: Protected) -> libc::c_int {
+    f(l)
+}
+
+/// The C functions pushed by yuna, which have to be created up front before lua 5.2.
+#[cfg(any(feature = "lua51", feature = "luajit"))]
+const FUNCTIONS: &'static [ffi::lua_CFunction] = &[protected,error::message_handler];
+
+/// Creates the C functions pushed by `push_function` in the registry.
+#[cfg(any(feature = "lua51", feature = "luajit"))]
+pub unsafe fn register(l: *mut ffi::lua_State) -> bool {
+    extern "C-unwind" fn create(l: *mut ffi::lua_State) -> libc::c_int {
+        for &f in FUNCTIONS {
+            unsafe {
+                ffi::lua_pushcfunction(l,f);
+                ffi::lua_rawsetp(l,ffi::LUA_REGISTRYINDEX,f as *const libc::c_void);
+            }
+        }
+        0
+    }
+    ffi::lua_cpcall(l,create,::std::ptr::null_mut()) == ffi::LUA_OK
+}
+
+#[cfg(not(any(feature = "lua51", feature = "luajit")))]
+pub unsafe fn register(_l: *mut ffi::lua_State) -> bool {
+    true
+}
+
+/// Pushes one of the C functions of yuna without allocating.
+///
+/// Light C functions exist since lua 5.2, before the closure created by `register` is used.
+#[cfg(any(feature = "lua51", feature = "luajit"))]
+pub unsafe fn push_function(l: *mut ffi::lua_State,f: ffi::lua_CFunction) {
+    ffi::lua_rawgetp(l,ffi::LUA_REGISTRYINDEX,f as *const libc::c_void);
+}
+
+#[cfg(not(any(feature = "lua51", feature = "luajit")))]
+pub unsafe fn push_function(l: *mut ffi::lua_State,f: ffi::lua_CFunction) {
+    ffi::lua_pushcfunction(l,f);
+}
+
+/// Calls `f` in protected mode with the `nargs` values on top of the stack as arguments,
+/// keeping `nresults` of the values it returns.
+///
+/// Returns the status of `lua_pcall`, on errors the error value is left on the stack.
+/// An error jumps out of `f` without unwinding, so values with destructors have to be
+/// owned by the caller and only borrowed by `f`.
+/// Panics are caught and resumed by `run`, except on LuaJIT where they abort.
+pub unsafe fn run_raw<F>(l: *mut ffi::lua_State,nargs: libc::c_int,nresults: libc::c_int,mut f: F) -> libc::c_int
+    where F: FnMut(*mut ffi::lua_State) -> libc::c_int {
+    let mut f: Protected = &mut f;
+    push_function(l,protected);
+    ffi::lua_insert(l,-nargs - 1);
+    ffi::lua_pushlightuserdata(l,&mut f as *mut Protected as *mut libc::c_void);
+    ffi::lua_pcall(l,nargs + 1,nresults,0)
+}
+
+/// Like `run_raw`, but pops the error and converts it.
+///
+/// Panics of rust callbacks invoked by metamethods are resumed.
+pub unsafe fn run<F>(context: &LuaContext,nargs: libc::c_int,nresults: libc::c_int,f: F) -> Result<(),LuaError>
+    where F: FnMut(*mut ffi::lua_State) -> libc::c_int {
+    match run_raw(context.l,nargs,nresults,f) {
+        ffi::LUA_OK => Ok(()),
+        status => Err(error::pop_error(context,status,Default::default())),
+    }
+}
+
+/// Pushes a string, leaving the error on the stack instead if it fails.
+pub unsafe fn push_bytes(l: *mut ffi::lua_State,bytes: &[u8]) -> libc::c_int {
+    run_raw(l,0,1,|l| {
+        ffi::lua_pushlstring(l,bytes.as_ptr() as *const libc::c_char,bytes.len() as libc::size_t);
+        1
+    })
+}
+
+/// Pops the value on top of the stack and returns a reference to it in the registry.
+pub unsafe fn reference(context: &LuaContext) -> Result<libc::c_int,LuaError> {
+    let mut refindex = 0;
+    run(context,1,0,|l| {
+        refindex = lauxlib::luaL_ref(l,ffi::LUA_REGISTRYINDEX);
+        0
+    })?;
+    Ok(refindex)
+}
+
+/// Pushes a value, returns an error instead if lua runs out of memory.
+pub unsafe fn write<T: LuaWrite>(context: &LuaContext,value: T) -> Result<(),LuaError> {
+    let mut value = Some(value);
+    run(context,0,1,|_| {
+        LuaWrite::lua_write(context,value.take().unwrap());
+        1
+    })
+}
+
+/// Reads `table[key]` where the table is on top of the stack, which is popped.
+pub unsafe fn get<K: LuaWrite,V: LuaRead>(context: &LuaContext,key: K) -> Result<V,()> {
+    let mut key = Some(key);
+    let status = run(context,1,1,|l| {
+        LuaWrite::lua_write(context,key.take().unwrap());
+        ffi::lua_gettable(l,1);
+        1
+    });
+    match status {
+        Ok(()) => {
+            let result = LuaRead::lua_read_index(context,-1);
+            ffi::lua_pop(context.l,1);
+            result
+        },
+        Err(_) => Err(()),
+    }
+}
+
+/// Sets `table[key] = value` where the table is on top of the stack, which is popped.
+pub unsafe fn set<K: LuaWrite,V: LuaWrite>(context: &LuaContext,key: K,value: V) -> Result<(),LuaError> {
+    let mut key = Some(key);
+    let mut value = Some(value);
+    run(context,1,0,|l| {
+        LuaWrite::lua_write(context,key.take().unwrap());
+        LuaWrite::lua_write(context,value.take().unwrap());
+        ffi::lua_settable(l,1);
+        0
+    })
+}
+
+/// Compares the two values on top of the stack with `==`, which are popped.
+pub unsafe fn equal_top(context: &LuaContext) -> bool {
+    let status = run(context,2,1,|l| {
+        let eq = ffi::lua_compare(l,1,2,ffi::LUA_OPEQ);
+        ffi::lua_pushboolean(l,eq);
+        1
+    });
+    match status {
+        Ok(()) => {
+            let eq = ffi::lua_toboolean(context.l,-1) != 0;
+            ffi::lua_pop(context.l,1);
+            eq
+        },
+        Err(_) => false,
+    }
+}
@@ -0,0 +1,31 @@
+//! Unsafe low-level access to the lua stack.
+//!
+//! The high-level API of `State`, `Table` and `LuaValue` never exposes the stack.
+//! For everything else the raw `lua_State` of a `LuaContext` can be used with the C API
+//! re-exported in `ffi`. The `LuaRead` and `LuaWrite` traits operate on the same stack.
+//!
+//! # Examples
+//!
+//! ```
+//! use yuna::raw::ffi;
+//! use yuna::LuaRead;
+//!
+//! let state = yuna::State::new();
+//! let context = state.context();
+//!
+//! let b : bool = unsafe {
+//!     ffi::lua_pushboolean(context.as_ptr(),1);
+//!     let b = LuaRead::lua_read_index(context,-1).unwrap();
+//!     ffi::lua_pop(context.as_ptr(),1);
+//!     b
+//! };
+//!
+//! assert!(b);
+//! ```
+
+/// The lua C API.
+pub mod ffi {
+    pub use ffi::*;
+}
+
+pub use {LuaContext, LuaRead, LuaRef, LuaWrite};
@@ -0,0 +1,231 @@
+//! Reloading scripts and modules whose files changed while the application runs.
+
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+use std::time::SystemTime;
+
+use error::LuaError;
+use function;
+use package;
+use {LuaContext, LuaIndex, LuaValue, State, Table};
+
+/// Wraps every searcher of `package.searchers` to report what it found.
+///
+/// Lua 5.1 and LuaJIT call them `package.loaders`.
+const WRAP_SEARCHERS: &'static str = "
+    local searchers, record = ...
+    for i = 1, #searchers do
+        local searcher = searchers[i]
+        searchers[i] = function(name, ...)
+            local loader, extra = searcher(name, ...)
+            record(name, loader, extra)
+            return loader, extra
+        end
+    end
+";
+
+/// A file loaded through `do_file` or `require`.
+#[derive(Clone)]
+struct Tracked {
+    path: PathBuf,
+    module: Option<String>,
+    version: Option<(SystemTime,u64)>,
+}
+
+/// Returns the modification time and size of the file.
+fn version(path: &Path) -> Option<(SystemTime,u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?,metadata.len()))
+}
+
+/// The files tracked by a `HotReload`.
+#[derive(Default)]
+pub struct Files {
+    tracked: RefCell<Vec<Tracked>>,
+}
+
+impl Files {
+    fn track(&self,path: &Path,module: Option<String>) {
+        let mut tracked = self.tracked.borrow_mut();
+        if tracked.iter().any(|t| t.path == path && t.module == module) {
+            return;
+        }
+        tracked.push(Tracked { path: path.to_path_buf(), version: version(path), module: module });
+    }
+}
+
+/// Remembers a file run by `State::do_file` if a `HotReload` is active.
+pub fn track_script(context: &LuaContext,path: &Path) {
+    if let Some(files) = context.handle.reload.borrow().upgrade() {
+        files.track(path,None);
+    }
+}
+
+/// The result of reloading a changed file.
+#[derive(Debug)]
+pub struct Reload {
+    /// The changed file.
+    pub path: PathBuf,
+    /// The module name if the file was loaded by `require`.
+    pub module: Option<String>,
+    /// The error if running the new code failed, the old code stays in place then.
+    pub result: Result<(),LuaError>,
+}
+
+/// Watches the files loaded through `do_file` and `require` and reloads them on change.
+///
+/// Changes are detected by polling the modification time and size of the files. A changed
+/// script is run again, a changed module is run again and the new module table is patched
+/// into the old one in `package.loaded`, so existing references see the new functions.
+///
+/// Only modules required after the `HotReload` was created are tracked, and only those
+/// found by searchers present at that time. Modules from script sources are not tracked,
+/// as they have no files.
+///
+/// # Examples
+///
+/// ```no_run
+/// let mut state = yuna::State::new();
+/// state.openlibs();
+///
+/// let mut reload = yuna::HotReload::new(&state);
+/// state.do_file("main.lua").unwrap();
+///
+/// loop {
+///     for reload in reload.poll() {
+///         if let Err(err) = reload.result {
+///             eprintln!("reloading {} failed: {}",reload.path.display(),err);
+///         }
+///     }
+///     # break;
+/// }
+/// ```
+pub struct HotReload {
+    context: LuaContext,
+    files: Rc<Files>,
+}
+
+impl HotReload {
+    /// Starts tracking files loaded into the state.
+    ///
+    /// Only one `HotReload` can be active for a state, a new one replaces the old one.
+    pub fn new(state: &State) -> Self {
+        let files = Rc::new(Files::default());
+        *state.context.handle.reload.borrow_mut() = Rc::downgrade(&files);
+
+        let searchers = state.package_table(package::SEARCHERS);
+        if let Some(searchers) = searchers {
+            let weak: Weak<Files> = Rc::downgrade(&files);
+            let record = state.create_function(move |_state,args| {
+                let mut args = args.into_iter();
+                let (name,loader,extra) = (args.next(),args.next(),args.next());
+                // The searchers of lua 5.1 only return the loader, the file is searched again
+                #[cfg(any(feature = "lua51", feature = "luajit"))]
+                let extra = match (&name,&loader,extra) {
+                    (&Some(LuaValue::LuaString(ref name)),&Some(LuaValue::LuaFunction(_)),None) |
+                    (&Some(LuaValue::LuaString(ref name)),&Some(LuaValue::LuaFunction(_)),Some(LuaValue::Nil)) => {
+                        let package : Option<Table> = _state.read("package").unwrap_or(None);
+                        package.and_then(|package| package.read::<_,Option<String>>("path").unwrap_or(None))
+                               .and_then(|path| package::search_path(name,&path))
+                               .map(|path| LuaValue::LuaString(path.to_string_lossy().into_owned()))
+                    },
+                    (_,_,extra) => extra,
+                };
+                if let (Some(LuaValue::LuaString(name)),Some(LuaValue::LuaFunction(loader)),Some(LuaValue::LuaString(path)))
+                       = (name,loader,extra) {
+                    let path = Path::new(&path);
+                    match weak.upgrade() {
+                        Some(ref files) if function::is_lua(&loader) && path.is_file() => files.track(path,Some(name)),
+                        _ => {},
+                    }
+                }
+                Ok(vec![])
+            });
+            let wrap = state.load(WRAP_SEARCHERS.as_bytes(),"=yuna.reload").unwrap();
+            wrap.call(vec![LuaValue::LuaTable(searchers),LuaValue::LuaFunction(record)])
+                .expect("wrapping package.searchers failed");
+        }
+        HotReload { context: state.context.clone(), files: files }
+    }
+
+    /// Returns the tracked files.
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.files.tracked.borrow().iter().map(|t| t.path.clone()).collect()
+    }
+
+    /// Reloads all files changed since they were loaded or last polled.
+    ///
+    /// Errors while reloading are reported and leave the state running with the old code.
+    pub fn poll(&mut self) -> Vec<Reload> {
+        let changed: Vec<Tracked> = {
+            let mut tracked = self.files.tracked.borrow_mut();
+            tracked.iter_mut()
+                   .filter_map(|t| {
+                       let version = version(&t.path);
+                       if version.is_none() || version == t.version {
+                           return None;
+                       }
+                       t.version = version;
+                       Some(t.clone())
+                   })
+                   .collect()
+        };
+
+        let state = State { context: self.context.clone() };
+        changed.into_iter()
+               .map(|t| {
+                   let result = match t.module {
+                       Some(ref module) => reload_module(&state,module,&t.path),
+                       None => state.load_file(&t.path).and_then(|chunk| chunk.call(vec![])).map(|_| ()),
+                   };
+                   Reload { path: t.path, module: t.module, result: result }
+               })
+               .collect()
+    }
+}
+
+/// Runs the module again and patches the result into the old module table.
+fn reload_module(state: &State,module: &str,path: &Path) -> Result<(),LuaError> {
+    let mut loaded = match state.package_table("loaded") {
+        Some(loaded) => loaded,
+        None => return Ok(()),
+    };
+    let old = loaded.get(module);
+
+    let chunk = state.load_file(path)?;
+    let results = chunk.call(vec![state.pack(module),state.pack(path.to_string_lossy().into_owned())])?;
+    // Like require, a module returning nothing may have set package.loaded itself
+    let new = match results.into_iter().next() {
+        Some(LuaValue::Nil) | None => loaded.get(module),
+        Some(value) => value,
+    };
+
+    match (old,new) {
+        (_,LuaValue::Nil) => Ok(()),
+        (LuaValue::LuaTable(mut old),LuaValue::LuaTable(new)) => {
+            patch(&mut old,&new)?;
+            loaded.try_set(module,&old)
+        },
+        (_,new) => loaded.try_set(module,new),
+    }
+}
+
+/// Makes `old` hold the same fields as `new`.
+fn patch(old: &mut Table,new: &Table) -> Result<(),LuaError> {
+    if old == new {
+        return Ok(());
+    }
+    let stale = old.pairs().map_err(|()| LuaError::Memory)?;
+    let fresh = new.pairs().map_err(|()| LuaError::Memory)?;
+    for (key,_) in stale {
+        if !fresh.iter().any(|(k,_)| *k == key) {
+            old.try_set(key,LuaValue::Nil)?;
+        }
+    }
+    for (key,value) in fresh {
+        old.try_set(key,value)?;
+    }
+    Ok(())
+}
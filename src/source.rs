@@ -0,0 +1,155 @@
+//! Sources `require` can load scripts from, independent of the file system layout.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// Provides the code of lua modules.
+pub trait ScriptSource {
+    /// Returns the code of the module and its virtual path, used in the chunk name.
+    fn find(&self,module: &str) -> Option<(Vec<u8>,String)>;
+}
+
+/// Returns the paths searched for a module, `a.b` is looked up as `a/b.lua` and `a/b/init.lua`.
+///
+/// Module names which could escape the root, e.g. containing `..` or `/`, have no paths.
+pub fn module_paths(module: &str) -> Vec<String> {
+    let valid = module.split('.').all(|part| {
+        !part.is_empty() && !part.contains(['/', '\\', ':', '\0'])
+    });
+    if !valid {
+        return Vec::new();
+    }
+    let base = module.replace('.',"/");
+    vec![format!("{}.lua",base),format!("{}/init.lua",base)]
+}
+
+/// Loads modules from a directory.
+pub struct DirectorySource {
+    root: PathBuf,
+}
+
+impl DirectorySource {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        DirectorySource { root: root.into() }
+    }
+}
+
+impl ScriptSource for DirectorySource {
+    fn find(&self,module: &str) -> Option<(Vec<u8>,String)> {
+        module_paths(module).into_iter()
+                            .filter_map(|path| fs::read(self.root.join(&path)).ok().map(|code| (code,path)))
+                            .next()
+    }
+}
+
+/// Holds modules in memory, keyed by their virtual path like `ourgame/physics.lua`.
+#[derive(Default)]
+pub struct MemorySource {
+    files: HashMap<String,Vec<u8>>,
+}
+
+impl MemorySource {
+    pub fn new() -> Self {
+        MemorySource::default()
+    }
+
+    /// Adds or replaces the file at the given virtual path.
+    pub fn insert<P: Into<String>,C: Into<Vec<u8>>>(&mut self,path: P,code: C) {
+        self.files.insert(path.into(),code.into());
+    }
+
+    /// Removes the file at the given virtual path.
+    pub fn remove(&mut self,path: &str) -> Option<Vec<u8>> {
+        self.files.remove(path)
+    }
+}
+
+impl ScriptSource for MemorySource {
+    fn find(&self,module: &str) -> Option<(Vec<u8>,String)> {
+        module_paths(module).into_iter()
+                            .filter_map(|path| self.files.get(&path).map(|code| (code.clone(),path)))
+                            .next()
+    }
+}
+
+/// Stacks sources on top of each other, upper layers override modules of lower ones.
+///
+/// # Examples
+///
+/// ```
+/// use yuna::{LayeredSource, MemorySource, ScriptSource};
+///
+/// let mut base = MemorySource::new();
+/// base.insert("weapons.lua","return { sword = 10 }");
+/// base.insert("enemies.lua","return {}");
+///
+/// let mut modded = MemorySource::new();
+/// modded.insert("weapons.lua","return { sword = 99 }");
+///
+/// let mut layers = LayeredSource::new();
+/// layers.push(base);
+/// layers.push(modded);
+///
+/// assert_eq!(layers.find("weapons").unwrap().0,b"return { sword = 99 }".to_vec());
+/// assert_eq!(layers.find("enemies").unwrap().0,b"return {}".to_vec());
+/// ```
+#[derive(Default)]
+pub struct LayeredSource {
+    layers: Vec<Box<dyn ScriptSource>>,
+}
+
+impl LayeredSource {
+    pub fn new() -> Self {
+        LayeredSource::default()
+    }
+
+    /// Adds a layer on top of all existing ones.
+    pub fn push<S: ScriptSource + 'static>(&mut self,source: S) {
+        self.layers.push(Box::new(source));
+    }
+}
+
+impl ScriptSource for LayeredSource {
+    fn find(&self,module: &str) -> Option<(Vec<u8>,String)> {
+        self.layers.iter().rev().filter_map(|layer| layer.find(module)).next()
+    }
+}
+
+/// Modules included into the binary, created by `embed_scripts!`.
+///
+/// The files are keyed by their path relative to the embedded directory.
+///
+/// # Examples
+///
+/// ```ignore
+/// let mut state = yuna::State::new();
+/// state.openlibs();
+/// state.add_script_source(yuna::embed_scripts!("scripts"));
+/// ```
+pub struct EmbeddedSource {
+    files: &'static [(&'static str,&'static [u8])],
+}
+
+impl EmbeddedSource {
+    pub fn new(files: &'static [(&'static str,&'static [u8])]) -> Self {
+        EmbeddedSource { files: files }
+    }
+
+    /// Returns the virtual paths of all embedded files.
+    pub fn paths(&self) -> Vec<&'static str> {
+        self.files.iter().map(|&(path,_)| path).collect()
+    }
+}
+
+impl ScriptSource for EmbeddedSource {
+    fn find(&self,module: &str) -> Option<(Vec<u8>,String)> {
+        module_paths(module).into_iter()
+                            .filter_map(|path| {
+                                self.files.iter()
+                                          .find(|&&(file,_)| file == path)
+                                          .map(|&(_,code)| (code.to_vec(),path))
+                            })
+                            .next()
+    }
+}
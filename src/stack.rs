@@ -0,0 +1,141 @@
+//! Inspection of the active call stack.
+
+use std::fmt;
+use std::rc::Rc;
+
+use error;
+use ffi;
+use libc;
+use {LuaContext, LuaRead, LuaRef, LuaValue, Thread};
+
+/// A function active on the call stack, see `State::stack_frames`.
+///
+/// The frame refers to a position on the stack, it is only meaningful until the inspected
+/// code continues running. Frames of a coroutine keep it from being collected.
+pub struct StackFrame {
+    /// The position on the stack, 0 is the running function.
+    pub level: u32,
+    /// The chunk name as lua prints it, e.g. `[string "..."]` or a file name.
+    pub source: String,
+    /// The line currently executed or `None` if not available, e.g. for C functions.
+    pub line: Option<u32>,
+    /// The name of the function if lua could determine it.
+    pub name: Option<String>,
+    /// `"Lua"`, `"C"` or `"main"`.
+    pub what: String,
+    context: LuaContext,
+    /// The coroutine the frame belongs to, `None` for the main thread.
+    // Only held to keep the coroutine alive
+    #[allow(dead_code)]
+    thread: Option<Rc<Thread>>,
+}
+
+impl StackFrame {
+    /// Returns the local variables of the function in the order they were declared.
+    ///
+    /// Internal variables like the state of `for` loops are left out.
+    pub fn locals(&self) -> Vec<(String,LuaValue)> {
+        let l = self.context.l;
+        let mut locals = Vec::new();
+        unsafe {
+            let mut ar: ffi::lua_Debug = ::std::mem::zeroed();
+            if ffi::lua_getstack(l,self.level as libc::c_int,&mut ar) == 0 {
+                return locals;
+            }
+            for n in 1.. {
+                let name = ffi::lua_getlocal(l,&ar,n);
+                if name.is_null() {
+                    break;
+                }
+                let name = error::read_cstr(name).unwrap_or_default();
+                let value = LuaRead::lua_read_index(&self.context,-1).unwrap();
+                ffi::lua_pop(l,1);
+                if !name.starts_with('(') {
+                    locals.push((name,value));
+                }
+            }
+        }
+        locals
+    }
+
+    /// The chunk name of the function, e.g. `@script.lua` for files or `=[C]` for C functions.
+    ///
+    /// Unlike `source` it is not shortened.
+    pub fn chunk_name(&self) -> String {
+        unsafe {
+            let mut ar: ffi::lua_Debug = ::std::mem::zeroed();
+            if ffi::lua_getstack(self.context.l,self.level as libc::c_int,&mut ar) == 0 {
+                return String::new();
+            }
+            ffi::lua_getinfo(self.context.l,b"S\0".as_ptr() as *const libc::c_char,&mut ar);
+            error::read_cstr(ar.source).unwrap_or_default()
+        }
+    }
+}
+
+impl fmt::Debug for StackFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StackFrame")
+         .field("level",&self.level)
+         .field("source",&self.source)
+         .field("line",&self.line)
+         .field("name",&self.name)
+         .field("what",&self.what)
+         .finish()
+    }
+}
+
+/// Returns the active frames of the thread the context refers to.
+pub fn frames(context: &LuaContext) -> Vec<StackFrame> {
+    let frames = unsafe { error::stack_frames(context.l,0) };
+    let thread = if frames.is_empty() || context.l == context.handle.l {
+        None
+    } else {
+        unsafe {
+            ffi::lua_pushthread(context.l);
+            Some(Rc::new(Thread::ref_from_stack(context)))
+        }
+    };
+    frames.into_iter()
+          .enumerate()
+          .map(|(level,frame)| StackFrame {
+              level: level as u32,
+              source: frame.source,
+              line: frame.line,
+              name: frame.name,
+              what: frame.what,
+              // Not a clone, which would refer to the main thread
+              context: LuaContext { l: context.l, handle: context.handle.clone() },
+              thread: thread.clone(),
+          })
+          .collect()
+}
+
+/// Returns the number of active frames of the thread the context refers to.
+#[cfg(feature = "dap")]
+pub fn depth(context: &LuaContext) -> usize {
+    let mut level = 0;
+    unsafe {
+        let mut ar: ffi::lua_Debug = ::std::mem::zeroed();
+        while ffi::lua_getstack(context.l,level,&mut ar) != 0 {
+            level += 1;
+        }
+    }
+    level as usize
+}
+
+/// Returns the upvalues of the function on top of the stack, the names of C functions are empty.
+pub unsafe fn upvalues(context: &LuaContext) -> Vec<(String,LuaValue)> {
+    let l = context.l;
+    let mut upvalues = Vec::new();
+    for n in 1.. {
+        let name = ffi::lua_getupvalue(l,-1,n);
+        if name.is_null() {
+            break;
+        }
+        let name = error::read_cstr(name).unwrap_or_default();
+        upvalues.push((name,LuaRead::lua_read_index(context,-1).unwrap()));
+        ffi::lua_pop(l,1);
+    }
+    upvalues
+}
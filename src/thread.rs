@@ -0,0 +1,39 @@
+use std::fmt;
+
+use ffi;
+use libc;
+use {LuaContext, LuaRef};
+
+/// Holds a reference to a lua thread, i.e. a coroutine.
+pub struct Thread {
+    context: LuaContext,
+    refindex: libc::c_int,
+}
+
+impl Thread {
+    /// Returns the raw state of the thread.
+    ///
+    /// The pointer stays valid as long as the thread is referenced.
+    pub fn as_ptr(&self) -> *mut ffi::lua_State {
+        unsafe {
+            self.write_self();
+            let l = ffi::lua_tothread(self.context.l,-1);
+            ffi::lua_pop(self.context.l,1);
+            l
+        }
+    }
+}
+
+impl fmt::Debug for Thread {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,"thread: {:p}",self.as_ptr())
+    }
+}
+
+impl_lua_ref!(Thread);
+
+impl PartialEq for Thread {
+    fn eq(&self, other: &Thread) -> bool {
+        self.as_ptr() == other.as_ptr()
+    }
+}
@@ -0,0 +1,44 @@
+use std::fmt;
+
+use ffi;
+use libc;
+use {LuaContext, LuaRef};
+
+/// Holds a reference to a lua userdata, either full or light.
+pub struct UserData {
+    context: LuaContext,
+    refindex: libc::c_int,
+}
+
+impl UserData {
+    /// Returns the address of the userdata block.
+    pub fn as_ptr(&self) -> *mut libc::c_void {
+        unsafe {
+            self.write_self();
+            let ptr = ffi::lua_touserdata(self.context.l,-1);
+            ffi::lua_pop(self.context.l,1);
+            ptr
+        }
+    }
+}
+
+impl fmt::Debug for UserData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,"userdata: {:p}",self.as_ptr())
+    }
+}
+
+impl_lua_ref!(UserData);
+
+/// Compares the identity, `__eq` is not invoked.
+impl PartialEq for UserData {
+    fn eq(&self, other: &UserData) -> bool {
+        unsafe {
+            self.write_self();
+            other.write_self();
+            let eq = ffi::lua_rawequal(self.context.l,-2,-1) != 0;
+            ffi::lua_pop(self.context.l,2);
+            eq
+        }
+    }
+}
@@ -0,0 +1,163 @@
+#![cfg(feature = "cli")]
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn yuna(args: &[&str],stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_yuna"))
+                            .args(args)
+                            .stdin(Stdio::piped())
+                            .stdout(Stdio::piped())
+                            .stderr(Stdio::piped())
+                            .spawn()
+                            .unwrap();
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn repl_expressions_and_statements() {
+    let output = yuna(&[],"1+1\nx = 'lua'\nx, #x\nprint('hi', nil)\n{1, 2, key = true}\n0.5\n");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout),"2\n\"lua\"\t3\nhi\tnil\n{1: 1, 2: 2, \"key\": true}\n0.5\n");
+}
+
+#[test]
+fn repl_multiline_chunks() {
+    let output = yuna(&["repl"],"function add(a, b)\n  return a + b\nend\nadd(\n40,\n2)\n");
+    assert_eq!(String::from_utf8_lossy(&output.stdout),"42\n");
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn repl_errors_continue() {
+    let output = yuna(&[],"error('boom')\nx = = 1\n'still running'\n");
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    // LuaJIT tail calls `error` in the expression form of the line, so there is no position
+    assert!(stderr.contains(if cfg!(feature = "luajit") { "boom" } else { "stdin:1: boom" }));
+    assert!(stderr.contains("stack traceback:"));
+    assert!(stderr.contains("unexpected symbol near '='"));
+    assert_eq!(String::from_utf8_lossy(&output.stdout),"\"still running\"\n");
+}
+
+#[test]
+fn unknown_command() {
+    let output = yuna(&["frobnicate"],"");
+    assert_eq!(output.status.code(),Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown command 'frobnicate'"));
+}
+
+fn script(name: &str,code: &str) -> String {
+    let path = std::env::temp_dir().join(name);
+    std::fs::write(&path,code).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+#[test]
+fn run_script_with_args() {
+    let path = script("yuna_cli_run_args.lua","#!/usr/bin/env yuna\nprint(arg[-1], #arg, arg[1], arg[2], ...)\nprint(arg[0]:match('yuna_cli_run_args.lua$'))\n");
+    let output = yuna(&["run",&path,"--","a","--b"],"");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout),"yuna\t2\ta\t--b\ta\t--b\nyuna_cli_run_args.lua\n");
+}
+
+#[test]
+fn run_script_error() {
+    let path = script("yuna_cli_run_error.lua","local function fail()\n  error('broken')\nend\nfail()\n");
+    let output = yuna(&["run",&path],"");
+    assert_eq!(output.status.code(),Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("yuna_cli_run_error.lua:2: broken"));
+    let fail = if cfg!(any(feature = "lua53", feature = "lua54")) { "in local 'fail'" } else { "in function 'fail'" };
+    assert!(stderr.contains(fail));
+
+    let output = yuna(&["run","does/not/exist.lua"],"");
+    assert_eq!(output.status.code(),Some(1));
+    let output = yuna(&["run","--lib","sockets",&path],"");
+    assert_eq!(output.status.code(),Some(2));
+}
+
+#[test]
+fn run_script_restricted() {
+    let path = script("yuna_cli_run_sandbox.lua","print(io, loadfile, string.rep('a', 3))\n");
+    let output = yuna(&["run","--sandbox",&path],"");
+    assert_eq!(String::from_utf8_lossy(&output.stdout),"nil\tnil\taaa\n");
+
+    // Extra libraries don't undo the sandbox
+    let path = script("yuna_cli_run_sandbox_libs.lua","print(io ~= nil, dofile, loadfile, string.rep('a', 3))\n");
+    let output = yuna(&["run","--sandbox","--lib","base","--lib","io",&path],"");
+    assert_eq!(String::from_utf8_lossy(&output.stdout),"true\tnil\tnil\taaa\n");
+
+    let path = script("yuna_cli_run_loop.lua","while true do end\n");
+    let output = yuna(&["run","--instruction-limit","10000",&path],"");
+    assert_eq!(output.status.code(),Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("instruction limit exceeded"));
+
+    let path = script("yuna_cli_run_memory.lua","local t = {} for i = 1, 1e7 do t[i] = i end\n");
+    let output = yuna(&["run","--memory-limit","1000000",&path],"");
+    assert_eq!(output.status.code(),Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("not enough memory"));
+
+    // Too small to even load the script
+    let output = yuna(&["run","--memory-limit","1000",&path],"");
+    assert_eq!(output.status.code(),Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("not enough memory"));
+}
+
+#[test]
+fn compile_and_run_bytecode() {
+    let path = script("yuna_cli_compile.lua","local function greet(name)\n  print('hello ' .. name)\nend\ngreet(...)\n");
+    for &(strip,name) in [(false,"yuna_cli_compile.luac"),(true,"yuna_cli_compile_stripped.luac")].iter() {
+        let out = std::env::temp_dir().join(name);
+        let out = out.to_str().unwrap();
+        let mut args = vec!["compile",&path,"-o",out];
+        if strip {
+            args.push("--strip");
+        }
+        let output = yuna(&args,"");
+        assert!(output.status.success(),"{}",String::from_utf8_lossy(&output.stderr));
+        let signature: &[u8] = if cfg!(feature = "luajit") { b"\x1bLJ" } else { b"\x1bLua" };
+        assert!(std::fs::read(out).unwrap().starts_with(signature));
+
+        let output = yuna(&["run",out,"lua"],"");
+        assert_eq!(String::from_utf8_lossy(&output.stdout),"hello lua\n");
+    }
+
+    let broken = script("yuna_cli_compile_broken.lua","x = = 1\n");
+    let output = yuna(&["compile",&broken,"-o","/dev/null"],"");
+    assert_eq!(output.status.code(),Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unexpected symbol"));
+}
+
+#[test]
+fn compile_check_tree() {
+    let root = std::env::temp_dir().join("yuna_cli_check");
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(root.join("ai")).unwrap();
+    std::fs::write(root.join("main.lua"),"#!/usr/bin/env yuna\nreturn 1\n").unwrap();
+    std::fs::write(root.join("notes.txt"),"not lua at all (").unwrap();
+    std::fs::write(root.join("ai/brain.lua"),"local t = {\n1\n").unwrap();
+    std::fs::write(root.join("ai/eyes.lua"),"\nif x then\n").unwrap();
+
+    let output = yuna(&["compile","--check",root.to_str().unwrap()],"");
+    assert_eq!(output.status.code(),Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let lines: Vec<&str> = stderr.lines().collect();
+    assert_eq!(lines.len(),2);
+    let eof = if cfg!(any(feature = "lua51", feature = "luajit")) { "'<eof>'" } else { "<eof>" };
+    assert!(lines[0].ends_with(&format!("brain.lua:3: '}}' expected (to close '{{' at line 1) near {}",eof)));
+    assert!(lines[1].ends_with(&format!("eyes.lua:3: 'end' expected (to close 'if' at line 2) near {}",eof)));
+
+    let output = yuna(&["compile","--check",root.join("main.lua").to_str().unwrap()],"");
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+
+    // A byte order mark is skipped before the first line
+    std::fs::write(root.join("bom.lua"),b"\xEF\xBB\xBF#!/usr/bin/env yuna\nreturn '\xE9'\n").unwrap();
+    let output = yuna(&["compile","--check",root.join("bom.lua").to_str().unwrap()],"");
+    assert!(output.status.success());
+    std::fs::write(root.join("bom.lua"),b"\xEF\xBB\xBF#!/usr/bin/env yuna\nif x then\n").unwrap();
+    let output = yuna(&["compile","--check",root.join("bom.lua").to_str().unwrap()],"");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("bom.lua:3: 'end' expected"));
+}
@@ -0,0 +1,111 @@
+extern crate yuna;
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use yuna::{HookAction, HookMask};
+
+const SCRIPT: &str = "local M = {}
+
+function M.used(x)
+  if x > 0 then
+    return x
+  end
+  return -x
+end
+
+function M.unused()
+  return 1
+end
+
+for i = 1, 3 do
+  M.used(i)
+end
+return M
+";
+
+#[test]
+fn coverage_lines() {
+    let mut state = yuna::State::new();
+    state.start_coverage();
+    let f = state.load(SCRIPT.as_bytes(),"@mod.lua").unwrap();
+    f.call(vec![]).unwrap();
+    let coverage = state.stop_coverage();
+
+    assert_eq!(coverage.chunks(),vec!["@mod.lua"]);
+    assert_eq!(coverage.lines("@mod.lua"),vec![(1,1),(3,1),(4,3),(5,3),(7,0),(8,1),(10,1),(11,0),(12,1),(14,4),(15,3),(17,1)]);
+    assert_eq!(coverage.lines("@other.lua"),vec![]);
+
+    // Nothing is collected anymore
+    state.do_string("local x = 1").unwrap();
+    assert_eq!(state.stop_coverage().chunks().len(),0);
+}
+
+#[test]
+fn coverage_out_of_memory() {
+    // Running out of memory while the hook reads the lines of a function leaves the
+    // coverage usable
+    for extra in (0..4000).step_by(20) {
+        let mut state = yuna::State::new();
+        state.start_coverage();
+        let f = state.load(SCRIPT.as_bytes(),"@mod.lua").unwrap();
+        // Below the memory in use, which only works once the garbage of loading is collected
+        state.set_memory_limit(Some(state.memory_used() - 2000 + extra));
+        let _ = f.call(vec![]);
+        state.set_memory_limit(None);
+        state.stop_coverage();
+
+        state.start_coverage();
+        state.load(SCRIPT.as_bytes(),"@mod.lua").unwrap().call(vec![]).unwrap();
+        let coverage = state.stop_coverage();
+        assert_eq!(coverage.lines("@mod.lua").len(),12);
+    }
+}
+
+#[test]
+fn coverage_lcov_and_json() {
+    let mut state = yuna::State::new();
+    state.start_coverage();
+    state.load(b"local a = 1\nlocal function f()\n  return a\nend","=embedded/a.lua").unwrap().call(vec![]).unwrap();
+    state.do_string("local b = \"\\t\"").unwrap();
+    let mut coverage = state.stop_coverage();
+
+    assert_eq!(coverage.lcov(),"TN:\nSF:embedded/a.lua\nDA:1,1\nDA:3,0\nDA:4,1\nLF:3\nLH:2\nend_of_record\n");
+    assert_eq!(coverage.json(),"{\"=embedded/a.lua\":{\"1\":1,\"3\":0,\"4\":1},\"local b = \\\"\\\\t\\\"\":{\"1\":1}}");
+
+    state.start_coverage();
+    state.load(b"local a = 1\nlocal function f()\n  return a\nend\nf()","=embedded/a.lua").unwrap().call(vec![]).unwrap();
+    coverage.merge(&state.stop_coverage());
+    // LuaJIT reports the line of a call again once it returns
+    let calls = if cfg!(feature = "luajit") { 2 } else { 1 };
+    assert_eq!(coverage.lines("=embedded/a.lua"),vec![(1,2),(3,1),(4,2),(5,calls)]);
+}
+
+#[test]
+fn coverage_nested_functions() {
+    // Deeply nested and vararg functions far down a long chunk, none of them called
+    let script = format!("{}local function outer(...)\n  local function inner()\n    return 1\n  end\n  return inner, ...\nend\nlocal x = 1\n","\n".repeat(70000));
+    let mut state = yuna::State::new();
+    state.start_coverage();
+    state.load(script.as_bytes(),"@long.lua").unwrap().call(vec![]).unwrap();
+    let coverage = state.stop_coverage();
+
+    assert_eq!(coverage.lines("@long.lua"),vec![(70003,0),(70004,0),(70005,0),(70006,1),(70007,1)]);
+}
+
+#[test]
+fn coverage_with_hook() {
+    let mut state = yuna::State::new();
+    let counted = Rc::new(Cell::new(0));
+    let counter = counted.clone();
+    state.set_hook(HookMask { count: Some(1), ..HookMask::default() },move |_,info| {
+        assert_eq!(info.event(),yuna::HookEvent::Count);
+        counter.set(counter.get() + 1);
+        HookAction::Continue
+    });
+    state.start_coverage();
+    state.load(b"local a = 1\nlocal b = 2","@hook.lua").unwrap().call(vec![]).unwrap();
+    let coverage = state.stop_coverage();
+    assert_eq!(coverage.lines("@hook.lua"),vec![(1,1),(2,1)]);
+    assert!(counted.get() >= 2);
+}
@@ -0,0 +1,195 @@
+#![cfg(feature = "dap")]
+
+extern crate serde_json;
+extern crate yuna;
+
+use std::io::BufReader;
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use yuna::dap::{self, Debugger};
+
+const SCRIPT: &str = "local function add(a, b)
+  local sum = a + b
+  return sum
+end
+local t = { name = 'orc', stats = { hp = 10 } }
+local x = add(1, 2)
+print('x is', x)
+local y = x * 2
+";
+
+/// A scripted debug client.
+struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    seq: i64,
+    events: Vec<Value>,
+}
+
+impl Client {
+    fn connect(port: u16) -> Client {
+        let stream = TcpStream::connect(("127.0.0.1",port)).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        Client { reader: BufReader::new(stream.try_clone().unwrap()), writer: stream, seq: 0, events: Vec::new() }
+    }
+
+    fn read(&mut self) -> Value {
+        dap::read_message(&mut self.reader).unwrap().expect("debugger closed the connection")
+    }
+
+    /// Sends a request and returns its response, events are kept for `event`.
+    fn request(&mut self,command: &str,arguments: Value) -> Value {
+        self.seq += 1;
+        dap::write_message(&mut self.writer,&json!({
+            "seq": self.seq,
+            "type": "request",
+            "command": command,
+            "arguments": arguments,
+        })).unwrap();
+        loop {
+            let message = self.read();
+            if message["type"] == "response" && message["request_seq"] == self.seq {
+                assert_eq!(message["command"],command);
+                return message;
+            }
+            self.events.push(message);
+        }
+    }
+
+    /// Returns the body of the next event with the given name.
+    fn event(&mut self,name: &str) -> Value {
+        if let Some(i) = self.events.iter().position(|event| event["event"] == name) {
+            return self.events.remove(i)["body"].take();
+        }
+        loop {
+            let mut message = self.read();
+            if message["event"] == name {
+                return message["body"].take();
+            }
+            self.events.push(message);
+        }
+    }
+
+    fn top_frame(&mut self) -> Value {
+        let response = self.request("stackTrace",json!({ "threadId": 1 }));
+        response["body"]["stackFrames"][0].clone()
+    }
+
+    /// Returns the variables of a reference as (name, value, reference).
+    fn variables(&mut self,reference: &Value) -> Vec<(String,String,u64)> {
+        let response = self.request("variables",json!({ "variablesReference": reference }));
+        response["body"]["variables"].as_array().unwrap().iter().map(|v| {
+            (v["name"].as_str().unwrap().to_string(),v["value"].as_str().unwrap().to_string(),v["variablesReference"].as_u64().unwrap())
+        }).collect()
+    }
+
+    fn locals(&mut self,frame: u64) -> Vec<(String,String,u64)> {
+        let response = self.request("scopes",json!({ "frameId": frame }));
+        let reference = response["body"]["scopes"][0]["variablesReference"].clone();
+        self.variables(&reference)
+    }
+
+    fn evaluate(&mut self,expression: &str,frame: u64) -> Value {
+        self.request("evaluate",json!({ "expression": expression, "frameId": frame, "context": "watch" }))
+    }
+}
+
+/// Runs the script under the debugger while the client runs on another thread.
+fn debug<F: FnOnce(&mut Client) + Send + 'static>(name: &str,client: F) {
+    let path = std::env::temp_dir().join(name);
+    std::fs::write(&path,SCRIPT).unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let path_arg = path.to_str().unwrap().to_string();
+    let client = thread::spawn(move || {
+        let mut client_conn = Client::connect(port);
+        let response = client_conn.request("initialize",json!({ "adapterID": "yuna", "linesStartAt1": true }));
+        assert_eq!(response["success"],true);
+        client_conn.event("initialized");
+        client_conn.events.push(json!({ "event": "script", "body": path_arg }));
+        client(&mut client_conn);
+    });
+
+    let (stream,_) = listener.accept().unwrap();
+    let debugger = Debugger::new(stream.try_clone().unwrap(),stream);
+    let mut state = yuna::State::new();
+    state.openlibs();
+    debugger.attach(&mut state).unwrap();
+    let result = state.do_file(&path);
+    debugger.finish(if result.is_ok() { 0 } else { 1 });
+    client.join().unwrap();
+    result.unwrap();
+}
+
+#[test]
+fn dap_breakpoints_and_stepping() {
+    debug("yuna_dap_stepping.lua",|client| {
+        let path = client.event("script");
+        let response = client.request("setBreakpoints",json!({ "source": { "path": path }, "breakpoints": [{ "line": 2 }] }));
+        assert_eq!(response["body"]["breakpoints"][0]["verified"],true);
+        client.request("launch",json!({}));
+        client.request("configurationDone",json!({}));
+
+        assert_eq!(client.event("stopped")["reason"],"breakpoint");
+        let frames = client.request("stackTrace",json!({ "threadId": 1 }))["body"]["stackFrames"].clone();
+        assert_eq!(frames[0]["name"],"add");
+        assert_eq!(frames[0]["line"],2);
+        assert!(frames[0]["source"]["path"].as_str().unwrap().ends_with("yuna_dap_stepping.lua"));
+        assert_eq!(frames[1]["name"],"main chunk");
+        assert_eq!(frames[1]["line"],6);
+        assert_eq!(client.locals(1),vec![(String::from("a"),String::from("1"),0),(String::from("b"),String::from("2"),0)]);
+        assert_eq!(client.evaluate("a + b",1)["body"]["result"],"3");
+
+        client.request("next",json!({ "threadId": 1 }));
+        assert_eq!(client.event("stopped")["reason"],"step");
+        assert_eq!(client.top_frame()["line"],3);
+        assert_eq!(client.evaluate("sum",1)["body"]["result"],"3");
+
+        client.request("stepOut",json!({ "threadId": 1 }));
+        client.event("stopped");
+        let frame = client.top_frame();
+        assert_eq!(frame["name"],"main chunk");
+        assert_eq!(frame["line"],7);
+
+        // Tables are expanded one level at a time
+        let locals = client.locals(1);
+        let names: Vec<&str> = locals.iter().map(|l| l.0.as_str()).collect();
+        assert_eq!(names,vec!["add","t","x"]);
+        assert_eq!(locals[2].1,"3");
+        let t = client.variables(&json!(locals[1].2));
+        assert_eq!(t[0],(String::from("name"),String::from("\"orc\""),0));
+        assert_eq!(t[1].0,"stats");
+        let stats = client.variables(&json!(t[1].2));
+        assert_eq!(stats,vec![(String::from("hp"),String::from("10"),0)]);
+
+        let response = client.evaluate("t.missing.field",1);
+        assert_eq!(response["success"],false);
+
+        client.request("continue",json!({ "threadId": 1 }));
+        assert_eq!(client.event("output")["output"],"x is\t3\n");
+        assert_eq!(client.event("exited")["exitCode"],0);
+        client.event("terminated");
+    });
+}
+
+#[test]
+fn dap_stop_on_entry_and_disconnect() {
+    debug("yuna_dap_entry.lua",|client| {
+        client.event("script");
+        client.request("launch",json!({ "stopOnEntry": true }));
+        client.request("configurationDone",json!({}));
+
+        assert_eq!(client.event("stopped")["reason"],"entry");
+        assert_eq!(client.top_frame()["line"],4);
+        let threads = client.request("threads",json!({}));
+        assert_eq!(threads["body"]["threads"][0]["id"],1);
+
+        // Without the client the script runs to the end
+        client.request("disconnect",json!({}));
+        client.event("terminated");
+    });
+}
@@ -0,0 +1,91 @@
+#![cfg(feature = "derive")]
+
+extern crate yuna;
+
+use yuna::{LuaContext, LuaIndex, LuaRead, LuaValue, LuaWrite, Table};
+
+#[derive(LuaRead,LuaWrite,Debug,PartialEq)]
+enum Class {
+    Warrior,
+    #[lua(rename = "mage")]
+    Wizard,
+}
+
+#[derive(LuaRead,LuaWrite,Debug,PartialEq)]
+struct Hero {
+    name: String,
+    #[lua(rename = "hp")]
+    health: i32,
+    class: Class,
+    #[lua(default)]
+    level: u8,
+    #[lua(skip)]
+    cached: Option<f64>,
+}
+
+#[derive(LuaRead,LuaWrite,Debug,PartialEq)]
+struct Point(f32,f32);
+
+#[test]
+fn write_struct() {
+    let context = LuaContext::new();
+    let hero = Hero { name: String::from("Yuna"), health: 30, class: Class::Wizard, level: 4, cached: Some(1.0) };
+
+    unsafe { LuaWrite::lua_write(&context,hero) };
+    let table : Table = unsafe { LuaRead::lua_read_index(&context,-1) }.unwrap();
+
+    assert_eq!(table.get("name"),LuaValue::from_string("Yuna"));
+    assert_eq!(table.get("hp"),LuaValue::from_number(30));
+    assert_eq!(table.get("class"),LuaValue::from_string("mage"));
+    assert_eq!(table.get("level"),LuaValue::from_number(4));
+    assert_eq!(table.get("cached"),LuaValue::Nil);
+}
+
+#[test]
+fn read_struct() {
+    let context = LuaContext::new();
+    let mut table = Table::new(&context);
+    table.set("name","Auron");
+    table.set("hp",99);
+    table.set("class","Warrior");
+
+    unsafe { LuaWrite::lua_write(&context,&table) };
+    let hero : Hero = unsafe { LuaRead::lua_read_index(&context,-1) }.unwrap();
+
+    assert_eq!(hero,Hero { name: String::from("Auron"), health: 99, class: Class::Warrior, level: 0, cached: None });
+}
+
+#[test]
+fn read_struct_missing_field() {
+    let context = LuaContext::new();
+    let mut table = Table::new(&context);
+    table.set("name","Auron");
+
+    unsafe { LuaWrite::lua_write(&context,&table) };
+    let hero : Result<Hero,()> = unsafe { LuaRead::lua_read_index(&context,-1) };
+
+    assert_eq!(hero,Err(()));
+}
+
+#[test]
+fn tuple_struct_as_array() {
+    let context = LuaContext::new();
+
+    unsafe { LuaWrite::lua_write(&context,Point(1.5,-2.0)) };
+    let table : Table = unsafe { LuaRead::lua_read_index(&context,-1) }.unwrap();
+    assert_eq!(table.len(),2);
+    assert_eq!(table.get(1),LuaValue::from_number(1.5));
+
+    let point : Point = unsafe { LuaRead::lua_read_index(&context,-1) }.unwrap();
+    assert_eq!(point,Point(1.5,-2.0));
+}
+
+#[test]
+fn unknown_variant() {
+    let context = LuaContext::new();
+
+    unsafe { LuaWrite::lua_write(&context,"Thief") };
+    let class : Result<Class,()> = unsafe { LuaRead::lua_read_index(&context,-1) };
+
+    assert_eq!(class,Err(()));
+}
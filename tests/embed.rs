@@ -0,0 +1,39 @@
+#![cfg(feature = "derive")]
+
+#[macro_use]
+extern crate yuna;
+
+use yuna::{LuaIndex, ScriptSource};
+
+fn check_scripts(scripts: yuna::EmbeddedSource) {
+    assert_eq!(scripts.paths(),vec!["ourgame/init.lua","ourgame/physics.lua"]);
+
+    let mut state = yuna::State::new();
+    state.openlibs();
+    state.add_script_source(scripts);
+
+    state.do_string("gravity = require('ourgame.physics').gravity name = require('ourgame').name").unwrap();
+    assert_eq!(state.read::<_,f64>("gravity"),Ok(9.81));
+    assert_eq!(state.read::<_,String>("name"),Ok(String::from("ourgame")));
+
+    let err = state.do_string("require('ourgame.physics').fail()").unwrap_err();
+    assert_eq!(err.source(),Some("ourgame/physics.lua"));
+    assert_eq!(err.line(),Some(6));
+    assert_eq!(err.message_without_location(),"physics broke");
+}
+
+#[test]
+fn embed_scripts_source() {
+    let scripts = embed_scripts!("tests/scripts");
+    assert_eq!(scripts.find("ourgame").unwrap().0,b"return { name = \"ourgame\" }\n".to_vec());
+    check_scripts(scripts);
+}
+
+#[cfg(feature = "precompile")]
+#[test]
+fn embed_scripts_precompiled() {
+    let scripts = embed_scripts!("tests/scripts",precompile);
+    // Lua bytecode starts with "\x1bLua"
+    assert!(scripts.find("ourgame").unwrap().0.starts_with(b"\x1bLua"));
+    check_scripts(scripts);
+}
@@ -0,0 +1,276 @@
+extern crate yuna;
+
+use std::cell::Cell;
+use std::error::Error;
+use std::fmt;
+use std::panic;
+use std::rc::Rc;
+
+use yuna::{LuaIndex, LuaValue};
+
+#[test]
+fn function_called_from_lua() {
+    let mut state = yuna::State::new();
+    let add = state.create_function(|state,args| {
+        let a : f64 = state.unpack(args[0].clone()).unwrap();
+        let b : f64 = state.unpack(args[1].clone()).unwrap();
+        Ok(vec![state.pack(a + b),state.pack("done")])
+    });
+    state.set("add",&add);
+
+    state.do_string("sum, msg = add(40,2)").unwrap();
+
+    let sum : i32 = state.read("sum").unwrap();
+    let msg : String = state.read("msg").unwrap();
+    assert_eq!(sum,42);
+    assert_eq!(msg,"done");
+}
+
+#[test]
+fn function_call_lua_function() {
+    let mut state = yuna::State::new();
+    state.openlibs();
+    state.do_string("function f(...) return select('#',...), ... end").unwrap();
+
+    let f : yuna::Function = state.read("f").unwrap();
+    let results = f.call(vec![LuaValue::from_number(1),LuaValue::Nil]).unwrap();
+    assert_eq!(results,vec![LuaValue::from_number(2),LuaValue::from_number(1),LuaValue::Nil]);
+}
+
+#[test]
+fn function_error_becomes_lua_error() {
+    let mut state = yuna::State::new();
+    state.openlibs();
+    let fail = state.create_function(|_,_| Err(From::from("not allowed")));
+    state.set("fail",&fail);
+
+    state.do_string("ok, err = pcall(fail) err = tostring(err)").unwrap();
+    let ok : bool = state.read("ok").unwrap();
+    let err : String = state.read("err").unwrap();
+    assert!(!ok);
+    assert_eq!(err,"not allowed");
+
+    let err = state.do_string("fail()").unwrap_err();
+    assert_eq!(err.message(),"not allowed");
+}
+
+#[test]
+fn function_panic_is_resumed() {
+    let mut state = yuna::State::new();
+    state.openlibs();
+    let boom = state.create_function(|_,_| panic!("boom"));
+    state.set("boom",&boom);
+
+    // Scripts see the panic as an ordinary error
+    state.do_string("ok, err = pcall(boom) msg = tostring(err)").unwrap();
+    let msg : String = state.read("msg").unwrap();
+    assert_eq!(msg,"rust panic: boom");
+
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| state.do_string("boom()")));
+    let payload = result.unwrap_err();
+    assert_eq!(payload.downcast_ref::<&str>(),Some(&"boom"));
+
+    // The state is still usable afterwards
+    state.do_string("x = 1").unwrap();
+    assert_eq!(state.read::<_,i32>("x"),Ok(1));
+}
+
+#[test]
+fn function_metamethod_error() {
+    let mut state = yuna::State::new();
+    state.openlibs();
+    state.do_string("t = setmetatable({},{__newindex = function() error('read only',0) end,
+                                          __index = function() error('no fields',0) end})").unwrap();
+
+    let mut t : yuna::Table = state.read("t").unwrap();
+    let err = t.try_set("x",1).unwrap_err();
+    assert_eq!(err.message(),"read only");
+    assert_eq!(t.read::<_,i32>("x"),Err(()));
+}
+
+#[test]
+fn function_value_roundtrip() {
+    let mut state = yuna::State::new();
+    state.do_string("function f() end").unwrap();
+
+    let f = state.get("f");
+    match f {
+        LuaValue::LuaFunction(_) => {},
+        ref other => panic!("expected function, got {:?}",other),
+    }
+    state.set("g",f);
+    state.do_string("same = f == g").unwrap();
+    assert_eq!(state.read::<_,bool>("same"),Ok(true));
+}
+
+#[derive(Debug,PartialEq)]
+struct QuotaExceeded {
+    limit: u32,
+}
+
+impl fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,"quota of {} exceeded",self.limit)
+    }
+}
+
+impl Error for QuotaExceeded {}
+
+#[test]
+fn function_error_roundtrip() {
+    let mut state = yuna::State::new();
+    state.openlibs();
+    let spend = state.create_function(|_,_| Err(Box::new(QuotaExceeded { limit: 10 })));
+    state.set("spend",&spend);
+
+    // The error passes through script code untouched
+    let err = state.do_string("local ok, err = pcall(spend)
+                               assert(tostring(err) == 'quota of 10 exceeded')
+                               error(err)").unwrap_err();
+    assert_eq!(err.message(),"quota of 10 exceeded");
+    assert_eq!(err.downcast_ref::<QuotaExceeded>(),Some(&QuotaExceeded { limit: 10 }));
+    assert!(err.source().is_some());
+    assert!(err.value().is_none());
+}
+
+#[test]
+fn function_error_metamethods_check_arguments() {
+    let mut state = yuna::State::new();
+    state.openlibs();
+    let spend = state.create_function(|_,_| Err(Box::new(QuotaExceeded { limit: 10 })));
+    state.set("spend",&spend);
+
+    state.do_string("local ok, err = pcall(spend)
+                     assert(getmetatable(err) == false)
+                     local mt = debug.getmetatable(err)
+                     assert(not pcall(mt.__tostring, {}))
+                     assert(not pcall(mt.__tostring, io.stdout))
+                     assert(not pcall(mt.__gc, 'x'))
+                     mt.__gc(err)
+                     mt.__gc(err)
+                     assert(tostring(err) == 'dropped rust error')").unwrap();
+}
+
+#[test]
+fn function_error_value() {
+    let mut state = yuna::State::new();
+    state.openlibs();
+
+    let err = state.do_string("error({code = 42})").unwrap_err();
+    assert_eq!(err.message(),"(error object is a table value)");
+    assert!(err.downcast_ref::<QuotaExceeded>().is_none());
+    let code : i32 = match err.value() {
+        Some(LuaValue::LuaTable(t)) => t.read("code").unwrap(),
+        other => panic!("expected table, got {:?}",other),
+    };
+    assert_eq!(code,42);
+
+    // A callback returning the error raises the original value again
+    let call = state.create_function(|state,args| {
+        let f : yuna::Function = state.unpack(args[0].clone()).unwrap();
+        f.call(vec![])?;
+        Ok(vec![])
+    });
+    state.set("call",&call);
+    state.do_string("local ok, err = pcall(call,function() error({code = 7}) end)
+                     assert(err.code == 7)").unwrap();
+}
+
+#[test]
+fn function_dump() {
+    let mut state = yuna::State::new();
+    state.openlibs();
+    let f = state.load(b"local answer = 42\nerror('dumped ' .. answer)","=original").unwrap();
+
+    let full = f.dump(false).unwrap();
+    let stripped = f.dump(true).unwrap();
+    let signature: &[u8] = if cfg!(feature = "luajit") { b"\x1bLJ" } else { b"\x1bLua" };
+    assert!(full.starts_with(signature));
+    assert!(stripped.len() < full.len());
+
+    let err = state.load(&full,"=copy").unwrap().call(vec![]).unwrap_err();
+    assert_eq!(err.message(),"original:2: dumped 42");
+    let err = state.load(&stripped,"=copy").unwrap().call(vec![]).unwrap_err();
+    assert!(!err.message().contains("original"));
+    assert!(err.message().ends_with("dumped 42"));
+
+    let print : yuna::Function = state.read("print").unwrap();
+    assert_eq!(print.dump(false),None);
+}
+
+#[test]
+fn function_called_while_closing() {
+    let called = Rc::new(Cell::new(false));
+    let mut state = yuna::State::new();
+    state.openlibs();
+    let flag = called.clone();
+    let f = state.create_function(move |_,_| {
+        flag.set(true);
+        Ok(vec![])
+    });
+    state.set("f",&f);
+    drop(f);
+    // Lua 5.1 only calls __gc of userdata
+    state.do_string("local finalizer = {__gc = function() f() end}
+                     if newproxy then
+                         kept = newproxy(true)
+                         getmetatable(kept).__gc = finalizer.__gc
+                     else
+                         kept = setmetatable({},finalizer)
+                     end").unwrap();
+
+    // The finalizer runs while the state is closed, it can't reach the callback anymore
+    drop(state);
+    assert!(!called.get());
+    assert_eq!(Rc::strong_count(&called),1);
+}
+
+#[test]
+fn function_metamethods_check_arguments() {
+    let captured = Rc::new(Cell::new(0));
+    let mut state = yuna::State::new();
+    state.openlibs();
+    let counter = captured.clone();
+    let f = state.create_function(move |_,_| {
+        counter.set(counter.get() + 1);
+        Ok(vec![])
+    });
+    let boom = state.create_function(|_,_| panic!("boom"));
+    state.set("f",&f);
+    state.set("boom",&boom);
+    drop((f,boom));
+
+    // The metamethods are hidden, with the debug library they reject other values. Lua 5.1
+    // doesn't show the upvalues of C functions.
+    state.do_string("f()
+                     local _, callback = debug.getupvalue(f, 1)
+                     if callback ~= nil then
+                         assert(getmetatable(callback) == false)
+                         local mt = debug.getmetatable(callback)
+                         assert(not pcall(mt.__gc, 'x'))
+                         assert(not pcall(mt.__gc, {}))
+                         assert(not pcall(mt.__gc, io.stdout))
+                         mt.__gc(callback)
+                         mt.__gc(callback)
+                         local ok, err = pcall(f)
+                         assert(not ok and tostring(err):find('dropped'))
+                     end
+
+                     local _, e = pcall(boom)
+                     assert(getmetatable(e) == false)
+                     local mt = debug.getmetatable(e)
+                     assert(not pcall(mt.__tostring, {}))
+                     assert(not pcall(mt.__gc, io.stdout))
+                     mt.__gc(e)
+                     mt.__gc(e)
+                     assert(tostring(e) == 'rust panic: resumed')
+
+                     if callback ~= nil then
+                         debug.setupvalue(f, 1, e)
+                         local ok, err = pcall(f)
+                         assert(not ok and tostring(err):find('replaced'))
+                     end").unwrap();
+    drop(state);
+    assert_eq!(captured.get(),1);
+    assert_eq!(Rc::strong_count(&captured),1);
+}
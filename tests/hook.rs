@@ -0,0 +1,95 @@
+extern crate yuna;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use yuna::{HookAction, HookEvent, HookMask, LuaIndex};
+
+#[test]
+fn hook_calls_and_returns() {
+    let mut state = yuna::State::new();
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let seen = events.clone();
+    state.set_hook(HookMask { call: true, ret: true, ..HookMask::default() },move |_,info| {
+        if info.what() == "Lua" {
+            seen.borrow_mut().push((info.event(),info.name(),info.line_defined()));
+        }
+        HookAction::Continue
+    });
+
+    state.do_string("local function add(a, b)\n  return a + b\nend\nlocal function twice(x)\n  return add(x, x)\nend\nlocal y = twice(2)").unwrap();
+
+    let events = events.borrow();
+    // Lua 5.1 and LuaJIT report tail calls as calls
+    #[cfg(not(any(feature = "lua51", feature = "luajit")))]
+    let tail_call = (HookEvent::TailCall,None,Some(1));
+    #[cfg(feature = "lua51")]
+    let tail_call = (HookEvent::Call,Some(String::from("add")),Some(1));
+    #[cfg(feature = "luajit")]
+    let tail_call = (HookEvent::Call,Some(String::from("twice")),Some(1));
+    #[cfg(not(feature = "luajit"))]
+    let ret = (HookEvent::Return,None,Some(1));
+    #[cfg(feature = "luajit")]
+    let ret = (HookEvent::Return,Some(String::from("twice")),Some(1));
+    assert_eq!(*events,vec![(HookEvent::Call,Some(String::from("twice")),Some(4)),tail_call,ret]);
+}
+
+#[test]
+fn hook_lines_and_source() {
+    let mut state = yuna::State::new();
+    let lines = Rc::new(RefCell::new(Vec::new()));
+    let seen = lines.clone();
+    state.set_hook(HookMask { line: true, ..HookMask::default() },move |_,info| {
+        seen.borrow_mut().push((info.short_source(),info.source(),info.line()));
+        HookAction::Continue
+    });
+
+    let f = state.load(b"local a = 1\n\nlocal b = 2","=lines.lua").unwrap();
+    f.call(vec![]).unwrap();
+    assert_eq!(*lines.borrow(),vec![(String::from("lines.lua"),String::from("=lines.lua"),Some(1)),
+                                    (String::from("lines.lua"),String::from("=lines.lua"),Some(3))]);
+
+    state.remove_hook();
+    lines.borrow_mut().clear();
+    state.do_string("local c = 3").unwrap();
+    assert!(lines.borrow().is_empty());
+}
+
+#[test]
+fn hook_error_aborts() {
+    let mut state = yuna::State::new();
+    state.openlibs();
+    let mut counts = 0;
+    state.set_hook(HookMask { count: Some(100), ..HookMask::default() },move |_,info| {
+        assert_eq!(info.event(),HookEvent::Count);
+        counts += 1;
+        if counts % 5 == 0 { HookAction::Error(String::from("timeout")) } else { HookAction::Continue }
+    });
+
+    let err = state.do_string("while true do end").unwrap_err();
+    assert_eq!(err.message(),"timeout");
+
+    // Scripts can catch the error like any other
+    state.do_string("ok, err = pcall(function() while true do end end)").unwrap();
+    assert_eq!(state.read::<_,String>("err"),Ok(String::from("timeout")));
+}
+
+#[test]
+fn hook_with_instruction_limit() {
+    let mut state = yuna::State::new();
+    let counts = Rc::new(RefCell::new(0));
+    let seen = counts.clone();
+    state.set_hook(HookMask { count: Some(300), ..HookMask::default() },move |_,_| {
+        *seen.borrow_mut() += 1;
+        HookAction::Continue
+    });
+    state.set_instruction_limit(Some(3000));
+
+    let err = state.do_string("while true do end").unwrap_err();
+    assert_eq!(err.message(),"instruction limit exceeded");
+    // The limit counts every 100 instructions, the hook every 300
+    assert_eq!(*counts.borrow(),10);
+
+    state.set_instruction_limit(None);
+    state.do_string("for i = 1, 10 do end").unwrap();
+}
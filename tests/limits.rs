@@ -0,0 +1,102 @@
+extern crate yuna;
+
+use yuna::{Library, LuaIndex, LuaValue};
+
+#[test]
+fn open_single_libraries() {
+    let mut state = yuna::State::new();
+    state.open_libs(&[Library::Base,Library::Table]);
+
+    assert!(state.get("table") != LuaValue::Nil);
+    assert!(state.get("print") != LuaValue::Nil);
+    assert_eq!(state.get("io"),LuaValue::Nil);
+    assert_eq!(state.get("os"),LuaValue::Nil);
+
+    assert_eq!(Library::from_name("base"),Some(Library::Base));
+    assert_eq!(Library::from_name("math"),Some(Library::Math));
+    assert_eq!(Library::from_name("socket"),None);
+}
+
+#[test]
+fn sandbox() {
+    let mut state = yuna::State::new();
+    state.sandbox();
+
+    state.do_string("assert(io == nil and os == nil and debug == nil and package == nil)
+                     assert(dofile == nil and loadfile == nil)
+                     assert(load('return 1 + 1')() == 2)").unwrap();
+
+    let bytecode = state.create_string("\x1bLua");
+    state.set("bytecode",bytecode);
+    state.do_string("local f, err = load(bytecode)
+                     assert(f == nil and err:find('binary'))").unwrap();
+}
+
+#[test]
+fn memory_limit() {
+    let mut state = yuna::State::new();
+    state.openlibs();
+    assert!(state.memory_used() > 0);
+
+    state.set_memory_limit(Some(state.memory_used() + 100000));
+    match state.do_string("local t = {} for i = 1, 1e6 do t[i] = i end") {
+        Err(yuna::LuaError::Memory) => {},
+        other => panic!("expected memory error, got {:?}",other),
+    }
+
+    // The state recovers once the garbage is collected
+    state.set_memory_limit(None);
+    state.do_string("collectgarbage() x = string.rep('x', 1000)").unwrap();
+}
+
+#[test]
+fn memory_limit_from_rust() {
+    let mut state = yuna::State::new();
+    state.openlibs();
+    let big = "x".repeat(100000);
+    let f = state.create_function(|state,_| Ok(vec![state.create_string("y".repeat(100000))]));
+    state.set("f",&f);
+    state.set_memory_limit(Some(state.memory_used() + 10000));
+
+    match state.try_set("big",big.as_str()) {
+        Err(yuna::LuaError::Memory) => {},
+        other => panic!("expected memory error, got {:?}",other),
+    }
+    // Strings are only copied into lua when they are used
+    let s = state.create_string(&big);
+    match state.try_set("big",s) {
+        Err(yuna::LuaError::Memory) => {},
+        other => panic!("expected memory error, got {:?}",other),
+    }
+    let err = state.do_string("s = f()").unwrap_err();
+    assert!(err.message().contains("not enough memory"));
+
+    let created = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let mut tables = Vec::new();
+        loop {
+            tables.push(state.create_table());
+        }
+    }));
+    assert_eq!(created.unwrap_err().downcast_ref::<&str>(),Some(&"not enough memory"));
+
+    state.set_memory_limit(None);
+    state.try_set("big",big.as_str()).unwrap();
+    assert_eq!(state.read::<_,String>("big").unwrap().len(),100000);
+}
+
+#[test]
+fn instruction_limit() {
+    let mut state = yuna::State::new();
+    state.openlibs();
+
+    state.set_instruction_limit(Some(10000));
+    let err = state.do_string("while true do end").unwrap_err();
+    assert_eq!(err.message(),"instruction limit exceeded");
+
+    // Setting the limit again resets the count
+    state.set_instruction_limit(Some(10000));
+    state.do_string("for i = 1, 100 do end").unwrap();
+
+    state.set_instruction_limit(None);
+    state.do_string("for i = 1, 100000 do end").unwrap();
+}
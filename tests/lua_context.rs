@@ -13,5 +13,5 @@ fn clone_context() {
     let context = LuaContext::new();
     let clone = context.clone();
 
-    assert_eq!(context.l,clone.l);
+    assert_eq!(context.as_ptr(),clone.as_ptr());
 }
@@ -1,6 +1,6 @@
 extern crate yuna;
-extern crate lua52_sys as ffi;
 
+use yuna::raw::ffi;
 use yuna::{LuaValue, LuaContext,LuaRead,LuaWrite};
 
 #[test]
@@ -59,26 +59,31 @@ fn read_luavalue() {
     let context = LuaContext::new();
     let teststr = "LuaRocks";
 
-    unsafe { ffi::lua_pushnil(context.l) };
-    let valnil : LuaValue = LuaRead::lua_read_index(&context,-1).unwrap();
+    unsafe { ffi::lua_pushnil(context.as_ptr()) };
+    let valnil : LuaValue = unsafe { LuaRead::lua_read_index(&context,-1) }.unwrap();
     assert_eq!(valnil,LuaValue::Nil);
 
     unsafe { LuaWrite::lua_write(&context, true) };
-    let valb : LuaValue = LuaRead::lua_read_index(&context,-1).unwrap();
+    let valb : LuaValue = unsafe { LuaRead::lua_read_index(&context,-1) }.unwrap();
     assert_eq!(valb,LuaValue::LuaBoolean(true));
 
     unsafe { LuaWrite::lua_write(&context, teststr) };
-    let vals : LuaValue = LuaRead::lua_read_index(&context,-1).unwrap();
+    let vals : LuaValue = unsafe { LuaRead::lua_read_index(&context,-1) }.unwrap();
     assert_eq!(vals,LuaValue::LuaString(String::from(teststr)));
 
     unsafe { LuaWrite::lua_write(&context, 68.3) };
-    let valn : LuaValue = LuaRead::lua_read_index(&context,-1).unwrap();
+    let valn : LuaValue = unsafe { LuaRead::lua_read_index(&context,-1) }.unwrap();
     assert_eq!(valn,LuaValue::LuaNumber(68.3));
 
-    unsafe { ffi::lua_newtable(context.l) };
-    let valt : LuaValue = LuaRead::lua_read_index(&context,-1).unwrap();
-    let table : yuna::Table = LuaRead::lua_read_index(&context,-1).unwrap();
+    unsafe { ffi::lua_newtable(context.as_ptr()) };
+    let valt : LuaValue = unsafe { LuaRead::lua_read_index(&context,-1) }.unwrap();
+    let table : yuna::Table = unsafe { LuaRead::lua_read_index(&context,-1) }.unwrap();
     assert_eq!(valt,LuaValue::LuaTable(table));
+
+    // Acceptable indices above the top read as nil
+    let top = unsafe { ffi::lua_gettop(context.as_ptr()) };
+    let none : LuaValue = unsafe { LuaRead::lua_read_index(&context,top + 1) }.unwrap();
+    assert_eq!(none,LuaValue::Nil);
 }
 
 #[test]
@@ -86,24 +91,48 @@ fn write_luavalue() {
     let context = LuaContext::new();
 
     unsafe { LuaWrite::lua_write(&context,LuaValue::Nil) };
-    let nilread : LuaValue = LuaRead::lua_read_index(&context,-1).unwrap();
+    let nilread : LuaValue = unsafe { LuaRead::lua_read_index(&context,-1) }.unwrap();
     assert_eq!(LuaValue::Nil,nilread);
 
     unsafe { LuaWrite::lua_write(&context,LuaValue::LuaBoolean(false)) };
-    let boolread : LuaValue = LuaRead::lua_read_index(&context,-1).unwrap();
+    let boolread : LuaValue = unsafe { LuaRead::lua_read_index(&context,-1) }.unwrap();
     assert_eq!(LuaValue::LuaBoolean(false),boolread);
 
     unsafe { LuaWrite::lua_write(&context,LuaValue::LuaNumber(22.43)) };
-    let numread : LuaValue = LuaRead::lua_read_index(&context,-1).unwrap();
+    let numread : LuaValue = unsafe { LuaRead::lua_read_index(&context,-1) }.unwrap();
     assert_eq!(LuaValue::LuaNumber(22.43),numread);
 
     let strval = LuaValue::LuaString(String::from("LuaRocks"));
     unsafe { LuaWrite::lua_write(&context,strval.clone()) };
-    let strread : LuaValue = LuaRead::lua_read_index(&context,-1).unwrap();
+    let strread : LuaValue = unsafe { LuaRead::lua_read_index(&context,-1) }.unwrap();
     assert_eq!(strval,strread);
 
     let t = yuna::Table::new(&context);
     unsafe { LuaWrite::lua_write(&context,LuaValue::LuaTable(t.clone())) };
-    let tableread : LuaValue = LuaRead::lua_read_index(&context,-1).unwrap();
+    let tableread : LuaValue = unsafe { LuaRead::lua_read_index(&context,-1) }.unwrap();
     assert_eq!(LuaValue::LuaTable(t),tableread);
 }
+
+#[test]
+fn read_userdata_and_thread() {
+    let context = LuaContext::new();
+
+    let ptr = unsafe { ffi::lua_newuserdata(context.as_ptr(),8) };
+    let ud : LuaValue = unsafe { LuaRead::lua_read_index(&context,-1) }.unwrap();
+    match ud {
+        LuaValue::LuaUserData(ref u) => assert_eq!(u.as_ptr(),ptr),
+        ref other => panic!("expected userdata, got {:?}",other),
+    }
+    assert!(format!("{:?}",ud).starts_with("LuaUserData(userdata: 0x"));
+
+    let thread = unsafe { ffi::lua_newthread(context.as_ptr()) };
+    let th : LuaValue = unsafe { LuaRead::lua_read_index(&context,-1) }.unwrap();
+    match th {
+        LuaValue::LuaThread(ref t) => assert_eq!(t.as_ptr(),thread),
+        ref other => panic!("expected thread, got {:?}",other),
+    }
+
+    unsafe { LuaWrite::lua_write(&context,th.clone()) };
+    let thread_read : LuaValue = unsafe { LuaRead::lua_read_index(&context,-1) }.unwrap();
+    assert_eq!(th,thread_read);
+}
@@ -0,0 +1,43 @@
+extern crate yuna;
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use yuna::LuaIndex;
+
+#[test]
+fn register_module_is_loaded_lazily() {
+    let mut state = yuna::State::new();
+    state.openlibs();
+
+    let builds = Rc::new(Cell::new(0));
+    let counter = builds.clone();
+    state.register_module("ourgame.physics",move |state| {
+        counter.set(counter.get() + 1);
+        let mut physics = state.create_table();
+        let fall = state.create_function(|state,args| {
+            let t : f64 = state.unpack(args[0].clone()).unwrap();
+            Ok(vec![state.pack(0.5 * 9.81 * t * t)])
+        });
+        physics.set("fall",&fall);
+        physics
+    });
+    assert_eq!(builds.get(),0);
+
+    state.do_string("local physics = require 'ourgame.physics'
+                     distance = physics.fall(2)
+                     same = physics == require('ourgame.physics')
+                     cached = package.loaded['ourgame.physics'] == physics").unwrap();
+    assert_eq!(builds.get(),1);
+
+    assert_eq!(state.read::<_,f64>("distance"),Ok(19.62));
+    assert_eq!(state.read::<_,bool>("same"),Ok(true));
+    assert_eq!(state.read::<_,bool>("cached"),Ok(true));
+}
+
+#[test]
+#[should_panic(expected = "package library not opened")]
+fn register_module_without_package_library() {
+    let mut state = yuna::State::new();
+    state.register_module("empty",|state| state.create_table());
+}
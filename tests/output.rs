@@ -0,0 +1,89 @@
+extern crate yuna;
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use yuna::LuaIndex;
+
+/// A writer whose contents can be inspected after it was moved into the state.
+#[derive(Clone,Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self,data: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SharedBuffer {
+    fn contents(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
+
+#[test]
+fn output_print_and_io_write() {
+    let mut state = yuna::State::new();
+    state.openlibs();
+    let out = SharedBuffer::default();
+    let err = SharedBuffer::default();
+    state.set_output(out.clone());
+    state.set_error_output(err.clone());
+
+    state.do_string("print(1, nil, true, setmetatable({},{__tostring = function() return 'custom' end}))
+                     io.write('x = ', 10, ' ', 0.25, '\\n'):write('chained\\n')
+                     io.stdout:write('method\\n')
+                     io.stderr:write('warning: ', 3, '\\n')").unwrap();
+
+    assert_eq!(out.contents(),"1\tnil\ttrue\tcustom\nx = 10 0.25\nchained\nmethod\n");
+    assert_eq!(err.contents(),"warning: 3\n");
+
+    let error = state.do_string("io.write({})").unwrap_err();
+    assert!(error.message().contains("bad argument #1 to 'write'"));
+}
+
+#[test]
+fn capture_output_restores_sink() {
+    let mut state = yuna::State::new();
+    state.openlibs();
+    let out = SharedBuffer::default();
+    state.set_output(out.clone());
+
+    state.do_string("print('before')").unwrap();
+    let captured = state.capture_output(|state| state.do_string("print('inside')").unwrap());
+    state.do_string("print('after')").unwrap();
+
+    assert_eq!(captured,"inside\n");
+    assert_eq!(out.contents(),"before\nafter\n");
+}
+
+#[test]
+fn output_keeps_bytes_and_files() {
+    let mut state = yuna::State::new();
+    state.openlibs();
+    let out = SharedBuffer::default();
+    state.set_output(out.clone());
+    let path = std::env::temp_dir().join(format!("yuna-output-{}.txt",std::process::id()));
+    state.set("path",path.to_str().unwrap());
+
+    state.do_string("io.write('a\\0b\\255', 1.0, '\\n')
+                     assert(io.type(io.stdout) == 'file' and io.type(io.stderr) == 'file')
+                     assert(io.stdout:setvbuf('no'))
+                     assert(io.stdout:write('method\\n') == io.stdout)
+                     local f = assert(io.open(path, 'w'))
+                     io.output(f)
+                     io.write('to file')
+                     io.output(io.stdout)
+                     f:close()
+                     io.write('back')").unwrap();
+
+    assert_eq!(&out.0.borrow()[..],&b"a\0b\xff1\nmethod\nback"[..]);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(),"to file");
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,65 @@
+extern crate yuna;
+
+use std::fs;
+use std::path::PathBuf;
+
+use yuna::{LuaIndex, PackagePathError};
+
+#[test]
+fn package_path_with_spaces() {
+    let root = std::env::temp_dir().join("yuna package path");
+    fs::create_dir_all(root.join("ui")).unwrap();
+    fs::write(root.join("config.lua"),"return { volume = 7 }").unwrap();
+    fs::write(root.join("ui/init.lua"),"return 'ui'").unwrap();
+
+    let mut state = yuna::State::new();
+    state.openlibs();
+    state.set_package_path(&[&root]).unwrap();
+
+    state.do_string("volume = require('config').volume ui = require('ui')").unwrap();
+    assert_eq!(state.read::<_,i32>("volume"),Ok(7));
+    assert_eq!(state.read::<_,String>("ui"),Ok(String::from("ui")));
+
+    assert_eq!(state.search_path("config"),Some(root.join("config.lua")));
+    assert_eq!(state.search_path("ui"),Some(PathBuf::from(format!("{}/ui/init.lua",root.display()))));
+    assert_eq!(state.search_path("missing"),None);
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn add_package_path_and_cpath() {
+    let mut state = yuna::State::new();
+    state.openlibs();
+
+    state.set_package_path(&["a"]).unwrap();
+    state.add_package_path("b/?.luac").unwrap();
+    state.set_package_cpath::<&str>(&[]).unwrap();
+    state.add_package_cpath("lib").unwrap();
+
+    let package : yuna::Table = state.read("package").unwrap();
+    assert_eq!(package.read::<_,String>("path"),Ok(String::from("a/?.lua;a/?/init.lua;b/?.luac")));
+    let cpath = if cfg!(windows) { "lib/?.dll" } else { "lib/?.so" };
+    assert_eq!(package.read::<_,String>("cpath"),Ok(String::from(cpath)));
+}
+
+#[test]
+fn package_path_errors() {
+    let mut state = yuna::State::new();
+    assert_eq!(state.add_package_path("scripts"),Err(PackagePathError::NoPackageLibrary));
+    assert_eq!(state.search_path("config"),None);
+
+    state.openlibs();
+    assert_eq!(state.set_package_path(&["a;b"]),Err(PackagePathError::Separator(PathBuf::from("a;b"))));
+
+    // Lua can't escape the separator and the placeholder in directory names
+    let root = std::env::temp_dir().join("yuna package errors");
+    let separator = root.join("a;b");
+    let placeholder = root.join("what?");
+    fs::create_dir_all(&separator).unwrap();
+    fs::create_dir_all(&placeholder).unwrap();
+    assert_eq!(state.add_package_path(&separator),Err(PackagePathError::Separator(separator.clone())));
+    assert_eq!(state.add_package_cpath(&placeholder),Err(PackagePathError::Placeholder(placeholder.clone())));
+    assert_eq!(state.set_package_path(&[placeholder.join("?.lua")]),Ok(()));
+    fs::remove_dir_all(&root).unwrap();
+}
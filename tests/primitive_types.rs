@@ -1,6 +1,6 @@
 extern crate yuna;
-extern crate lua52_sys as ffi;
 
+use yuna::raw::ffi;
 use yuna::{LuaRead,LuaWrite};
 use std::ffi::CString;
 
@@ -8,14 +8,14 @@ use std::ffi::CString;
 fn read_bool() {
     let context = yuna::LuaContext::new();
 
-    unsafe { ffi::lua_pushboolean(context.l,1) };
+    unsafe { ffi::lua_pushboolean(context.as_ptr(),1) };
 
-    let t : Result<bool,()> = LuaRead::lua_read_index(&context, -1);
+    let t : Result<bool,()> = unsafe { LuaRead::lua_read_index(&context, -1) };
     assert_eq!(t,Ok(true));
 
-    unsafe { ffi::lua_pushboolean(context.l,0) };
+    unsafe { ffi::lua_pushboolean(context.as_ptr(),0) };
 
-    let f : Result<bool,()> = LuaRead::lua_read_index(&context, -1);
+    let f : Result<bool,()> = unsafe { LuaRead::lua_read_index(&context, -1) };
     assert_eq!(f,Ok(false));
 }
 
@@ -25,9 +25,9 @@ fn read_string() {
     let teststr = "LuaRocks";
     let cstring = CString::new(teststr).unwrap();
 
-    unsafe { ffi::lua_pushstring(context.l,cstring.as_ptr()) };
+    unsafe { ffi::lua_pushstring(context.as_ptr(),cstring.as_ptr()) };
 
-    let s : Result<String,_> = LuaRead::lua_read_index(&context, -1);
+    let s : Result<String,_> = unsafe { LuaRead::lua_read_index(&context, -1) };
     assert_eq!(s,Ok(String::from(teststr)));
 }
 
@@ -35,15 +35,15 @@ fn read_string() {
 fn read_integers() {
     let context = yuna::LuaContext::new();
 
-    unsafe { ffi::lua_pushinteger(context.l,-84 as isize) };
+    unsafe { ffi::lua_pushinteger(context.as_ptr(),-84 as ffi::lua_Integer) };
 
-    let a : Result<i8,()>  = LuaRead::lua_read_index(&context, -1);
+    let a : Result<i8,()>  = unsafe { LuaRead::lua_read_index(&context, -1) };
     assert_eq!(a,Ok(-84));
 
-    let b : Result<i16,()> = LuaRead::lua_read_index(&context, -1);
+    let b : Result<i16,()> = unsafe { LuaRead::lua_read_index(&context, -1) };
     assert_eq!(b,Ok(-84));
 
-    let c : Result<i32,()> = LuaRead::lua_read_index(&context, -1);
+    let c : Result<i32,()> = unsafe { LuaRead::lua_read_index(&context, -1) };
     assert_eq!(c,Ok(-84));
 
 }
@@ -52,15 +52,15 @@ fn read_integers() {
 fn read_unsigned() {
     let context = yuna::LuaContext::new();
 
-    unsafe { ffi::lua_pushinteger(context.l,116 as isize) };
+    unsafe { ffi::lua_pushinteger(context.as_ptr(),116 as ffi::lua_Integer) };
 
-    let a : Result<u8,()>  = LuaRead::lua_read_index(&context, -1);
+    let a : Result<u8,()>  = unsafe { LuaRead::lua_read_index(&context, -1) };
     assert_eq!(a,Ok(116));
 
-    let b : Result<u16,()> = LuaRead::lua_read_index(&context, -1);
+    let b : Result<u16,()> = unsafe { LuaRead::lua_read_index(&context, -1) };
     assert_eq!(b,Ok(116));
 
-    let c : Result<u32,()> = LuaRead::lua_read_index(&context, -1);
+    let c : Result<u32,()> = unsafe { LuaRead::lua_read_index(&context, -1) };
     assert_eq!(c,Ok(116));
 
 }
@@ -69,12 +69,12 @@ fn read_unsigned() {
 fn read_float() {
     let context = yuna::LuaContext::new();
 
-    unsafe { ffi::lua_pushnumber(context.l,38.342) };
+    unsafe { ffi::lua_pushnumber(context.as_ptr(),38.342) };
 
-    let a : Result<f32,()> = LuaRead::lua_read_index(&context, -1);
+    let a : Result<f32,()> = unsafe { LuaRead::lua_read_index(&context, -1) };
     assert_eq!(a,Ok(38.342));
 
-    let b : Result<f64,()> = LuaRead::lua_read_index(&context, -1);
+    let b : Result<f64,()> = unsafe { LuaRead::lua_read_index(&context, -1) };
     assert_eq!(b,Ok(38.342));
 
 }
@@ -84,12 +84,12 @@ fn write_bool() {
     let context = yuna::LuaContext::new();
 
     unsafe { LuaWrite::lua_write(&context, false) };
-    let f : bool = LuaRead::lua_read_index(&context,-1).unwrap();
-    assert_eq!(f,false);
+    let f : bool = unsafe { LuaRead::lua_read_index(&context,-1) }.unwrap();
+    assert!(!f);
 
     unsafe { LuaWrite::lua_write(&context, true) };
-    let t : bool = LuaRead::lua_read_index(&context,-1).unwrap();
-    assert_eq!(t,true);
+    let t : bool = unsafe { LuaRead::lua_read_index(&context,-1) }.unwrap();
+    assert!(t);
 }
 
 #[test]
@@ -98,7 +98,7 @@ fn write_str() {
     let teststr = "LuaRocks";
 
     unsafe { LuaWrite::lua_write(&context, teststr) };
-    let s : String = LuaRead::lua_read_index(&context,-1).unwrap();
+    let s : String = unsafe { LuaRead::lua_read_index(&context,-1) }.unwrap();
     assert_eq!(s,String::from(teststr));
 
 }
@@ -111,15 +111,15 @@ fn write_integers() {
     let c: i32 = -842;
 
     unsafe { LuaWrite::lua_write(&context, a) };
-    let ar : i8 = LuaRead::lua_read_index(&context, -1).unwrap();
+    let ar : i8 = unsafe { LuaRead::lua_read_index(&context, -1) }.unwrap();
     assert_eq!(ar,a);
 
     unsafe { LuaWrite::lua_write(&context, b) };
-    let br : i16 = LuaRead::lua_read_index(&context, -1).unwrap();
+    let br : i16 = unsafe { LuaRead::lua_read_index(&context, -1) }.unwrap();
     assert_eq!(br,b);
 
     unsafe { LuaWrite::lua_write(&context, c) };
-    let cr : i32 = LuaRead::lua_read_index(&context, -1).unwrap();
+    let cr : i32 = unsafe { LuaRead::lua_read_index(&context, -1) }.unwrap();
     assert_eq!(cr,c);
 
 }
@@ -132,15 +132,15 @@ fn write_unsigned() {
     let c: u32 = 842;
 
     unsafe { LuaWrite::lua_write(&context, a) };
-    let ar : u8 = LuaRead::lua_read_index(&context, -1).unwrap();
+    let ar : u8 = unsafe { LuaRead::lua_read_index(&context, -1) }.unwrap();
     assert_eq!(ar,a);
 
     unsafe { LuaWrite::lua_write(&context, b) };
-    let br : u16 = LuaRead::lua_read_index(&context, -1).unwrap();
+    let br : u16 = unsafe { LuaRead::lua_read_index(&context, -1) }.unwrap();
     assert_eq!(br,b);
 
     unsafe { LuaWrite::lua_write(&context, c) };
-    let cr : u32 = LuaRead::lua_read_index(&context, -1).unwrap();
+    let cr : u32 = unsafe { LuaRead::lua_read_index(&context, -1) }.unwrap();
     assert_eq!(cr,c);
 
 }
@@ -152,11 +152,11 @@ fn write_float() {
     let b: f64 = 100123.123;
 
     unsafe { LuaWrite::lua_write(&context, a) };
-    let ar : f32 = LuaRead::lua_read_index(&context, -1).unwrap();
+    let ar : f32 = unsafe { LuaRead::lua_read_index(&context, -1) }.unwrap();
     assert_eq!(ar,a);
 
     unsafe { LuaWrite::lua_write(&context, b) };
-    let br : f64 = LuaRead::lua_read_index(&context, -1).unwrap();
+    let br : f64 = unsafe { LuaRead::lua_read_index(&context, -1) }.unwrap();
     assert_eq!(br,b);
 
 }
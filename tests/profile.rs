@@ -0,0 +1,138 @@
+extern crate yuna;
+
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use yuna::{FrameKind, HookAction, HookMask, LuaIndex, LuaValue};
+
+const SCRIPT: &str = "local function busy(n)
+  local x = 0
+  for i = 1, n do x = x + i end
+  return x
+end
+local function outer()
+  return busy(20000) + 1
+end
+outer()
+";
+
+#[test]
+fn profile_collapsed_stacks() {
+    let mut state = yuna::State::new();
+    state.start_profiler(100);
+    let f = state.load(SCRIPT.as_bytes(),"@busy.lua").unwrap();
+    f.call(vec![]).unwrap();
+    let profile = state.stop_profiler();
+
+    assert_eq!(profile.interval(),100);
+    assert!(profile.samples() > 100);
+    let collapsed = profile.collapsed();
+    let hot = collapsed.lines().find(|line| line.starts_with("main chunk (busy.lua);outer (busy.lua:6);busy (busy.lua:1) ")).unwrap();
+    let count: u64 = hot.rsplit(' ').next().unwrap().parse().unwrap();
+    assert!(count * 10 > profile.samples() * 9);
+
+    let functions = profile.functions();
+    assert_eq!(functions[0].name,Some(String::from("busy")));
+    assert_eq!(functions[0].source,"busy.lua");
+    assert_eq!(functions[0].line,Some(1));
+    assert_eq!(functions[0].self_samples,count);
+    let main = functions.iter().find(|function| function.line.is_none()).unwrap();
+    assert_eq!(main.total_samples,profile.samples());
+    assert!(main.total_time >= functions[0].self_time);
+
+    // The profiler is gone
+    state.do_string("for i = 1, 1000 do end").unwrap();
+    assert_eq!(state.stop_profiler().samples(),0);
+}
+
+#[test]
+fn profile_rust_callbacks() {
+    let mut state = yuna::State::new();
+    let each = state.create_function(|_,args| {
+        match args[0] {
+            LuaValue::LuaFunction(ref f) => {
+                for _ in 0..100 {
+                    f.call(vec![])?;
+                }
+                Ok(vec![])
+            },
+            _ => Err("function expected".into()),
+        }
+    });
+    state.set("each",&each);
+
+    state.start_profiler(50);
+    state.do_string("local function work() for i = 1, 200 do end end\neach(work)").unwrap();
+    let profile = state.stop_profiler();
+
+    // Functions called from rust have no name
+    assert!(profile.collapsed().lines().any(|line| line.contains(";each [rust];? ([string")));
+    let each = profile.functions().into_iter().find(|function| function.kind == FrameKind::Rust).unwrap();
+    assert_eq!(each.name,Some(String::from("each")));
+    // Sampled on return, the sample on entry belongs to the caller
+    assert_eq!(each.self_samples,1);
+    assert_eq!(each.total_samples,profile.samples() - 1);
+    assert!(profile.to_string().contains("each ([C])"));
+}
+
+#[test]
+fn profile_time_in_callbacks() {
+    let mut state = yuna::State::new();
+    let spin = state.create_function(|_,_| {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_millis(30) {}
+        Ok(vec![])
+    });
+    state.set("spin",&spin);
+
+    state.start_profiler(1000);
+    state.do_string("local function run() spin() end\nrun()").unwrap();
+    let profile = state.stop_profiler();
+
+    assert!(profile.collapsed().lines().any(|line| line.ends_with(";run ([string \"local function run() spin() end...\"]:1);spin [rust] 1")));
+    let spin = profile.functions().into_iter().find(|function| function.kind == FrameKind::Rust).unwrap();
+    assert_eq!(spin.name,Some(String::from("spin")));
+    assert_eq!(spin.self_samples,1);
+    assert!(spin.self_time >= Duration::from_millis(30));
+}
+
+#[test]
+fn profile_out_of_memory() {
+    // Sampling while lua is out of memory must not raise an error in the hook
+    for extra in (0..4000).step_by(20) {
+        let mut state = yuna::State::new();
+        let nop = state.create_function(|_,_| Ok(vec![]));
+        state.set("nop",&nop);
+        state.start_profiler(1);
+        let f = state.load(b"local t = {} for i = 1, 100 do nop() t[i] = {} end","=oom").unwrap();
+        // Below the memory in use, which only works once the garbage of loading is collected
+        state.set_memory_limit(Some(state.memory_used() - 2000 + extra));
+        let _ = f.call(vec![]);
+        state.set_memory_limit(None);
+        state.stop_profiler();
+
+        state.start_profiler(1);
+        state.do_string("nop()").unwrap();
+        let profile = state.stop_profiler();
+        assert!(profile.functions().iter().any(|function| function.kind == FrameKind::Rust));
+    }
+}
+
+#[test]
+fn profile_with_hook_and_limit() {
+    let mut state = yuna::State::new();
+    let counted = Rc::new(Cell::new(0));
+    let counter = counted.clone();
+    state.set_hook(HookMask { count: Some(70), ..HookMask::default() },move |_,_| {
+        counter.set(counter.get() + 1);
+        HookAction::Continue
+    });
+    state.set_instruction_limit(Some(100000));
+    state.start_profiler(30);
+    assert!(state.do_string("while true do end").is_err());
+    let profile = state.stop_profiler();
+    assert!(profile.samples() >= 3000);
+    assert!(profile.samples() <= 100000 / 30 + 1);
+    assert!(counted.get() >= 1400);
+}
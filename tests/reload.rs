@@ -0,0 +1,79 @@
+extern crate yuna;
+
+use std::fs;
+use std::path::PathBuf;
+
+use yuna::LuaIndex;
+
+/// Creates an empty directory for the files of one test.
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn reload_module_in_place() {
+    let dir = scratch("yuna_reload_module");
+    fs::write(dir.join("weapons.lua"),"local M = {}\nfunction M.damage() return 10 end\nM.old = true\nreturn M").unwrap();
+
+    let mut state = yuna::State::new();
+    state.openlibs();
+    state.set_package_path(&[&dir]).unwrap();
+    let mut reload = yuna::HotReload::new(&state);
+
+    state.do_string("weapons = require('weapons') damage = weapons.damage").unwrap();
+    assert_eq!(reload.files(),vec![dir.join("weapons.lua")]);
+    assert!(reload.poll().is_empty());
+
+    fs::write(dir.join("weapons.lua"),"local M = {}\nfunction M.damage() return 99 end\nreturn M").unwrap();
+    let reloads = reload.poll();
+    assert_eq!(reloads.len(),1);
+    assert_eq!(reloads[0].module,Some(String::from("weapons")));
+    assert!(reloads[0].result.is_ok());
+
+    // References to the module table see the new code, old fields are gone
+    state.do_string("assert(weapons.damage() == 99)
+                     assert(weapons.old == nil)
+                     assert(require('weapons') == weapons)
+                     assert(damage() == 10)").unwrap();
+}
+
+#[test]
+fn reload_errors_keep_old_code() {
+    let dir = scratch("yuna_reload_errors");
+    let script = dir.join("main.lua");
+    fs::write(&script,"counter = (counter or 0) + 1").unwrap();
+
+    let mut state = yuna::State::new();
+    state.openlibs();
+    let mut reload = yuna::HotReload::new(&state);
+    state.do_file(&script).unwrap();
+
+    fs::write(&script,"counter = = 1").unwrap();
+    let reloads = reload.poll();
+    assert_eq!(reloads.len(),1);
+    assert_eq!(reloads[0].module,None);
+    assert!(reloads[0].result.is_err());
+    assert_eq!(state.read::<_,i32>("counter"),Ok(1));
+
+    // Only changes are reloaded
+    assert!(reload.poll().is_empty());
+
+    fs::write(&script,"counter = (counter or 0) + 10").unwrap();
+    assert!(reload.poll()[0].result.is_ok());
+    assert_eq!(state.read::<_,i32>("counter"),Ok(11));
+}
+
+#[test]
+fn reload_untracked_without_hot_reload() {
+    let dir = scratch("yuna_reload_untracked");
+    let script = dir.join("main.lua");
+    fs::write(&script,"x = 1").unwrap();
+
+    let mut state = yuna::State::new();
+    state.do_file(&script).unwrap();
+    let reload = yuna::HotReload::new(&state);
+    assert!(reload.files().is_empty());
+}
@@ -0,0 +1,9 @@
+local physics = {}
+
+physics.gravity = 9.81
+
+function physics.fail()
+  error("physics broke")
+end
+
+return physics
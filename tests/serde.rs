@@ -0,0 +1,168 @@
+#![cfg(feature = "serde")]
+
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate yuna;
+
+use std::collections::HashMap;
+
+use serde::{Serialize, Serializer};
+
+use yuna::{LuaContext, LuaIndex, LuaValue, Table};
+
+#[derive(Serialize,Deserialize,Debug,PartialEq)]
+enum Kind {
+    Melee,
+    Ranged(f32),
+    Boss { phase: u8 },
+}
+
+#[derive(Serialize,Deserialize,Debug,PartialEq)]
+struct Enemy {
+    name: String,
+    hp: i32,
+    kind: Kind,
+    loot: Option<String>,
+}
+
+#[derive(Serialize,Deserialize,Debug,PartialEq)]
+struct Level {
+    enemies: Vec<Enemy>,
+    spawn: (f64,f64),
+    flags: HashMap<String,bool>,
+}
+
+fn level() -> Level {
+    let mut flags = HashMap::new();
+    flags.insert(String::from("night"),true);
+    Level {
+        enemies: vec![
+            Enemy { name: String::from("rat"), hp: 3, kind: Kind::Melee, loot: None },
+            Enemy { name: String::from("archer"), hp: 10, kind: Kind::Ranged(12.5), loot: Some(String::from("bow")) },
+            Enemy { name: String::from("dragon"), hp: 500, kind: Kind::Boss { phase: 2 }, loot: None },
+        ],
+        spawn: (1.5,-2.0),
+        flags,
+    }
+}
+
+#[test]
+fn serialize_struct_to_table() {
+    let context = LuaContext::new();
+
+    let value = yuna::to_lua(&context,&level()).unwrap();
+    let table = match value {
+        LuaValue::LuaTable(t) => t,
+        other => panic!("expected table, got {:?}",other),
+    };
+
+    let enemies : Table = table.read("enemies").unwrap();
+    assert_eq!(enemies.len(),3);
+
+    let archer : Table = enemies.read(2).unwrap();
+    assert_eq!(archer.get("name"),LuaValue::from_string("archer"));
+    assert_eq!(archer.get("hp"),LuaValue::from_number(10));
+
+    let rat : Table = enemies.read(1).unwrap();
+    assert_eq!(rat.get("kind"),LuaValue::from_string("Melee"));
+    assert_eq!(rat.get("loot"),LuaValue::Nil);
+
+    let kind : Table = archer.read("kind").unwrap();
+    assert_eq!(kind.get("Ranged"),LuaValue::from_number(12.5));
+}
+
+#[test]
+fn roundtrip_struct() {
+    let context = LuaContext::new();
+
+    let value = yuna::to_lua(&context,&level()).unwrap();
+    let level_read : Level = yuna::from_lua(value).unwrap();
+
+    assert_eq!(level_read,level());
+}
+
+#[test]
+fn error_path() {
+    let context = LuaContext::new();
+
+    let value = yuna::to_lua(&context,&level()).unwrap();
+    if let LuaValue::LuaTable(ref t) = value {
+        let enemies : Table = t.read("enemies").unwrap();
+        let mut dragon : Table = enemies.read(3).unwrap();
+        dragon.set("hp","lots");
+    }
+
+    let err = yuna::from_lua::<Level>(value).unwrap_err();
+    assert_eq!(err.path(),"enemies[3].hp");
+    assert!(err.to_string().starts_with("enemies[3].hp: invalid type"));
+}
+
+#[test]
+fn serialize_large_integers() {
+    let context = LuaContext::new();
+
+    assert_eq!(yuna::to_lua(&context,&(1i64 << 53)),Ok(LuaValue::LuaNumber(9007199254740992.0)));
+    assert_eq!(yuna::to_lua(&context,&(1u64 << 60)),Ok(LuaValue::LuaNumber(1152921504606846976.0)));
+    assert_eq!(yuna::to_lua(&context,&i64::MIN),Ok(LuaValue::LuaNumber(-9223372036854775808.0)));
+
+    let err = yuna::to_lua(&context,&((1i64 << 53) + 1)).unwrap_err();
+    assert_eq!(err.message(),"integer 9007199254740993 can't be represented exactly as a lua number");
+    assert!(yuna::to_lua(&context,&i64::MAX).is_err());
+    assert!(yuna::to_lua(&context,&u64::MAX).is_err());
+    assert!(yuna::to_lua(&context,&vec![u64::MAX - 1]).unwrap_err().to_string().starts_with("[1]: integer"));
+}
+
+#[test]
+fn roundtrip_large_integers() {
+    let context = LuaContext::new();
+
+    for &n in &[1u64 << 53,1 << 60,1 << 63,u64::MAX - 2047] {
+        assert_eq!(yuna::from_lua::<u64>(yuna::to_lua(&context,&n).unwrap()),Ok(n));
+    }
+    for &n in &[-(1i64 << 60),i64::MIN] {
+        assert_eq!(yuna::from_lua::<i64>(yuna::to_lua(&context,&n).unwrap()),Ok(n));
+    }
+    assert!(yuna::from_lua::<u64>(LuaValue::LuaNumber(18446744073709551616.0)).is_err());
+    assert!(yuna::from_lua::<i64>(LuaValue::LuaNumber(0.5)).is_err());
+    assert_eq!(yuna::from_lua::<f64>(LuaValue::LuaNumber(1e300)),Ok(1e300));
+}
+
+#[derive(Deserialize,Debug,PartialEq)]
+struct Name {
+    name: String,
+}
+
+#[test]
+fn unknown_fields_are_ignored() {
+    let mut state = yuna::State::new();
+    state.openlibs();
+    state.do_string("value = {name = 'rat', callback = print, co = coroutine.create(function() end)}").unwrap();
+
+    let name : Name = yuna::from_lua(state.get("value")).unwrap();
+    assert_eq!(name,Name { name: String::from("rat") });
+}
+
+/// A map with a float key, which serde can't derive.
+struct FloatKeys(Vec<(f64,i32)>);
+
+impl Serialize for FloatKeys {
+    fn serialize<S: Serializer>(&self,serializer: S) -> Result<S::Ok,S::Error> {
+        serializer.collect_map(self.0.iter().cloned())
+    }
+}
+
+#[test]
+fn nan_keys_are_rejected() {
+    let context = LuaContext::new();
+
+    let value = yuna::to_lua(&context,&FloatKeys(vec![(0.5,1)])).unwrap();
+    let table = match value {
+        LuaValue::LuaTable(t) => t,
+        other => panic!("expected table, got {:?}",other),
+    };
+    assert_eq!(table.get(0.5),LuaValue::from_number(1));
+
+    let err = yuna::to_lua(&context,&FloatKeys(vec![(0.5,1),(f64::NAN,2)])).unwrap_err();
+    assert_eq!(err.message(),"table keys must not be NaN");
+}
@@ -0,0 +1,81 @@
+extern crate yuna;
+
+use std::fs;
+
+use yuna::{DirectorySource, LayeredSource, LuaIndex, MemorySource, ScriptSource};
+
+#[test]
+fn memory_source_require() {
+    let mut state = yuna::State::new();
+    state.openlibs();
+
+    let mut scripts = MemorySource::new();
+    scripts.insert("ourgame/physics.lua","return { gravity = 9.81 }");
+    scripts.insert("ourgame/init.lua","return { name = 'ourgame', path = ... }");
+    state.add_script_source(scripts);
+
+    state.do_string("gravity = require('ourgame.physics').gravity
+                     name = require('ourgame').name").unwrap();
+    assert_eq!(state.read::<_,f64>("gravity"),Ok(9.81));
+    assert_eq!(state.read::<_,String>("name"),Ok(String::from("ourgame")));
+
+    let err = state.do_string("require('missing')").unwrap_err();
+    assert!(err.message().contains("no module 'missing' in script source"));
+}
+
+#[test]
+fn script_source_chunk_names() {
+    let mut state = yuna::State::new();
+    state.openlibs();
+
+    let mut scripts = MemorySource::new();
+    scripts.insert("broken/runtime.lua","local x\n\nreturn x.y");
+    scripts.insert("broken/syntax.lua","return +");
+    state.add_script_source(scripts);
+
+    let err = state.do_string("require('broken.runtime')").unwrap_err();
+    assert_eq!(err.source(),Some("broken/runtime.lua"));
+    assert_eq!(err.line(),Some(3));
+
+    let err = state.do_string("require('broken.syntax')").unwrap_err();
+    assert!(err.message().contains("error loading module 'broken.syntax' from 'broken/syntax.lua'"));
+    assert!(err.message().contains("broken/syntax.lua:1:"));
+}
+
+#[test]
+fn layered_source_overrides() {
+    let mut base = MemorySource::new();
+    base.insert("weapons.lua","return { sword = 10 }");
+    base.insert("enemies.lua","return { slime = 1 }");
+    let mut modded = MemorySource::new();
+    modded.insert("weapons.lua","return { sword = 99 }");
+
+    let mut layers = LayeredSource::new();
+    layers.push(base);
+    layers.push(modded);
+
+    let mut state = yuna::State::new();
+    state.openlibs();
+    state.add_script_source(layers);
+
+    state.do_string("sword = require('weapons').sword slime = require('enemies').slime").unwrap();
+    assert_eq!(state.read::<_,i32>("sword"),Ok(99));
+    assert_eq!(state.read::<_,i32>("slime"),Ok(1));
+}
+
+#[test]
+fn directory_source() {
+    let root = std::env::temp_dir().join("yuna_directory_source");
+    fs::create_dir_all(root.join("ui")).unwrap();
+    fs::write(root.join("ui/menu.lua"),"return 'menu'").unwrap();
+    fs::write(root.join("secret.lua"),"return 'secret'").unwrap();
+
+    let source = DirectorySource::new(root.join("ui"));
+    assert_eq!(source.find("menu"),Some((b"return 'menu'".to_vec(),String::from("menu.lua"))));
+    assert_eq!(source.find("missing"),None);
+    // Module names can't escape the root
+    assert_eq!(source.find("../secret"),None);
+    assert_eq!(source.find("..secret"),None);
+
+    fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,98 @@
+extern crate yuna;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use yuna::{HookAction, HookMask, LuaIndex, LuaValue};
+
+#[test]
+fn stack_frames_in_callback() {
+    let mut state = yuna::State::new();
+    state.openlibs();
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let frames = seen.clone();
+    let inspect = state.create_function(move |state,_| {
+        frames.borrow_mut().extend(state.stack_frames().into_iter().map(|frame| {
+            (frame.what.clone(),frame.name.clone(),frame.line,frame.locals())
+        }));
+        Ok(vec![])
+    });
+    state.set("inspect",&inspect);
+
+    state.do_string("local function attack(target, damage)\n  local hp = target.hp - damage\n  for i = 1, 1 do inspect() end\nend\nattack({hp = 30}, 12)").unwrap();
+
+    let frames = seen.borrow();
+    assert_eq!(frames.len(),3);
+    assert_eq!(frames[0].0,"C");
+    assert_eq!(frames[1].1,Some(String::from("attack")));
+    assert_eq!(frames[1].2,Some(3));
+    let names: Vec<&str> = frames[1].3.iter().map(|(name,_)| name.as_str()).collect();
+    assert_eq!(names,vec!["target","damage","hp","i"]);
+    assert_eq!(frames[1].3[2].1,LuaValue::from_number(18));
+    assert_eq!(frames[2].0,"main");
+    assert_eq!(frames[2].3[0].0,"attack");
+
+    assert!(state.stack_frames().is_empty());
+}
+
+#[test]
+fn stack_frames_in_hook() {
+    let mut state = yuna::State::new();
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let locals = seen.clone();
+    state.set_hook(HookMask { line: true, ..HookMask::default() },move |state,info| {
+        if info.line() == Some(3) {
+            *locals.borrow_mut() = state.stack_frames()[0].locals();
+        }
+        HookAction::Continue
+    });
+
+    state.do_string("local a = 'x'\nlocal b = a .. 'y'\nlocal c = nil").unwrap();
+    assert_eq!(*seen.borrow(),vec![(String::from("a"),LuaValue::from_string("x")),
+                                   (String::from("b"),LuaValue::from_string("xy"))]);
+}
+
+#[test]
+fn function_upvalues() {
+    let mut state = yuna::State::new();
+    state.do_string("local count, step = 0, 2\nfunction counter() count = count + step return count end").unwrap();
+
+    let counter : yuna::Function = state.read("counter").unwrap();
+    counter.call(vec![]).unwrap();
+    let upvalues = counter.upvalues();
+    assert_eq!(upvalues[0],(String::from("count"),LuaValue::from_number(2)));
+    assert_eq!(upvalues[1],(String::from("step"),LuaValue::from_number(2)));
+
+    let callback = state.create_function(|_,_| Ok(vec![]));
+    let upvalues = callback.upvalues();
+    assert_eq!(upvalues.len(),1);
+    assert_eq!(upvalues[0].0,"");
+}
+
+#[test]
+fn stack_frames_keep_coroutine_alive() {
+    let mut state = yuna::State::new();
+    state.openlibs();
+    let kept = Rc::new(RefCell::new(Vec::new()));
+    let frames = kept.clone();
+    let inspect = state.create_function(move |state,_| {
+        *frames.borrow_mut() = state.stack_frames();
+        Ok(vec![])
+    });
+    state.set("inspect",&inspect);
+
+    state.do_string("weak = setmetatable({}, {__mode = 'k'})
+                     local co = coroutine.create(function(x) local y = x * 2 inspect() end)
+                     weak[co] = true
+                     coroutine.resume(co, 21)
+                     co = nil
+                     collectgarbage() collectgarbage()").unwrap();
+
+    state.do_string("assert(next(weak) ~= nil)").unwrap();
+    // The coroutine finished, its frames are gone
+    assert!(kept.borrow()[1].locals().is_empty());
+    assert_eq!(kept.borrow()[1].chunk_name(),"");
+
+    kept.borrow_mut().clear();
+    state.do_string("collectgarbage() collectgarbage() assert(next(weak) == nil)").unwrap();
+}
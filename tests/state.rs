@@ -44,9 +44,9 @@ fn state_do_string() {
     let num : i32 = 86;
     let mut state = yuna::State::new();
 
-    state.do_string(format!("num = {}",num));
+    state.do_string(format!("num = {}",num)).unwrap();
 
-    let r = state.read("num").unwrap();
+    let r : i32 = state.read("num").unwrap();
 
     assert_eq!(num,r);
 }
@@ -57,7 +57,183 @@ fn state_global() {
 
 
     let global : yuna::Table = state.global();
-    state.set("answer",42 as i32);
+    state.set("answer",42i32);
 
     assert_eq!(global.get("answer"),state.get("answer"));
 }
+
+#[test]
+fn state_create_table() {
+    let state = yuna::State::new();
+
+    let mut table = state.create_table();
+    table.set("answer",42);
+
+    assert_eq!(table.get("answer"),yuna::LuaValue::LuaNumber(42.0));
+}
+
+#[test]
+fn state_create_string() {
+    let state = yuna::State::new();
+
+    let s = state.create_string("LuaRocks");
+
+    assert_eq!(s,yuna::LuaValue::from_string("LuaRocks"));
+}
+
+#[test]
+fn state_pack_and_unpack() {
+    let state = yuna::State::new();
+
+    assert_eq!(state.pack(true),yuna::LuaValue::LuaBoolean(true));
+    assert_eq!(state.pack(-3i8),yuna::LuaValue::LuaNumber(-3.0));
+    assert_eq!(state.pack("LuaRocks"),yuna::LuaValue::from_string("LuaRocks"));
+
+    let n : u16 = state.unpack(yuna::LuaValue::LuaNumber(731.0)).unwrap();
+    assert_eq!(n,731);
+
+    let s : String = state.unpack(yuna::LuaValue::from_string("LuaRocks")).unwrap();
+    assert_eq!(s,"LuaRocks");
+
+    let table = state.create_table();
+    let packed = state.pack(&table);
+    let unpacked : yuna::Table = state.unpack(packed).unwrap();
+    assert_eq!(unpacked,table);
+
+    let invalid : Result<i32,()> = state.unpack(yuna::LuaValue::from_string("abc"));
+    assert_eq!(invalid,Err(()));
+}
+
+#[test]
+fn state_do_string_syntax_error() {
+    let mut state = yuna::State::new();
+
+    match state.do_string("x = = 1") {
+        Err(yuna::LuaError::Syntax(message)) => assert!(message.contains("unexpected symbol")),
+        other => panic!("expected syntax error, got {:?}",other),
+    }
+}
+
+#[test]
+fn state_do_string_traceback() {
+    let mut state = yuna::State::new();
+    state.openlibs();
+
+    let err = state.do_string("local function inner()\n  error('deep')\nend\nfunction outer()\n  inner()\nend\nouter()").unwrap_err();
+
+    assert!(err.message().ends_with("deep"));
+    let traceback = err.traceback().unwrap();
+    // Since lua 5.3 the kind of variable holding the function is named
+    let inner = if cfg!(any(feature = "lua53", feature = "lua54")) { "in upvalue 'inner'" } else { "in function 'inner'" };
+    assert!(traceback.contains(inner));
+    assert!(traceback.contains("in function 'outer'"));
+    assert!(traceback.starts_with("stack traceback:\n\t[C]: in function 'error'\n\t[string \"local function inner()...\"]:2: in "));
+
+    let frames = err.frames();
+    assert_eq!(frames[0].what,"C");
+    assert_eq!(frames[0].name,Some(String::from("error")));
+    assert_eq!(frames[1].name,Some(String::from("inner")));
+    assert_eq!(frames[1].line,Some(2));
+    assert_eq!(frames[2].name,Some(String::from("outer")));
+    assert_eq!(frames[2].line,Some(5));
+    assert_eq!(frames[3].what,"main");
+
+    assert!(format!("{:#}",err).contains("stack traceback:"));
+    assert!(!format!("{}",err).contains("stack traceback:"));
+}
+
+#[test]
+fn state_error_location() {
+    let mut state = yuna::State::new();
+    state.openlibs();
+
+    let err = state.do_string("local x = 1\nerror('broken')").unwrap_err();
+    assert_eq!(err.source(),Some("[string \"local x = 1...\"]"));
+    assert_eq!(err.line(),Some(2));
+    assert_eq!(err.message_without_location(),"broken");
+
+    // The location comes from the traceback even if the message has none
+    let err = state.do_string("\n\nerror({})").unwrap_err();
+    assert_eq!(err.line(),Some(3));
+    assert_eq!(err.message_without_location(),"(error object is a table value)");
+
+    let err = state.do_string("error('plain',0)").unwrap_err();
+    assert_eq!(err.line(),Some(1));
+    assert_eq!(err.message_without_location(),"plain");
+
+    // The location of the message wins over the frame raising the error
+    let err = state.do_string("local function check(x)\n  error('bad x', 2)\nend\n\ncheck(1)").unwrap_err();
+    assert_eq!(err.source(),Some("[string \"local function check(x)...\"]"));
+    assert_eq!(err.line(),Some(5));
+    assert_eq!(err.frames()[1].line,Some(2));
+
+    let path = std::env::temp_dir().join("yuna_state_error_location.lua");
+    std::fs::write(&path,"\nlocal t = nil\nreturn t.x").unwrap();
+    let err = state.do_file(&path).unwrap_err();
+    std::fs::remove_file(&path).unwrap();
+    assert!(err.source().unwrap().ends_with("yuna_state_error_location.lua"));
+    assert_eq!(err.line(),Some(3));
+    let message = if cfg!(any(feature = "lua53", feature = "lua54")) {
+        "attempt to index a nil value (local 't')"
+    } else {
+        "attempt to index local 't' (a nil value)"
+    };
+    assert_eq!(err.message_without_location(),message);
+
+    std::fs::write(&path,"return +").unwrap();
+    let err = state.do_file(&path).unwrap_err();
+    std::fs::remove_file(&path).unwrap();
+    assert!(err.source().unwrap().ends_with("yuna_state_error_location.lua"));
+    assert_eq!(err.line(),Some(1));
+}
+
+#[test]
+fn state_check_syntax() {
+    let state = yuna::State::new();
+    let top = unsafe { yuna::raw::ffi::lua_gettop(state.context().as_ptr()) };
+
+    assert_eq!(state.check_syntax("answer = 42","answer.lua"),Ok(()));
+    assert_eq!(state.get("answer"),yuna::LuaValue::Nil);
+
+    let diagnostics = state.check_syntax("local t = {\n1,\n2\n","config.lua").unwrap_err();
+    let eof = if cfg!(any(feature = "lua51", feature = "luajit")) { "'<eof>'" } else { "<eof>" };
+    let message = format!("'}}' expected (to close '{{' at line 1) near {}",eof);
+    assert_eq!(diagnostics,vec![yuna::Diagnostic {
+        source: String::from("config.lua"),
+        line: Some(4),
+        message: message.clone(),
+    }]);
+    assert_eq!(diagnostics[0].to_string(),format!("config.lua:4: {}",message));
+
+    assert_eq!(unsafe { yuna::raw::ffi::lua_gettop(state.context().as_ptr()) },top);
+}
+
+#[test]
+fn state_check_syntax_rejects_bytecode() {
+    let state = yuna::State::new();
+    let chunk = state.load(b"answer = 42","answer.lua").unwrap().dump(false).unwrap();
+
+    let diagnostics = state.check_syntax(&chunk,"answer.lua").unwrap_err();
+    let message = if cfg!(feature = "luajit") {
+        "attempt to load chunk with wrong mode"
+    } else {
+        "attempt to load a binary chunk (mode is 't')"
+    };
+    assert_eq!(diagnostics[0].message,message);
+}
+
+#[test]
+fn state_do_file() {
+    let mut state = yuna::State::new();
+    let path = std::env::temp_dir().join("yuna_state_do_file.lua");
+    std::fs::write(&path,"answer = 42").unwrap();
+
+    state.do_file(&path).unwrap();
+    let answer : i32 = state.read("answer").unwrap();
+    assert_eq!(answer,42);
+
+    match state.do_file("does/not/exist.lua") {
+        Err(yuna::LuaError::File(message)) => assert!(message.contains("does/not/exist.lua")),
+        other => panic!("expected file error, got {:?}",other),
+    }
+}
@@ -1,6 +1,6 @@
 extern crate yuna;
-extern crate lua52_sys as ffi;
 
+use yuna::raw::ffi;
 use yuna::{Table,LuaContext,LuaRead,LuaWrite, LuaIndex};
 
 
@@ -9,15 +9,15 @@ fn read_and_write_table() {
     let context = LuaContext::new();
 
     // Create empty table
-    unsafe { ffi::lua_newtable(context.l) };
+    unsafe { ffi::lua_newtable(context.as_ptr()) };
     // Read Table
-    let t : Table = LuaRead::lua_read_index(&context,-1).unwrap();
+    let t : Table = unsafe { LuaRead::lua_read_index(&context,-1) }.unwrap();
 
     // Push Table via LuaWrite
     unsafe { LuaWrite::lua_write(&context,&t) };
 
     // Compare created table with pushed table
-    let comp = unsafe { ffi::lua_compare(context.l,-2,-1,ffi::LUA_OPEQ) };
+    let comp = unsafe { ffi::lua_compare(context.as_ptr(),-2,-1,ffi::LUA_OPEQ) };
     assert!( comp == 1 );
 
 }
@@ -33,7 +33,7 @@ fn new_table() {
     unsafe { LuaWrite::lua_write(&context,&t) };
 
     // Check if pushed table is really a table
-    let is_table = unsafe { ffi::lua_istable(context.l,-1 ) };
+    let is_table = unsafe { ffi::lua_istable(context.as_ptr(),-1 ) };
     assert!( is_table );
 
 }
@@ -51,7 +51,7 @@ fn clone_table() {
     unsafe { LuaWrite::lua_write(&context,&clone) };
 
     // Compare created table with cloned table
-    let comp = unsafe { ffi::lua_compare(context.l,-2,-1,ffi::LUA_OPEQ) };
+    let comp = unsafe { ffi::lua_compare(context.as_ptr(),-2,-1,ffi::LUA_OPEQ) };
     assert!( comp == 1 );
 
 }
@@ -81,3 +81,68 @@ fn lua_index_table() {
     let getanswer = table.get("answer");
     assert_eq!(getanswer,yuna::LuaValue::LuaNumber(42.0));
 }
+
+#[test]
+fn debug_table() {
+    let context = LuaContext::new();
+    let mut table = Table::new(&context);
+
+    table.set(1,"first");
+    table.set(2,"second");
+    table.set("answer",42);
+
+    assert_eq!(format!("{:?}",table),r#"{1: "first", 2: "second", "answer": 42}"#);
+    assert_eq!(format!("{:?}",yuna::LuaValue::LuaTable(table)),r#"LuaTable({1: "first", 2: "second", "answer": 42})"#);
+}
+
+#[test]
+fn debug_nested_table() {
+    let context = LuaContext::new();
+    let mut table = Table::new(&context);
+    let mut inner = Table::new(&context);
+
+    inner.set("pi",3.5);
+    table.set("inner",&inner);
+
+    assert_eq!(format!("{:?}",table),r#"{"inner": {"pi": 3.5}}"#);
+    assert_eq!(format!("{:#?}",table),"{\n    \"inner\": {\n        \"pi\": 3.5,\n    },\n}");
+}
+
+#[test]
+fn debug_table_cycle_and_depth() {
+    let context = LuaContext::new();
+    let mut table = Table::new(&context);
+    let same = table.clone();
+
+    table.set("self",&same);
+    assert_eq!(format!("{:?}",table),r#"{"self": <cycle>}"#);
+
+    let mut deep = Table::new(&context);
+    for _ in 0..10 {
+        let mut outer = Table::new(&context);
+        outer.set(1,&deep);
+        deep = outer;
+    }
+    assert!(format!("{:?}",deep).contains("{...}"));
+}
+
+#[test]
+fn table_pairs() {
+    let mut state = yuna::State::new();
+    state.do_string("t = {10, x = true} tables = {} for i = 1, 1000 do tables[i] = {} end").unwrap();
+
+    let t : Table = state.read("t").unwrap();
+    let mut pairs = t.pairs().unwrap();
+    pairs.sort_by_key(|pair| format!("{:?}",pair.0));
+    assert_eq!(pairs,vec![(yuna::LuaValue::LuaNumber(1.0),yuna::LuaValue::LuaNumber(10.0)),
+                          (yuna::LuaValue::from_string("x"),yuna::LuaValue::LuaBoolean(true))]);
+
+    // Each table read needs a reference, which fails without memory
+    let tables : Table = state.read("tables").unwrap();
+    let top = unsafe { ffi::lua_gettop(state.context().as_ptr()) };
+    state.set_memory_limit(Some(state.memory_used()));
+    assert_eq!(tables.pairs(),Err(()));
+    state.set_memory_limit(None);
+    assert_eq!(unsafe { ffi::lua_gettop(state.context().as_ptr()) },top);
+    assert_eq!(tables.pairs().unwrap().len(),1000);
+}
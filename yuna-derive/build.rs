@@ -0,0 +1,13 @@
+// Field names and lifetimes are spelled out throughout the crate
+#![allow(clippy::redundant_field_names, clippy::redundant_static_lifetimes)]
+
+// The same linking as yuna, the sources are bundled in its directory
+include!("../lua/link.rs");
+
+fn main() {
+    // Only precompiling runs lua
+    if env::var_os("CARGO_FEATURE_PRECOMPILE").is_none() {
+        return;
+    }
+    link("../lua");
+}
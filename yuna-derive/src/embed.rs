@@ -0,0 +1,96 @@
+//! `embed_scripts!`, which includes a directory of lua files into the binary.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use proc_macro2::{Span, TokenStream};
+use syn::parse::{Parse, ParseStream};
+use syn::{Ident, LitStr, Token};
+
+/// The arguments `"dir"` or `"dir", precompile`.
+struct Args {
+    dir: LitStr,
+    precompile: bool,
+}
+
+impl Parse for Args {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let dir = input.parse()?;
+        let mut precompile = false;
+        if input.parse::<Option<Token![,]>>()?.is_some() && !input.is_empty() {
+            let option: Ident = input.parse()?;
+            if option != "precompile" {
+                return Err(syn::Error::new(option.span(),"expected `precompile`"));
+            }
+            precompile = true;
+            input.parse::<Option<Token![,]>>()?;
+        }
+        Ok(Args { dir, precompile })
+    }
+}
+
+/// Collects all lua files below `dir` as pairs of relative path and absolute path, sorted by path.
+fn collect(root: &Path,dir: &Path,files: &mut Vec<(String,PathBuf)>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect(root,&path,files)?;
+        } else if path.extension().is_some_and(|ext| ext == "lua") {
+            let relative = path.strip_prefix(root).unwrap()
+                               .components()
+                               .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                               .collect::<Vec<_>>()
+                               .join("/");
+            files.push((relative,path));
+        }
+    }
+    files.sort();
+    Ok(())
+}
+
+pub fn expand(input: TokenStream) -> syn::Result<TokenStream> {
+    let args: Args = syn::parse2(input)?;
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| String::from("."));
+    let root = Path::new(&manifest_dir).join(args.dir.value());
+
+    let mut files = Vec::new();
+    collect(&root,&root,&mut files).map_err(|e| {
+        syn::Error::new(args.dir.span(),format!("can't read {}: {}",root.display(),e))
+    })?;
+
+    let mut tracked = Vec::new();
+    let mut entries = Vec::new();
+    for (relative,path) in files {
+        let absolute = LitStr::new(&path.to_string_lossy(),Span::call_site());
+        let code = if args.precompile {
+            // Include the source anyway, so changes trigger a rebuild
+            tracked.push(quote!(const _: &[u8] = include_bytes!(#absolute);));
+            let bytecode = compile(&args.dir,&path,&relative)?;
+            let bytecode = syn::LitByteStr::new(&bytecode,Span::call_site());
+            quote!(#bytecode)
+        } else {
+            quote!(include_bytes!(#absolute))
+        };
+        entries.push(quote!((#relative,#code)));
+    }
+
+    Ok(quote! {
+        {
+            #(#tracked)*
+            static FILES: &[(&str,&[u8])] = &[#(#entries),*];
+            ::yuna::EmbeddedSource::new(FILES)
+        }
+    })
+}
+
+#[cfg(feature = "precompile")]
+fn compile(dir: &LitStr,path: &Path,relative: &str) -> syn::Result<Vec<u8>> {
+    let code = fs::read(path).map_err(|e| syn::Error::new(dir.span(),format!("can't read {}: {}",path.display(),e)))?;
+    ::precompile::compile(&code,&format!("@{}",relative)).map_err(|e| syn::Error::new(dir.span(),e))
+}
+
+#[cfg(not(feature = "precompile"))]
+fn compile(dir: &LitStr,_: &Path,_: &str) -> syn::Result<Vec<u8>> {
+    Err(syn::Error::new(dir.span(),"precompiling scripts requires the `precompile` feature of yuna"))
+}
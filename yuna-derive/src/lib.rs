@@ -0,0 +1,263 @@
+//! Derive macros for yuna's `LuaRead` and `LuaWrite` traits and the `embed_scripts!` macro.
+//!
+//! * Structs with named fields are converted to and from tables keyed by field name.
+//! * Tuple structs are converted to and from arrays.
+//! * Enums without fields are converted to and from strings holding the variant name.
+//!
+//! Fields and variants accept `#[lua(rename = "name")]`, fields additionally
+//! `#[lua(default)]` to use `Default::default()` when the key is nil and `#[lua(skip)]`
+//! to never write the field and always read it as `Default::default()`.
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+#[cfg(feature = "precompile")]
+extern crate libc;
+
+#[cfg(all(feature = "precompile", not(any(feature = "lua51", feature = "lua52", feature = "lua53", feature = "lua54", feature = "luajit"))))]
+compile_error!("precompile needs the lua version, select it with one of the features lua51, lua52, lua53, lua54 or luajit");
+
+mod embed;
+#[cfg(feature = "precompile")]
+mod precompile;
+
+use proc_macro2::{Span, TokenStream};
+use syn::{Data, DeriveInput, Fields, Ident, LitStr};
+
+#[proc_macro_derive(LuaRead, attributes(lua))]
+pub fn derive_lua_read(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    expand(&input,expand_read).into()
+}
+
+#[proc_macro_derive(LuaWrite, attributes(lua))]
+pub fn derive_lua_write(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    expand(&input,expand_write).into()
+}
+
+/// Embeds all `.lua` files below a directory, relative to the crate root.
+///
+/// Expands to a `yuna::EmbeddedSource` serving the files as modules by their relative path.
+/// `embed_scripts!("scripts", precompile)` stores bytecode instead of source and requires
+/// the `precompile` feature.
+#[proc_macro]
+pub fn embed_scripts(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    embed::expand(input.into()).unwrap_or_else(|e| e.to_compile_error()).into()
+}
+
+fn expand(input: &DeriveInput,f: fn(&DeriveInput) -> syn::Result<TokenStream>) -> TokenStream {
+    f(input).unwrap_or_else(|e| e.to_compile_error())
+}
+
+/// Options set with `#[lua(...)]`.
+#[derive(Default)]
+struct Attrs {
+    rename: Option<String>,
+    default: bool,
+    skip: bool,
+}
+
+impl Attrs {
+    fn parse(attrs: &[syn::Attribute]) -> syn::Result<Self> {
+        let mut result = Attrs::default();
+        for attr in attrs {
+            if !attr.path().is_ident("lua") {
+                continue;
+            }
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    let name: LitStr = meta.value()?.parse()?;
+                    result.rename = Some(name.value());
+                } else if meta.path.is_ident("default") {
+                    result.default = true;
+                } else if meta.path.is_ident("skip") {
+                    result.skip = true;
+                } else {
+                    return Err(meta.error("expected `rename`, `default` or `skip`"));
+                }
+                Ok(())
+            })?;
+        }
+        Ok(result)
+    }
+
+    /// The lua-side name of a field or variant.
+    fn name(&self,ident: &Ident) -> LitStr {
+        let name = match self.rename {
+            Some(ref name) => name.clone(),
+            None => ident.to_string(),
+        };
+        LitStr::new(&name,ident.span())
+    }
+}
+
+/// Adds the given bound to all type parameters.
+fn add_bounds(input: &DeriveInput,bound: TokenStream) -> syn::Generics {
+    let mut generics = input.generics.clone();
+    for param in generics.type_params_mut() {
+        param.bounds.push(syn::parse2(bound.clone()).unwrap());
+    }
+    generics
+}
+
+fn expand_read(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let generics = add_bounds(input,quote!(::yuna::LuaRead));
+    let (impl_generics,ty_generics,where_clause) = generics.split_for_impl();
+
+    let body = match input.data {
+        Data::Struct(ref data) => {
+            let table = quote! {
+                let table = match <::yuna::LuaValue as ::yuna::LuaRead>::lua_read_index(context,index)? {
+                    ::yuna::LuaValue::LuaTable(table) => table,
+                    _ => return Err(()),
+                };
+            };
+            match data.fields {
+                Fields::Named(ref fields) => {
+                    let mut inits = Vec::new();
+                    for field in &fields.named {
+                        let ident = field.ident.as_ref().unwrap();
+                        let attrs = Attrs::parse(&field.attrs)?;
+                        let value = read_field(&attrs,attrs.name(ident));
+                        inits.push(quote!(#ident: #value));
+                    }
+                    quote! {
+                        #table
+                        Ok(#name { #(#inits),* })
+                    }
+                },
+                Fields::Unnamed(ref fields) => {
+                    let mut inits = Vec::new();
+                    for (i,field) in fields.unnamed.iter().enumerate() {
+                        let attrs = Attrs::parse(&field.attrs)?;
+                        if attrs.rename.is_some() {
+                            return Err(syn::Error::new_spanned(field,"tuple struct fields can't be renamed"));
+                        }
+                        inits.push(read_field(&attrs,(i + 1) as i32));
+                    }
+                    quote! {
+                        #table
+                        Ok(#name(#(#inits),*))
+                    }
+                },
+                Fields::Unit => quote!(Ok(#name)),
+            }
+        },
+        Data::Enum(ref data) => {
+            let mut arms = Vec::new();
+            for variant in &data.variants {
+                if !matches!(variant.fields,Fields::Unit) {
+                    return Err(syn::Error::new_spanned(variant,"only enums without fields can be derived"));
+                }
+                let ident = &variant.ident;
+                let lua_name = Attrs::parse(&variant.attrs)?.name(ident);
+                arms.push(quote!(#lua_name => Ok(#name::#ident)));
+            }
+            quote! {
+                match <::yuna::LuaValue as ::yuna::LuaRead>::lua_read_index(context,index)? {
+                    ::yuna::LuaValue::LuaString(s) => match s.as_str() {
+                        #(#arms,)*
+                        _ => Err(()),
+                    },
+                    _ => Err(()),
+                }
+            }
+        },
+        Data::Union(_) => return Err(syn::Error::new(Span::call_site(),"unions can't be derived")),
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::yuna::LuaRead for #name #ty_generics #where_clause {
+            unsafe fn lua_read_index(context: &::yuna::LuaContext,index: i32) -> Result<Self,()> {
+                #body
+            }
+        }
+    })
+}
+
+/// Reads a single field from `table`.
+fn read_field<K: quote::ToTokens>(attrs: &Attrs,key: K) -> TokenStream {
+    if attrs.skip {
+        quote!(::std::default::Default::default())
+    } else if attrs.default {
+        quote! {
+            match ::yuna::LuaIndex::get(&table,#key) {
+                ::yuna::LuaValue::Nil => ::std::default::Default::default(),
+                _ => ::yuna::LuaIndex::read(&table,#key)?,
+            }
+        }
+    } else {
+        quote!(::yuna::LuaIndex::read(&table,#key)?)
+    }
+}
+
+fn expand_write(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let generics = add_bounds(input,quote!(::yuna::LuaWrite));
+    let (impl_generics,ty_generics,where_clause) = generics.split_for_impl();
+
+    let body = match input.data {
+        Data::Struct(ref data) => {
+            let mut sets = Vec::new();
+            match data.fields {
+                Fields::Named(ref fields) => {
+                    for field in &fields.named {
+                        let ident = field.ident.as_ref().unwrap();
+                        let attrs = Attrs::parse(&field.attrs)?;
+                        if !attrs.skip {
+                            let key = attrs.name(ident);
+                            sets.push(quote!(::yuna::LuaIndex::set(&mut table,#key,value.#ident);));
+                        }
+                    }
+                },
+                Fields::Unnamed(ref fields) => {
+                    for (i,field) in fields.unnamed.iter().enumerate() {
+                        let attrs = Attrs::parse(&field.attrs)?;
+                        if !attrs.skip {
+                            let member = syn::Index::from(i);
+                            let key = (i + 1) as i32;
+                            sets.push(quote!(::yuna::LuaIndex::set(&mut table,#key,value.#member);));
+                        }
+                    }
+                },
+                Fields::Unit => {},
+            }
+            quote! {
+                #[allow(unused_mut)]
+                let mut table = ::yuna::Table::new(context);
+                #(#sets)*
+                ::yuna::LuaWrite::lua_write(context,&table);
+            }
+        },
+        Data::Enum(ref data) => {
+            let mut arms = Vec::new();
+            for variant in &data.variants {
+                if !matches!(variant.fields,Fields::Unit) {
+                    return Err(syn::Error::new_spanned(variant,"only enums without fields can be derived"));
+                }
+                let ident = &variant.ident;
+                let lua_name = Attrs::parse(&variant.attrs)?.name(ident);
+                arms.push(quote!(#name::#ident => #lua_name));
+            }
+            quote! {
+                let name = match value {
+                    #(#arms,)*
+                };
+                ::yuna::LuaWrite::lua_write(context,name);
+            }
+        },
+        Data::Union(_) => return Err(syn::Error::new(Span::call_site(),"unions can't be derived")),
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::yuna::LuaWrite for #name #ty_generics #where_clause {
+            unsafe fn lua_write(context: &::yuna::LuaContext,value: Self) {
+                #body
+            }
+        }
+    })
+}
@@ -0,0 +1,79 @@
+//! Compiles lua code to bytecode with the lua library the macros are linked against.
+//!
+//! The bytecode is only valid for lua builds with the same number sizes and byte order as
+//! the one the build runs on.
+
+use std::ffi::CString;
+use std::slice;
+
+use libc::{c_char, c_int, c_void, size_t};
+
+/// The few functions of the lua C API needed, with the signatures of the selected version.
+#[allow(non_camel_case_types, non_snake_case)]
+mod ffi {
+    use libc::{c_char, c_int, c_void, size_t};
+
+    pub enum lua_State {}
+    pub type lua_Writer = extern "C" fn(L: *mut lua_State, p: *const c_void, sz: size_t, ud: *mut c_void) -> c_int;
+
+    pub const LUA_OK: c_int = 0;
+
+    extern "C" {
+        pub fn luaL_newstate() -> *mut lua_State;
+        pub fn lua_close(L: *mut lua_State);
+        pub fn lua_tolstring(L: *mut lua_State, idx: c_int, len: *mut size_t) -> *const c_char;
+        #[cfg(not(feature = "lua51"))]
+        pub fn luaL_loadbufferx(L: *mut lua_State, buff: *const c_char, sz: size_t,
+                                name: *const c_char, mode: *const c_char) -> c_int;
+        #[cfg(feature = "lua51")]
+        pub fn luaL_loadbuffer(L: *mut lua_State, buff: *const c_char, sz: size_t, name: *const c_char) -> c_int;
+        #[cfg(not(any(feature = "lua53", feature = "lua54")))]
+        pub fn lua_dump(L: *mut lua_State, writer: lua_Writer, data: *mut c_void) -> c_int;
+        #[cfg(any(feature = "lua53", feature = "lua54"))]
+        #[link_name = "lua_dump"]
+        fn lua_dump_strip(L: *mut lua_State, writer: lua_Writer, data: *mut c_void, strip: c_int) -> c_int;
+    }
+
+    #[cfg(any(feature = "lua53", feature = "lua54"))]
+    pub unsafe fn lua_dump(L: *mut lua_State, writer: lua_Writer, data: *mut c_void) -> c_int {
+        lua_dump_strip(L, writer, data, 0)
+    }
+
+    /// Lua 5.1 has no modes, the code is text as the macro reads it from `.lua` files.
+    #[cfg(feature = "lua51")]
+    pub unsafe fn luaL_loadbufferx(L: *mut lua_State, buff: *const c_char, sz: size_t,
+                                   name: *const c_char, _mode: *const c_char) -> c_int {
+        luaL_loadbuffer(L, buff, sz, name)
+    }
+}
+
+extern "C" fn writer(_: *mut ffi::lua_State,p: *const c_void,sz: size_t,ud: *mut c_void) -> c_int {
+    unsafe {
+        let out = &mut *(ud as *mut Vec<u8>);
+        out.extend_from_slice(slice::from_raw_parts(p as *const u8,sz));
+    }
+    0
+}
+
+/// Compiles the code and returns the dumped function, or the syntax error message.
+pub fn compile(code: &[u8],chunk_name: &str) -> Result<Vec<u8>,String> {
+    let name = CString::new(chunk_name).map_err(|e| e.to_string())?;
+    unsafe {
+        let l = ffi::luaL_newstate();
+        if l.is_null() {
+            return Err(String::from("not enough memory"));
+        }
+        let status = ffi::luaL_loadbufferx(l,code.as_ptr() as *const c_char,code.len() as size_t,
+                                           name.as_ptr(),b"t\0".as_ptr() as *const c_char);
+        let result = if status == ffi::LUA_OK {
+            let mut out = Vec::new();
+            ffi::lua_dump(l,writer,&mut out as *mut Vec<u8> as *mut c_void);
+            Ok(out)
+        } else {
+            let message = ffi::lua_tolstring(l,-1,::std::ptr::null_mut());
+            Err(::std::ffi::CStr::from_ptr(message).to_string_lossy().into_owned())
+        };
+        ffi::lua_close(l);
+        result
+    }
+}